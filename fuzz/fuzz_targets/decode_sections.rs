@@ -0,0 +1,11 @@
+#![no_main]
+
+use cosmwasm_vm_wasmi::{decode_sections, encode_sections};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(sections) = decode_sections(data) {
+        let sections = sections.into_iter().map(<[u8]>::to_vec).collect::<Vec<_>>();
+        assert_eq!(encode_sections(&sections).as_deref(), Some(data));
+    }
+});
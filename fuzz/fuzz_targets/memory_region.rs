@@ -0,0 +1,90 @@
+#![no_main]
+
+use core::cell::RefCell;
+use cosmwasm_vm::memory::{
+    FromRegion, LimitedRead, LimitedTypedRead, MemoryReadError, MemoryWriteError, Pointable,
+    RawFromRegion, RawIntoRegion, ReadWriteMemory, ReadableMemory, WritableMemory, Write,
+};
+use libfuzzer_sys::fuzz_target;
+
+/// Upper bound on the number of bytes a region read may return.
+const READ_LIMIT: u32 = 64 * 1024;
+
+#[derive(Debug)]
+enum Error {
+    Read(MemoryReadError),
+    Write(MemoryWriteError),
+    OutOfBounds,
+}
+
+impl From<MemoryReadError> for Error {
+    fn from(e: MemoryReadError) -> Self {
+        Error::Read(e)
+    }
+}
+
+impl From<MemoryWriteError> for Error {
+    fn from(e: MemoryWriteError) -> Self {
+        Error::Write(e)
+    }
+}
+
+/// Linear memory initialized with the fuzzer input, mimicking a contract memory.
+struct Memory(RefCell<Vec<u8>>);
+
+impl Memory {
+    fn range(&self, offset: u32, len: usize) -> Result<core::ops::Range<usize>, Error> {
+        let start = usize::try_from(offset).map_err(|_| Error::OutOfBounds)?;
+        let end = start.checked_add(len).ok_or(Error::OutOfBounds)?;
+        if end > self.0.borrow().len() {
+            Err(Error::OutOfBounds)
+        } else {
+            Ok(start..end)
+        }
+    }
+}
+
+impl Pointable for Memory {
+    type Pointer = u32;
+}
+
+impl ReadableMemory for Memory {
+    type Error = Error;
+    fn read(&self, offset: Self::Pointer, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        let range = self.range(offset, buffer.len())?;
+        buffer.copy_from_slice(&self.0.borrow()[range]);
+        Ok(())
+    }
+    fn size(&self) -> usize {
+        self.0.borrow().len()
+    }
+}
+
+impl WritableMemory for Memory {
+    type Error = Error;
+    fn write(&self, offset: Self::Pointer, buffer: &[u8]) -> Result<(), Self::Error> {
+        let range = self.range(offset, buffer.len())?;
+        self.0.borrow_mut()[range].copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
+impl ReadWriteMemory for Memory {}
+
+fuzz_target!(|data: &[u8]| {
+    // The first 4 bytes select the region pointer, the rest is the memory content.
+    if data.len() < 4 {
+        return;
+    }
+    let (pointer, memory) = data.split_at(4);
+    let pointer = u32::from_le_bytes([pointer[0], pointer[1], pointer[2], pointer[3]]);
+    let memory = Memory(RefCell::new(memory.to_vec()));
+
+    if let Ok(RawFromRegion(buffer)) =
+        RawFromRegion::try_from(LimitedRead(&memory, pointer, READ_LIMIT))
+    {
+        assert!(buffer.len() <= READ_LIMIT as usize);
+    }
+    let _ = FromRegion::<u64>::try_from(LimitedTypedRead(&memory, pointer, READ_LIMIT));
+    let _ = RawIntoRegion::try_from(Write(&memory, pointer, b"cosmwasm"));
+});
@@ -1,5 +1,5 @@
 use cosmwasm_orchestrate::{
-    fetcher::FileFetcher,
+    fetcher::CodeCache,
     vm::{Account, AddressHandler, Context, CosmosAddressHandler, State, VmError},
     *,
 };
@@ -47,7 +47,10 @@ impl AddressHandler for DummyAddressHandler {
 
 #[tokio::main]
 async fn main() {
-    let code = FileFetcher::from_url(REFLECT_URL).await.unwrap();
+    let code = CodeCache::from_env()
+        .from_url(REFLECT_URL, None)
+        .await
+        .unwrap();
     let sender = Account::generate_from_seed::<CustomCosmosAddressHandler>("sender").unwrap();
     let mut state = StateBuilder::<CustomCosmosAddressHandler>::new()
         .add_code(&code)
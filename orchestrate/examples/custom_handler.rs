@@ -1,5 +1,5 @@
 use cosmwasm_orchestrate::{
-    fetcher::FileFetcher,
+    fetcher::CodeCache,
     vm::{Account, AddressHandler, Context, CustomHandler, JunoAddressHandler, State, VmError},
     *,
 };
@@ -66,7 +66,10 @@ pub type CustomJunoApi<'a, E = Dispatch> = Api<
 
 #[tokio::main]
 async fn main() {
-    let code = FileFetcher::from_url(REFLECT_URL).await.unwrap();
+    let code = CodeCache::from_env()
+        .from_url(REFLECT_URL, None)
+        .await
+        .unwrap();
     let sender = Account::generate_from_seed::<JunoAddressHandler>("sender").unwrap();
     let mut state = StateBuilder::<JunoAddressHandler, MyCustomHandler>::new()
         .add_code(&code)
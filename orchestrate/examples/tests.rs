@@ -23,7 +23,10 @@ mod tests {
     #[tokio::test]
     async fn bank() {
         initialize();
-        let code = FileFetcher::from_url(REFLECT_URL).await.unwrap();
+        let code = CodeCache::from_env()
+            .from_url(REFLECT_URL, None)
+            .await
+            .unwrap();
         let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
         let mut state = StateBuilder::<WasmAddressHandler>::new()
             .add_code(&code)
@@ -90,13 +93,16 @@ mod tests {
     async fn cw20() {
         initialize();
 
-        // Fetch the wasm binary of the given contract from a remote chain.
-        let code = CosmosFetcher::from_contract_addr(
-            "https://juno-api.polkachu.com",
-            "juno19rqljkh95gh40s7qdx40ksx3zq5tm4qsmsrdz9smw668x9zdr3lqtg33mf",
-        )
-        .await
-        .unwrap();
+        // Fetch the wasm binary of the given contract from a remote chain, unless cached.
+        let code = CodeCache::from_env()
+            .from_contract_addr::<CosmosFetcher>(
+                "juno-1",
+                "https://juno-api.polkachu.com",
+                "juno19rqljkh95gh40s7qdx40ksx3zq5tm4qsmsrdz9smw668x9zdr3lqtg33mf",
+                None,
+            )
+            .await
+            .unwrap();
 
         // Generate a Juno compatible address
         let sender = Account::generate_from_seed::<JunoAddressHandler>("sender").unwrap();
@@ -1,8 +1,13 @@
-use crate::vm::{
-    Account, AddressHandler, Context, CustomHandler, IbcChannelId, JunoAddressHandler, State,
-    SubstrateAddressHandler, VmError, VmState, WasmAddressHandler,
+use crate::{
+    error::Error,
+    genesis::Genesis,
+    vm::{
+        Account, AddressHandler, Context, CustomHandler, IbcChannelId, JunoAddressHandler, State,
+        SubstrateAddressHandler, VmError, VmState, WasmAddressHandler,
+    },
 };
 use core::marker::PhantomData;
+use core::time::Duration;
 use cosmwasm_std::{
     from_binary, Addr, Binary, BlockInfo, Coin, ContractInfo, Env, Event, IbcChannelConnectMsg,
     IbcChannelOpenMsg, IbcPacketAckMsg, IbcPacketReceiveMsg, IbcPacketTimeoutMsg, MessageInfo,
@@ -483,6 +488,9 @@ pub struct StateBuilder<AH: AddressHandler, CH: CustomHandler = ()> {
     balances: Vec<(Account, Coin)>,
     ibc_channels: Vec<IbcChannelId>,
     custom_handler: CH,
+    execution_timeout: Option<Duration>,
+    diagnostics: bool,
+    genesis: Option<Genesis>,
     _marker: PhantomData<AH>,
 }
 
@@ -500,6 +508,9 @@ impl<CH: CustomHandler + Default, AH: AddressHandler> StateBuilder<AH, CH> {
             balances: Vec::default(),
             ibc_channels: Vec::default(),
             custom_handler: CH::default(),
+            execution_timeout: None,
+            diagnostics: false,
+            genesis: None,
             _marker: PhantomData,
         }
     }
@@ -540,14 +551,54 @@ impl<CH: CustomHandler + Default, AH: AddressHandler> StateBuilder<AH, CH> {
         self
     }
 
+    /// Abort any top-level call running for longer than `timeout`, regardless of the gas left.
+    /// Contracts without gas metering are instrumented with checks at function entries and loop
+    /// headers.
+    #[must_use]
+    pub fn set_execution_timeout(mut self, timeout: Duration) -> Self {
+        self.execution_timeout = Some(timeout);
+        self
+    }
+
+    /// Report the functions active when a contract traps or aborts, see
+    /// [`State::enable_diagnostics`].
+    #[must_use]
+    pub fn enable_diagnostics(mut self) -> Self {
+        self.diagnostics = true;
+        self
+    }
+
+    /// Load the `wasm` and `bank` sections of a wasmd genesis, replacing any genesis previously
+    /// loaded. See [`State::import_genesis`].
+    #[must_use]
+    pub fn set_genesis(mut self, genesis: Genesis) -> Self {
+        self.genesis = Some(genesis);
+        self
+    }
+
+    /// Load a `genesis.json` or the output of `wasmd export`, see [`Self::set_genesis`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the `wasm` or `bank` sections are malformed.
+    pub fn load_genesis(self, genesis: &[u8]) -> Result<Self, Error> {
+        Ok(self.set_genesis(Genesis::from_slice(genesis)?))
+    }
+
     #[must_use]
     pub fn build(self) -> State<CH, AH> {
-        State::new(
+        let mut state = State::new(
             self.codes,
             self.balances,
             self.ibc_channels,
             self.custom_handler,
-        )
+        );
+        state.execution_timeout = self.execution_timeout;
+        state.diagnostics = self.diagnostics;
+        if let Some(genesis) = self.genesis {
+            state.import_genesis(genesis);
+        }
+        state
     }
 }
 
@@ -0,0 +1,495 @@
+use crate::{
+    vm::{
+        AccessConfig, Account, AddressHandler, Context, CustomHandler, JunoAddressHandler, State,
+        SubstrateAddressHandler, VmError, WasmAddressHandler,
+    },
+    Api as IApi, Direct, Dispatch,
+};
+use core::time::Duration;
+use cosmwasm_std::{
+    Binary, BlockInfo, Coin, ContractInfo, Env, Event, MessageInfo, Timestamp, TransactionInfo,
+};
+use cosmwasm_vm::system::CosmwasmCodeId;
+use serde::{de::DeserializeOwned, Serialize};
+
+type Api<'a, E, CH, AH> = IApi<'a, E, AH, State<CH, AH>, Context<'a, CH, AH>>;
+
+pub type JunoChain<CH = ()> = Chain<CH, JunoAddressHandler>;
+
+pub type WasmChain<CH = ()> = Chain<CH, WasmAddressHandler>;
+
+pub type SubstrateChain<CH = ()> = Chain<CH, SubstrateAddressHandler>;
+
+/// Configuration of the chain simulated by a [`Chain`]. The addresses are handled by the
+/// `AddressHandler` of the chain.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ChainConfig {
+    pub chain_id: String,
+    /// Height of the current block.
+    pub height: u64,
+    /// Time of the current block.
+    pub time: Timestamp,
+    /// Time elapsed between two blocks.
+    pub block_time: Duration,
+    /// Gas limit of each call.
+    pub gas: u64,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self {
+            chain_id: "orchestrate-chain".into(),
+            height: 1,
+            time: Timestamp::from_seconds(1),
+            block_time: Duration::from_secs(5),
+            gas: 100_000_000,
+        }
+    }
+}
+
+/// A message of a transaction, see [`Chain::tx`].
+#[derive(Clone, Debug)]
+pub enum TxMessage {
+    /// Upload a code, ids being assigned sequentially.
+    StoreCode {
+        code: Vec<u8>,
+        instantiate_permission: AccessConfig,
+    },
+    /// Instantiate a contract, `funds` being transferred from the signer to the new contract.
+    Instantiate {
+        code_id: CosmwasmCodeId,
+        admin: Option<Account>,
+        message: Vec<u8>,
+        funds: Vec<Coin>,
+    },
+    /// Execute a contract, `funds` being transferred from the signer to the contract.
+    Execute {
+        contract: Account,
+        message: Vec<u8>,
+        funds: Vec<Coin>,
+    },
+    /// Migrate a contract, the signer must be its admin.
+    Migrate {
+        contract: Account,
+        code_id: CosmwasmCodeId,
+        message: Vec<u8>,
+    },
+    /// Transfer `amount` from the signer to `to`.
+    BankSend { to: Account, amount: Vec<Coin> },
+}
+
+impl TxMessage {
+    /// * `code_id`: Id of code to instantiate a contract from.
+    /// * `admin`: Admin of the contract.
+    /// * `message`: Typed message. Possibly `InstantiateMsg` from a contract.
+    /// * `funds`: Funds sent to the contract.
+    pub fn instantiate<M: Serialize>(
+        code_id: CosmwasmCodeId,
+        admin: Option<Account>,
+        message: M,
+        funds: Vec<Coin>,
+    ) -> Result<Self, VmError> {
+        Ok(Self::Instantiate {
+            code_id,
+            admin,
+            message: serde_json::to_vec(&message).map_err(|_| VmError::CannotSerialize)?,
+            funds,
+        })
+    }
+
+    /// * `contract`: Contract to execute.
+    /// * `message`: Typed message. Possibly `ExecuteMsg` from a contract.
+    /// * `funds`: Funds sent to the contract.
+    pub fn execute<M: Serialize>(
+        contract: Account,
+        message: M,
+        funds: Vec<Coin>,
+    ) -> Result<Self, VmError> {
+        Ok(Self::Execute {
+            contract,
+            message: serde_json::to_vec(&message).map_err(|_| VmError::CannotSerialize)?,
+            funds,
+        })
+    }
+
+    /// * `contract`: Contract to migrate.
+    /// * `code_id`: Id of the code to migrate to.
+    /// * `message`: Typed message. Possibly `MigrateMsg` from a contract.
+    pub fn migrate<M: Serialize>(
+        contract: Account,
+        code_id: CosmwasmCodeId,
+        message: M,
+    ) -> Result<Self, VmError> {
+        Ok(Self::Migrate {
+            contract,
+            code_id,
+            message: serde_json::to_vec(&message).map_err(|_| VmError::CannotSerialize)?,
+        })
+    }
+}
+
+/// Result of a [`TxMessage`], in the same order as the messages of the transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageResponse {
+    StoreCode {
+        code_id: CosmwasmCodeId,
+    },
+    Instantiate {
+        contract: Account,
+        data: Option<Binary>,
+        events: Vec<Event>,
+    },
+    Execute {
+        data: Option<Binary>,
+        events: Vec<Event>,
+    },
+    Migrate {
+        data: Option<Binary>,
+        events: Vec<Event>,
+    },
+    BankSend {
+        events: Vec<Event>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxResponse {
+    /// Gas consumed by the whole transaction.
+    pub gas_used: u64,
+    pub responses: Vec<MessageResponse>,
+}
+
+/// A session over a [`State`] deriving the `Env` and `MessageInfo` of the calls from the current
+/// block. Each instantiation or execution is a transaction of the current block, indexed in
+/// order, until [`Chain::next_block`] is called.
+pub struct Chain<CH, AH> {
+    pub state: State<CH, AH>,
+    pub config: ChainConfig,
+    transaction_index: u32,
+}
+
+impl<CH: CustomHandler, AH: AddressHandler> Chain<CH, AH> {
+    #[must_use]
+    pub fn new(state: State<CH, AH>, config: ChainConfig) -> Self {
+        Self {
+            state,
+            config,
+            transaction_index: 0,
+        }
+    }
+
+    /// The current block.
+    #[must_use]
+    pub fn block(&self) -> BlockInfo {
+        BlockInfo {
+            height: self.config.height,
+            time: self.config.time,
+            chain_id: self.config.chain_id.clone(),
+        }
+    }
+
+    /// Index of the next transaction within the current block.
+    #[must_use]
+    pub fn transaction_index(&self) -> u32 {
+        self.transaction_index
+    }
+
+    /// Move to the next block, `block_time` later.
+    pub fn next_block(&mut self) {
+        self.config.height += 1;
+        self.config.time = self
+            .config
+            .time
+            .plus_nanos(u64::try_from(self.config.block_time.as_nanos()).unwrap_or(u64::MAX));
+        self.transaction_index = 0;
+    }
+
+    /// `Env` of a contract outside of any transaction, as seen by queries.
+    ///
+    /// * `contract`: Executing contract's address.
+    #[must_use]
+    pub fn env(&self, contract: &Account) -> Env {
+        Env {
+            block: self.block(),
+            transaction: None,
+            contract: ContractInfo {
+                address: contract.clone().into(),
+            },
+        }
+    }
+
+    /// Upload a code under the next code id, see [`State::store_code`].
+    ///
+    /// * `sender`: Creator of the code.
+    /// * `code`: Wasm binary of the code.
+    /// * `access_config`: Accounts allowed to instantiate contracts from the code.
+    pub fn store_code(
+        &mut self,
+        sender: &Account,
+        code: &[u8],
+        access_config: AccessConfig,
+    ) -> Result<CosmwasmCodeId, VmError> {
+        self.state.store_code(sender, code.into(), access_config)
+    }
+
+    /// Instantiate a contract, `funds` being transferred from `sender` to the new contract.
+    /// Returns the contract address along with the data and events of the instantiation.
+    ///
+    /// * `sender`: Creator of the contract.
+    /// * `code_id`: Id of code to instantiate a contract from.
+    /// * `admin`: Admin of the contract.
+    /// * `message`: Typed message. Possibly `InstantiateMsg` from a contract.
+    /// * `funds`: Funds sent to the contract.
+    pub fn instantiate<M: Serialize>(
+        &mut self,
+        sender: &Account,
+        code_id: CosmwasmCodeId,
+        admin: Option<Account>,
+        message: M,
+        funds: Vec<Coin>,
+    ) -> Result<(Account, (Option<Binary>, Vec<Event>)), VmError> {
+        let message = serde_json::to_vec(&message).map_err(|_| VmError::CannotSerialize)?;
+        let (_, code_hash) = self
+            .state
+            .codes
+            .get(&code_id)
+            .ok_or(VmError::CodeNotFound(code_id))?;
+        let contract = Account::generate::<AH>(code_hash, &message)?;
+        let transaction = self.next_transaction();
+        let (env, info) = Self::call(transaction, self.block(), sender, &contract, funds.clone());
+        let gas = self.config.gas;
+        self.transact(sender, &contract, &funds, |state| {
+            Api::<Dispatch, CH, AH>::instantiate_with_address_raw(
+                state, code_id, admin, env, info, gas, &message,
+            )
+        })
+    }
+
+    /// Execute a contract, `funds` being transferred from `sender` to the contract.
+    ///
+    /// * `sender`: Caller of the contract.
+    /// * `contract`: Contract to execute.
+    /// * `message`: Typed message. Possibly `ExecuteMsg` from a contract.
+    /// * `funds`: Funds sent to the contract.
+    pub fn execute<M: Serialize>(
+        &mut self,
+        sender: &Account,
+        contract: &Account,
+        message: M,
+        funds: Vec<Coin>,
+    ) -> Result<(Option<Binary>, Vec<Event>), VmError> {
+        let message = serde_json::to_vec(&message).map_err(|_| VmError::CannotSerialize)?;
+        let transaction = self.next_transaction();
+        let (env, info) = Self::call(transaction, self.block(), sender, contract, funds.clone());
+        let gas = self.config.gas;
+        self.transact(sender, contract, &funds, |state| {
+            Api::<Dispatch, CH, AH>::execute_raw(state, env, info, gas, &message)
+        })
+    }
+
+    /// Query a contract at the current block.
+    ///
+    /// * `contract`: Contract to query.
+    /// * `message`: Typed message. Possibly `QueryMsg` from a contract.
+    pub fn query<M: Serialize, R: DeserializeOwned>(
+        &mut self,
+        contract: &Account,
+        message: M,
+    ) -> Result<R, VmError> {
+        let env = self.env(contract);
+        Api::<Direct, CH, AH>::query(&mut self.state, env, message)
+    }
+
+    /// Run `messages` signed by `sender` as a single transaction of the current block. The
+    /// messages share the gas limit of the chain and either all succeed or are all rolled back,
+    /// the error of the first failing message being returned along with its index.
+    ///
+    /// * `sender`: Signer of the transaction.
+    /// * `messages`: Messages to run, in order.
+    pub fn tx(
+        &mut self,
+        sender: &Account,
+        messages: Vec<TxMessage>,
+    ) -> Result<TxResponse, VmError> {
+        let transaction = self.next_transaction();
+        let db = self.state.db.clone();
+        let codes = self.state.codes.clone();
+        let code_records = self.state.code_records.clone();
+        let mut gas_left = self.config.gas;
+        let mut responses = Vec::with_capacity(messages.len());
+        for (index, message) in messages.into_iter().enumerate() {
+            match self.run(&transaction, sender, &mut gas_left, message) {
+                Ok(response) => responses.push(response),
+                Err(e) => {
+                    self.state.db = db;
+                    self.state.codes = codes;
+                    self.state.code_records = code_records;
+                    if let Some(coverage) = &mut self.state.coverage {
+                        coverage.retain(&self.state.codes);
+                    }
+                    return Err(VmError::MessageFailed(index, Box::new(e)));
+                }
+            }
+        }
+        Ok(TxResponse {
+            gas_used: self.config.gas - gas_left,
+            responses,
+        })
+    }
+
+    /// Run a message of a transaction, contract calls consuming `gas_left`.
+    fn run(
+        &mut self,
+        transaction: &TransactionInfo,
+        sender: &Account,
+        gas_left: &mut u64,
+        message: TxMessage,
+    ) -> Result<MessageResponse, VmError> {
+        let gas = *gas_left;
+        Ok(match message {
+            TxMessage::StoreCode {
+                code,
+                instantiate_permission,
+            } => MessageResponse::StoreCode {
+                code_id: self
+                    .state
+                    .store_code(sender, code, instantiate_permission)?,
+            },
+            TxMessage::Instantiate {
+                code_id,
+                admin,
+                message,
+                funds,
+            } => {
+                let (_, code_hash) = self
+                    .state
+                    .codes
+                    .get(&code_id)
+                    .ok_or(VmError::CodeNotFound(code_id))?;
+                let contract = Account::generate::<AH>(code_hash, &message)?;
+                self.state.db.transfer(sender, &contract, &funds)?;
+                let (env, info) =
+                    Self::call(transaction.clone(), self.block(), sender, &contract, funds);
+                let (contract, (data, events)) =
+                    Api::<Dispatch, CH, AH>::instantiate_with_address_raw(
+                        &mut self.state,
+                        code_id,
+                        admin,
+                        env,
+                        info,
+                        gas,
+                        &message,
+                    )?;
+                *gas_left = *self.state.gas.current();
+                MessageResponse::Instantiate {
+                    contract,
+                    data,
+                    events,
+                }
+            }
+            TxMessage::Execute {
+                contract,
+                message,
+                funds,
+            } => {
+                self.state.db.transfer(sender, &contract, &funds)?;
+                let (env, info) =
+                    Self::call(transaction.clone(), self.block(), sender, &contract, funds);
+                let (data, events) = Api::<Dispatch, CH, AH>::execute_raw(
+                    &mut self.state,
+                    env,
+                    info,
+                    gas,
+                    &message,
+                )?;
+                *gas_left = *self.state.gas.current();
+                MessageResponse::Execute { data, events }
+            }
+            TxMessage::Migrate {
+                contract,
+                code_id,
+                message,
+            } => {
+                let (env, info) =
+                    Self::call(transaction.clone(), self.block(), sender, &contract, vec![]);
+                let (data, events) = Api::<Dispatch, CH, AH>::migrate_raw(
+                    &mut self.state,
+                    code_id,
+                    env,
+                    info,
+                    gas,
+                    &message,
+                )?;
+                *gas_left = *self.state.gas.current();
+                MessageResponse::Migrate { data, events }
+            }
+            TxMessage::BankSend { to, amount } => {
+                self.state.db.transfer(sender, &to, &amount)?;
+                let amount = amount
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",");
+                MessageResponse::BankSend {
+                    events: vec![Event::new("transfer")
+                        .add_attribute("recipient", to.to_string())
+                        .add_attribute("sender", sender.to_string())
+                        .add_attribute("amount", amount)],
+                }
+            }
+        })
+    }
+
+    /// Allocate the next transaction of the current block.
+    fn next_transaction(&mut self) -> TransactionInfo {
+        let transaction = TransactionInfo {
+            index: self.transaction_index,
+        };
+        self.transaction_index += 1;
+        transaction
+    }
+
+    /// `Env` and `MessageInfo` of a call made by `sender` to `contract` within `transaction`.
+    fn call(
+        transaction: TransactionInfo,
+        block: BlockInfo,
+        sender: &Account,
+        contract: &Account,
+        funds: Vec<Coin>,
+    ) -> (Env, MessageInfo) {
+        (
+            Env {
+                block,
+                transaction: Some(transaction),
+                contract: ContractInfo {
+                    address: contract.clone().into(),
+                },
+            },
+            MessageInfo {
+                sender: sender.clone().into(),
+                funds,
+            },
+        )
+    }
+
+    /// Transfer `funds` to `contract` before calling it, the transfer being reverted if the call
+    /// fails.
+    fn transact<R>(
+        &mut self,
+        sender: &Account,
+        contract: &Account,
+        funds: &[Coin],
+        call: impl FnOnce(&mut State<CH, AH>) -> Result<R, VmError>,
+    ) -> Result<R, VmError> {
+        let bank = self.state.db.bank.clone();
+        let result = self
+            .state
+            .db
+            .transfer(sender, contract, funds)
+            .and_then(|()| call(&mut self.state));
+        if result.is_err() {
+            self.state.db.bank = bank;
+        }
+        result
+    }
+}
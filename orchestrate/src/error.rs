@@ -4,10 +4,19 @@ use core::fmt::Display;
 #[allow(clippy::module_name_repetitions)]
 pub enum Error {
     Network,
+    /// The endpoint answered with a non-success HTTP status.
+    Status(u16),
+    /// The fetched file is not a wasm module.
+    NotWasm,
     CannotDecode,
     CannotSerialize,
     CannotDeserialize,
     CannotCompileWasm,
+    CannotDecompress,
+    /// The code is not cached and the cache is offline.
+    CacheMiss,
+    ChecksumMismatch,
+    Io(std::io::Error),
 }
 
 impl Display for Error {
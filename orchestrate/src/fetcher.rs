@@ -1,6 +1,25 @@
-use crate::error::Error;
+use crate::{
+    error::Error,
+    genesis::{
+        self, AccessConfig, AccessType, AppState, Balance, BankGenesis, Code, Contract,
+        ContractInfo, Genesis, Model, WasmGenesis,
+    },
+};
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
+use core::{
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use cosmwasm_std::{Binary, Coin, Uint64};
+use flate2::read::GzDecoder;
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 #[allow(clippy::module_name_repetitions)]
 pub struct FileFetcher;
@@ -10,6 +29,8 @@ impl FileFetcher {
         Ok(reqwest::get(url.as_ref())
             .await
             .map_err(|_| Error::Network)?
+            .error_for_status()
+            .map_err(status_error)?
             .bytes()
             .await
             .map_err(|_| Error::Network)?
@@ -21,19 +42,17 @@ impl FileFetcher {
 pub trait CosmosApi {
     const CONTRACT_ENDPOINT: &'static str;
     const CODE_ENDPOINT: &'static str;
+    const BALANCES_ENDPOINT: &'static str = "/cosmos/bank/v1beta1/balances";
 
     async fn from_contract_addr(endpoint: &str, contract_address: &str) -> Result<Vec<u8>, Error> {
-        let response = reqwest::get(&format!(
-            "{}/{}/{}",
-            endpoint,
-            Self::CONTRACT_ENDPOINT,
-            contract_address
-        ))
-        .await
-        .map_err(|_| Error::Network)?
-        .text()
-        .await
-        .map_err(|_| Error::Network)?;
+        let response = reqwest::get(url(endpoint, Self::CONTRACT_ENDPOINT, &[contract_address])?)
+            .await
+            .map_err(|_| Error::Network)?
+            .error_for_status()
+            .map_err(status_error)?
+            .text()
+            .await
+            .map_err(|_| Error::Network)?;
 
         let response: ContractResponse =
             serde_json::from_str(&response).map_err(|_| Error::CannotDeserialize)?;
@@ -46,9 +65,11 @@ pub trait CosmosApi {
     }
 
     async fn from_code_id(endpoint: &str, code_id: u64) -> Result<Vec<u8>, Error> {
-        let response = reqwest::get(format!("{}/{}/{}", endpoint, Self::CODE_ENDPOINT, code_id))
+        let response = reqwest::get(url(endpoint, Self::CODE_ENDPOINT, &[&code_id.to_string()])?)
             .await
             .map_err(|_| Error::Network)?
+            .error_for_status()
+            .map_err(status_error)?
             .text()
             .await
             .map_err(|_| Error::Network)?;
@@ -57,6 +78,162 @@ pub trait CosmosApi {
             serde_json::from_str(&response).map_err(|_| Error::CannotDeserialize)?;
         base64::decode(response.data).map_err(|_| Error::CannotDecode)
     }
+
+    /// Fetch a code along with its creator and instantiate permission.
+    async fn code(endpoint: &str, code_id: u64) -> Result<Code, Error> {
+        get::<CodeResponse>(url(endpoint, Self::CODE_ENDPOINT, &[&code_id.to_string()])?)
+            .await
+            .map(|code| code.into_code(code_id))
+    }
+
+    /// Fetch the code id, creator, admin and label of a contract.
+    async fn contract_info(endpoint: &str, contract_address: &str) -> Result<ContractInfo, Error> {
+        let ContractInfoResponse { contract_info } =
+            get(url(endpoint, Self::CONTRACT_ENDPOINT, &[contract_address])?).await?;
+        Ok(contract_info)
+    }
+
+    /// Fetch the whole raw storage of a contract, page by page.
+    async fn contract_state(endpoint: &str, contract_address: &str) -> Result<Vec<Model>, Error> {
+        get_all::<StateResponse>(url(
+            endpoint,
+            Self::CONTRACT_ENDPOINT,
+            &[contract_address, "state"],
+        )?)
+        .await
+    }
+
+    /// Fetch all the balances of an account, page by page.
+    async fn balances(endpoint: &str, address: &str) -> Result<Vec<Coin>, Error> {
+        get_all::<BalancesResponse>(url(endpoint, Self::BALANCES_ENDPOINT, &[address])?).await
+    }
+
+    /// Fetch a contract with its code, storage and balances, ready to be installed with its
+    /// original address, admin and label by [`crate::vm::State::import_genesis`].
+    async fn fetch_contract(endpoint: &str, contract_address: &str) -> Result<Genesis, Error> {
+        let contract_info = Self::contract_info(endpoint, contract_address).await?;
+        let code = Self::code(endpoint, contract_info.code_id.u64()).await?;
+        let contract_state = Self::contract_state(endpoint, contract_address).await?;
+        let coins = Self::balances(endpoint, contract_address).await?;
+        Ok(contract_genesis(
+            contract_address,
+            contract_info,
+            code,
+            contract_state,
+            vec![Balance {
+                address: contract_address.into(),
+                coins,
+            }],
+        ))
+    }
+}
+
+/// The genesis installing a single contract along with its code.
+fn contract_genesis(
+    contract_address: &str,
+    contract_info: ContractInfo,
+    code: Code,
+    contract_state: Vec<Model>,
+    balances: Vec<Balance>,
+) -> Genesis {
+    Genesis {
+        app_state: AppState {
+            bank: BankGenesis {
+                balances,
+                supply: Vec::new(),
+            },
+            wasm: WasmGenesis {
+                codes: vec![code],
+                contracts: vec![Contract {
+                    contract_address: contract_address.into(),
+                    contract_info,
+                    contract_state,
+                    contract_code_history: Vec::new(),
+                }],
+                sequences: Vec::new(),
+            },
+        },
+    }
+}
+
+fn status_error(e: reqwest::Error) -> Error {
+    e.status()
+        .map_or(Error::Network, |status| Error::Status(status.as_u16()))
+}
+
+/// The URL of `route` on `endpoint`, followed by `segments` which are percent-encoded. The path
+/// of `endpoint` is kept, so that an LCD served under a prefix can be used.
+fn url(endpoint: &str, route: &str, segments: &[&str]) -> Result<Url, Error> {
+    let mut url = Url::parse(endpoint).map_err(|_| Error::Network)?;
+    url.path_segments_mut()
+        .map_err(|()| Error::Network)?
+        .pop_if_empty()
+        .extend(route.split('/').filter(|segment| !segment.is_empty()))
+        .extend(segments);
+    Ok(url)
+}
+
+async fn get<T: DeserializeOwned>(url: Url) -> Result<T, Error> {
+    let response = reqwest::get(url)
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(status_error)?
+        .text()
+        .await
+        .map_err(|_| Error::Network)?;
+    decode(&response)
+}
+
+async fn get_all<P: Page>(url: Url) -> Result<Vec<P::Item>, Error> {
+    let mut pages = Pages::new(url);
+    while let Some(url) = pages.next_url() {
+        pages.push(get::<P>(url).await?);
+    }
+    Ok(pages.items)
+}
+
+fn decode<T: DeserializeOwned>(response: &str) -> Result<T, Error> {
+    serde_json::from_str(response).map_err(|_| Error::CannotDeserialize)
+}
+
+/// Items of a paginated query, following its `next_key` until the last page.
+struct Pages<T> {
+    url: Url,
+    next: Option<Url>,
+    items: Vec<T>,
+}
+
+impl<T> Pages<T> {
+    fn new(url: Url) -> Self {
+        Self {
+            next: Some(url.clone()),
+            url,
+            items: Vec::new(),
+        }
+    }
+
+    /// The URL of the next page, if any.
+    fn next_url(&mut self) -> Option<Url> {
+        self.next.take()
+    }
+
+    fn push<P: Page<Item = T>>(&mut self, page: P) {
+        let (items, pagination) = page.into_parts();
+        self.items.extend(items);
+        self.next = pagination
+            .and_then(|pagination| pagination.next_key)
+            .filter(|key| !key.is_empty())
+            .map(|key| {
+                let mut url = self.url.clone();
+                url.query_pairs_mut().append_pair("pagination.key", &key);
+                url
+            });
+    }
+}
+
+trait Page: DeserializeOwned {
+    type Item;
+    fn into_parts(self) -> (Vec<Self::Item>, Option<Pagination>);
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -82,3 +259,361 @@ struct ContractResponse {
 struct CosmosResponse {
     data: String,
 }
+
+/// Content-addressed on-disk cache of fetched codes, keyed by URL or by chain id and code id or
+/// contract address.
+/// The codes are stored under their SHA-256 in `blobs`, the keys pointing to them in `index`.
+#[derive(Clone, Debug)]
+pub struct CodeCache {
+    pub dir: PathBuf,
+    offline: bool,
+    decompress: bool,
+}
+
+impl CodeCache {
+    /// Cache the codes in `dir`.
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            offline: false,
+            decompress: false,
+        }
+    }
+
+    /// Cache the codes in `ORCHESTRATE_CACHE_DIR`, `.orchestrate-cache` by default, offline if
+    /// `ORCHESTRATE_OFFLINE` is set.
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var_os("ORCHESTRATE_CACHE_DIR")
+                .map_or_else(|| PathBuf::from(".orchestrate-cache"), PathBuf::from),
+        )
+        .set_offline(std::env::var_os("ORCHESTRATE_OFFLINE").is_some())
+    }
+
+    /// Never hit the network, failing with [`Error::CacheMiss`] on a code not cached yet.
+    #[must_use]
+    pub fn set_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Decompress the fetched codes that are gzipped.
+    #[must_use]
+    pub fn set_decompress(mut self, decompress: bool) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Fetch a code with [`FileFetcher::from_url`], unless cached.
+    ///
+    /// * `url`: Location of the code.
+    /// * `checksum`: Expected hex encoded SHA-256 of the code, if known.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the code can't be fetched or doesn't match `checksum`.
+    pub async fn from_url(&self, url: &str, checksum: Option<&str>) -> Result<Vec<u8>, Error> {
+        self.get_or_fetch(&format!("url:{url}"), checksum, FileFetcher::from_url(url))
+            .await
+    }
+
+    /// Fetch a code with [`CosmosApi::from_code_id`], unless cached.
+    ///
+    /// * `chain_id`: Chain served by `endpoint`, code ids being only unique within a chain.
+    /// * `endpoint`: LCD endpoint of the chain.
+    /// * `code_id`: Id of the code.
+    /// * `checksum`: Expected hex encoded SHA-256 of the code, if known.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the code can't be fetched or doesn't match `checksum`.
+    pub async fn from_code_id<C: CosmosApi>(
+        &self,
+        chain_id: &str,
+        endpoint: &str,
+        code_id: u64,
+        checksum: Option<&str>,
+    ) -> Result<Vec<u8>, Error> {
+        self.get_or_fetch(
+            &format!("code:{chain_id}:{code_id}"),
+            checksum,
+            C::from_code_id(endpoint, code_id),
+        )
+        .await
+    }
+
+    /// Fetch the code of a contract with [`CosmosApi::from_contract_addr`], unless cached.
+    ///
+    /// A migrated contract keeps being served the code cached before its migration, pass its
+    /// `checksum` to have the new code fetched instead.
+    ///
+    /// * `chain_id`: Chain served by `endpoint`, addresses being only unique within a chain.
+    /// * `endpoint`: LCD endpoint of the chain.
+    /// * `contract_address`: Address of the contract.
+    /// * `checksum`: Expected hex encoded SHA-256 of the code, if known.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the code can't be fetched or doesn't match `checksum`.
+    pub async fn from_contract_addr<C: CosmosApi>(
+        &self,
+        chain_id: &str,
+        endpoint: &str,
+        contract_address: &str,
+        checksum: Option<&str>,
+    ) -> Result<Vec<u8>, Error> {
+        self.get_or_fetch(
+            &format!("contract:{chain_id}:{contract_address}"),
+            checksum,
+            C::from_contract_addr(endpoint, contract_address),
+        )
+        .await
+    }
+
+    async fn get_or_fetch(
+        &self,
+        key: &str,
+        checksum: Option<&str>,
+        fetch: impl Future<Output = Result<Vec<u8>, Error>>,
+    ) -> Result<Vec<u8>, Error> {
+        let index = self
+            .dir
+            .join("index")
+            .join(hex::encode(Sha256::digest(key)));
+        if let Some(code) = self.load(&index, checksum) {
+            return Ok(code);
+        }
+        if self.offline {
+            return Err(Error::CacheMiss);
+        }
+        let mut code = fetch.await?;
+        if self.decompress && code.starts_with(&[0x1f, 0x8b]) {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(&code[..])
+                .read_to_end(&mut decompressed)
+                .map_err(|_| Error::CannotDecompress)?;
+            code = decompressed;
+        }
+        // Never cache an error page served with a success status.
+        if !code.starts_with(b"\0asm") {
+            return Err(Error::NotWasm);
+        }
+        let hash = hex::encode(Sha256::digest(&code));
+        if checksum.map_or(false, |checksum| !checksum.eq_ignore_ascii_case(&hash)) {
+            return Err(Error::ChecksumMismatch);
+        }
+        write_atomically(&self.dir.join("blobs").join(&hash), &code)?;
+        write_atomically(&index, hash.as_bytes())?;
+        Ok(code)
+    }
+
+    /// The cached code, if it is intact and matches `checksum`.
+    fn load(&self, index: &Path, checksum: Option<&str>) -> Option<Vec<u8>> {
+        let hash = fs::read_to_string(index).ok()?;
+        let code = fs::read(self.dir.join("blobs").join(&hash)).ok()?;
+        let actual = hex::encode(Sha256::digest(&code));
+        (actual == hash && checksum.map_or(true, |checksum| checksum.eq_ignore_ascii_case(&hash)))
+            .then_some(code)
+    }
+}
+
+/// Write to a temporary file first, so that concurrent tests never read a partial file. The
+/// temporary file is unique to the write, tests of a binary running as threads of one process.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<(), Error> {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let parent = path.parent().expect("cache paths have a parent");
+    fs::create_dir_all(parent).map_err(Error::Io)?;
+    let temporary = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temporary, content).map_err(Error::Io)?;
+    fs::rename(&temporary, path).map_err(Error::Io)
+}
+
+/// Blocking client of an LCD endpoint, backing the lazy storage of a forked
+/// [`crate::vm::State`], see [`crate::vm::Db::fork`]. It must not be used from within an async
+/// runtime.
+#[derive(Clone, Debug)]
+pub struct RemoteFork {
+    pub endpoint: String,
+    contract_endpoint: &'static str,
+    code_endpoint: &'static str,
+    balances_endpoint: &'static str,
+}
+
+impl RemoteFork {
+    /// Fork the chain served at `endpoint`, using the routes of `C`.
+    #[must_use]
+    pub fn new<C: CosmosApi>(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            contract_endpoint: C::CONTRACT_ENDPOINT,
+            code_endpoint: C::CODE_ENDPOINT,
+            balances_endpoint: C::BALANCES_ENDPOINT,
+        }
+    }
+
+    /// Fetch a contract along with its code but without its storage, which is lazily fetched
+    /// once the contract is installed with [`crate::vm::State::import_genesis`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the contract or its code can't be fetched.
+    pub fn contract(&self, contract_address: &str) -> Result<Genesis, Error> {
+        let ContractInfoResponse { contract_info } = get_blocking(url(
+            &self.endpoint,
+            self.contract_endpoint,
+            &[contract_address],
+        )?)?;
+        let code_id = contract_info.code_id.u64();
+        let code = get_blocking::<CodeResponse>(url(
+            &self.endpoint,
+            self.code_endpoint,
+            &[&code_id.to_string()],
+        )?)?
+        .into_code(code_id);
+        Ok(contract_genesis(
+            contract_address,
+            contract_info,
+            code,
+            Vec::new(),
+            Vec::new(),
+        ))
+    }
+
+    /// Fetch the value of `key` in the storage of a contract.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the endpoint can't be reached or answers with a malformed response.
+    pub fn raw(&self, contract_address: &str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let RawResponse { data } = get_blocking(url(
+            &self.endpoint,
+            self.contract_endpoint,
+            &[contract_address, "raw", &base64::encode(key)],
+        )?)?;
+        Ok(data.map(|data| data.0).filter(|data| !data.is_empty()))
+    }
+
+    /// Fetch the whole storage of a contract.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a page can't be fetched.
+    pub fn state(&self, contract_address: &str) -> Result<Vec<Model>, Error> {
+        get_all_blocking::<StateResponse>(url(
+            &self.endpoint,
+            self.contract_endpoint,
+            &[contract_address, "state"],
+        )?)
+    }
+
+    /// Fetch all the balances of an account.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a page can't be fetched.
+    pub fn balances(&self, address: &str) -> Result<Vec<Coin>, Error> {
+        get_all_blocking::<BalancesResponse>(url(
+            &self.endpoint,
+            self.balances_endpoint,
+            &[address],
+        )?)
+    }
+}
+
+fn get_blocking<T: DeserializeOwned>(url: Url) -> Result<T, Error> {
+    let response = reqwest::blocking::get(url)
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .map_err(status_error)?
+        .text()
+        .map_err(|_| Error::Network)?;
+    decode(&response)
+}
+
+fn get_all_blocking<P: Page>(url: Url) -> Result<Vec<P::Item>, Error> {
+    let mut pages = Pages::new(url);
+    while let Some(url) = pages.next_url() {
+        pages.push(get_blocking::<P>(url)?);
+    }
+    Ok(pages.items)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RawResponse {
+    data: Option<Binary>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Pagination {
+    next_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CodeInfoResponse {
+    creator: String,
+    instantiate_permission: Option<AccessConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CodeResponse {
+    code_info: CodeInfoResponse,
+    data: Binary,
+}
+
+impl CodeResponse {
+    fn into_code(self, code_id: u64) -> Code {
+        Code {
+            code_id: Uint64::new(code_id),
+            code_info: genesis::CodeInfo {
+                code_hash: Binary::from(&Sha256::digest(&self.data.0)[..]),
+                creator: self.code_info.creator,
+                instantiate_config: self
+                    .code_info
+                    .instantiate_permission
+                    .unwrap_or(AccessConfig {
+                        permission: AccessType::Everybody,
+                        address: String::new(),
+                        addresses: Vec::new(),
+                    }),
+            },
+            code_bytes: self.data,
+            pinned: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ContractInfoResponse {
+    contract_info: ContractInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StateResponse {
+    models: Vec<Model>,
+    pagination: Option<Pagination>,
+}
+
+impl Page for StateResponse {
+    type Item = Model;
+    fn into_parts(self) -> (Vec<Model>, Option<Pagination>) {
+        (self.models, self.pagination)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BalancesResponse {
+    balances: Vec<Coin>,
+    pagination: Option<Pagination>,
+}
+
+impl Page for BalancesResponse {
+    type Item = Coin;
+    fn into_parts(self) -> (Vec<Coin>, Option<Pagination>) {
+        (self.balances, self.pagination)
+    }
+}
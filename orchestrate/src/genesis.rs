@@ -0,0 +1,445 @@
+//! The `wasm` and `bank` sections of a wasmd `genesis.json`, as written by `wasmd export`.
+
+use crate::{
+    error::Error,
+    vm::{
+        self, Account, CodeHistoryEntry, CodeHistoryOperation, CodeRecord, ContractRecord, State,
+        Storage,
+    },
+};
+use alloc::collections::BTreeMap;
+use cosmwasm_std::{Binary, Coin, Uint64};
+use cosmwasm_vm::system::{CosmwasmCodeId, CosmwasmContractMeta};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+/// Key of the last code id in the wasmd store, `0x04 || "lastCodeId"`.
+const LAST_CODE_ID_KEY: &[u8] = b"\x04lastCodeId";
+/// Key of the last contract id in the wasmd store, `0x04 || "lastContractId"`.
+const LAST_CONTRACT_ID_KEY: &[u8] = b"\x04lastContractId";
+
+const OPERATION_INIT: &str = "CONTRACT_CODE_HISTORY_OPERATION_TYPE_INIT";
+const OPERATION_MIGRATE: &str = "CONTRACT_CODE_HISTORY_OPERATION_TYPE_MIGRATE";
+const OPERATION_GENESIS: &str = "CONTRACT_CODE_HISTORY_OPERATION_TYPE_GENESIS";
+
+/// A genesis file, only the `app_state` sections known to orchestrate being kept.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Genesis {
+    pub app_state: AppState,
+}
+
+impl Genesis {
+    /// Parse a `genesis.json` or the output of `wasmd export`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the `wasm` or `bank` sections are malformed.
+    pub fn from_slice(genesis: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(genesis).map_err(|_| Error::CannotDeserialize)
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct AppState {
+    #[serde(default)]
+    pub bank: BankGenesis,
+    #[serde(default)]
+    pub wasm: WasmGenesis,
+}
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BankGenesis {
+    #[serde(default)]
+    pub balances: Vec<Balance>,
+    #[serde(default)]
+    pub supply: Vec<Coin>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Balance {
+    pub address: String,
+    pub coins: Vec<Coin>,
+}
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct WasmGenesis {
+    #[serde(default)]
+    pub codes: Vec<Code>,
+    #[serde(default)]
+    pub contracts: Vec<Contract>,
+    #[serde(default)]
+    pub sequences: Vec<Sequence>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Code {
+    pub code_id: Uint64,
+    pub code_info: CodeInfo,
+    pub code_bytes: Binary,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CodeInfo {
+    pub code_hash: Binary,
+    pub creator: String,
+    pub instantiate_config: AccessConfig,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AccessConfig {
+    pub permission: AccessType,
+    #[serde(default)]
+    pub address: String,
+    #[serde(default)]
+    pub addresses: Vec<String>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum AccessType {
+    #[serde(alias = "ACCESS_TYPE_UNSPECIFIED")]
+    Unspecified,
+    #[serde(alias = "ACCESS_TYPE_NOBODY")]
+    Nobody,
+    #[serde(alias = "ACCESS_TYPE_ONLY_ADDRESS")]
+    OnlyAddress,
+    #[serde(alias = "ACCESS_TYPE_EVERYBODY")]
+    Everybody,
+    #[serde(alias = "ACCESS_TYPE_ANY_OF_ADDRESSES")]
+    AnyOfAddresses,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Contract {
+    pub contract_address: String,
+    pub contract_info: ContractInfo,
+    #[serde(default)]
+    pub contract_state: Vec<Model>,
+    #[serde(default)]
+    pub contract_code_history: Vec<ContractCodeHistoryEntry>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ContractInfo {
+    pub code_id: Uint64,
+    pub creator: String,
+    /// Empty if the contract has no admin.
+    #[serde(default)]
+    pub admin: String,
+    pub label: String,
+    #[serde(default)]
+    pub created: Option<AbsoluteTxPosition>,
+    #[serde(default)]
+    pub ibc_port_id: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AbsoluteTxPosition {
+    pub block_height: Uint64,
+    pub tx_index: Uint64,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ContractCodeHistoryEntry {
+    pub operation: String,
+    pub code_id: Uint64,
+    #[serde(default)]
+    pub updated: Option<AbsoluteTxPosition>,
+    pub msg: serde_json::Value,
+}
+
+/// An entry of the storage of a contract, the key being hex encoded.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Model {
+    #[serde(with = "hex_bytes")]
+    pub key: Vec<u8>,
+    pub value: Binary,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Sequence {
+    pub id_key: Binary,
+    pub value: Uint64,
+}
+
+/// An unspecified permission denies every instantiation, as in wasmd.
+impl From<AccessConfig> for vm::AccessConfig {
+    fn from(config: AccessConfig) -> Self {
+        match config.permission {
+            AccessType::Everybody => vm::AccessConfig::Everybody,
+            AccessType::Unspecified | AccessType::Nobody => vm::AccessConfig::Nobody,
+            AccessType::OnlyAddress => {
+                vm::AccessConfig::AnyOfAddresses(vec![Account::unchecked(config.address)])
+            }
+            AccessType::AnyOfAddresses => vm::AccessConfig::AnyOfAddresses(
+                config
+                    .addresses
+                    .into_iter()
+                    .map(Account::unchecked)
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<vm::AccessConfig> for AccessConfig {
+    fn from(config: vm::AccessConfig) -> Self {
+        let (permission, addresses) = match config {
+            vm::AccessConfig::Everybody => (AccessType::Everybody, Vec::new()),
+            vm::AccessConfig::Nobody => (AccessType::Nobody, Vec::new()),
+            vm::AccessConfig::AnyOfAddresses(addresses) => (
+                AccessType::AnyOfAddresses,
+                addresses.iter().map(ToString::to_string).collect(),
+            ),
+        };
+        AccessConfig {
+            permission,
+            address: String::new(),
+            addresses,
+        }
+    }
+}
+
+/// Upper case hex, as `HexBytes` of Tendermint.
+mod hex_bytes {
+    use super::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode_upper(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        hex::decode(String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<CH, AH> State<CH, AH> {
+    /// Load the codes, contracts and balances of `genesis`. Codes keep their id, replacing the
+    /// codes already uploaded under the same id. The supply of a denom is the one of the genesis
+    /// if any, the sum of the imported balances otherwise.
+    pub fn import_genesis(&mut self, genesis: Genesis) {
+        let AppState { bank, wasm } = genesis.app_state;
+        for Code {
+            code_id,
+            code_info,
+            code_bytes,
+            ..
+        } in wasm.codes
+        {
+            let code = code_bytes.0;
+            let code_hash = Sha256::new().chain_update(&code).finalize()[..].into();
+            self.codes.insert(code_id.u64(), (code_hash, code));
+            self.code_records.insert(
+                code_id.u64(),
+                CodeRecord {
+                    creator: Account::unchecked(code_info.creator),
+                    instantiate_permission: code_info.instantiate_config.into(),
+                },
+            );
+        }
+        for Contract {
+            contract_address,
+            contract_info,
+            contract_state,
+            contract_code_history,
+        } in wasm.contracts
+        {
+            let contract = Account::unchecked(contract_address);
+            self.db.records.insert(
+                contract.clone(),
+                ContractRecord {
+                    creator: Account::unchecked(contract_info.creator),
+                    created: contract_info
+                        .created
+                        .map_or(0, |created| created.block_height.u64()),
+                    ibc_port: (!contract_info.ibc_port_id.is_empty())
+                        .then_some(contract_info.ibc_port_id),
+                    history: contract_code_history
+                        .into_iter()
+                        .map(|entry| CodeHistoryEntry {
+                            operation: match entry.operation.as_str() {
+                                OPERATION_INIT => CodeHistoryOperation::Init,
+                                OPERATION_MIGRATE => CodeHistoryOperation::Migrate,
+                                _ => CodeHistoryOperation::Genesis,
+                            },
+                            code_id: entry.code_id.u64(),
+                            updated: entry
+                                .updated
+                                .map_or(0, |updated| updated.block_height.u64()),
+                            message: serde_json::to_vec(&entry.msg).unwrap_or_default().into(),
+                        })
+                        .collect(),
+                },
+            );
+            self.db.contracts.insert(
+                contract.clone(),
+                CosmwasmContractMeta {
+                    code_id: contract_info.code_id.u64(),
+                    admin: (!contract_info.admin.is_empty())
+                        .then(|| Account::unchecked(contract_info.admin)),
+                    label: contract_info.label,
+                },
+            );
+            self.db.storage.insert(
+                contract,
+                Storage {
+                    data: contract_state
+                        .into_iter()
+                        .map(|Model { key, value }| (key, value.0))
+                        .collect(),
+                    ..Storage::default()
+                },
+            );
+        }
+        for Balance { address, coins } in bank.balances {
+            let balances = self
+                .db
+                .bank
+                .balances
+                .entry(Account::unchecked(address))
+                .or_default();
+            for Coin { denom, amount } in coins {
+                *balances.entry(denom.clone()).or_default() += amount.u128();
+                *self.db.bank.supply.entry(denom).or_default() += amount.u128();
+            }
+        }
+        for Coin { denom, amount } in bank.supply {
+            self.db.bank.supply.insert(denom, amount.u128());
+        }
+    }
+
+    /// Export the codes, contracts and balances in the shape of a wasmd genesis. The creators of
+    /// the codes added to the builder are left empty, as are the transaction indexes.
+    #[must_use]
+    pub fn export_genesis(&self) -> Genesis {
+        let codes = self
+            .codes
+            .iter()
+            .map(|(code_id, (code_hash, code))| {
+                let record = self.code_records.get(code_id);
+                Code {
+                    code_id: Uint64::new(*code_id),
+                    code_info: CodeInfo {
+                        code_hash: Binary::from(&code_hash[..]),
+                        creator: record
+                            .map(|record| record.creator.to_string())
+                            .unwrap_or_default(),
+                        instantiate_config: record
+                            .map_or(vm::AccessConfig::Everybody, |record| {
+                                record.instantiate_permission.clone()
+                            })
+                            .into(),
+                    },
+                    code_bytes: Binary::from(&code[..]),
+                    pinned: false,
+                }
+            })
+            .collect::<Vec<_>>();
+        let contracts = self
+            .db
+            .contracts
+            .iter()
+            .map(|(contract, meta)| {
+                let record = self.db.records.get(contract);
+                Contract {
+                    contract_address: contract.to_string(),
+                    contract_info: ContractInfo {
+                        code_id: Uint64::new(meta.code_id),
+                        creator: record
+                            .map(|record| record.creator.to_string())
+                            .unwrap_or_default(),
+                        admin: meta
+                            .admin
+                            .as_ref()
+                            .map(ToString::to_string)
+                            .unwrap_or_default(),
+                        label: meta.label.clone(),
+                        created: record.map(|record| position(record.created)),
+                        ibc_port_id: record
+                            .and_then(|record| record.ibc_port.clone())
+                            .unwrap_or_default(),
+                    },
+                    contract_state: self
+                        .db
+                        .storage
+                        .get(contract)
+                        .map(|storage| {
+                            storage
+                                .data
+                                .iter()
+                                .map(|(key, value)| Model {
+                                    key: key.clone(),
+                                    value: Binary::from(&value[..]),
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    contract_code_history: self
+                        .db
+                        .code_history(contract)
+                        .iter()
+                        .map(|entry| ContractCodeHistoryEntry {
+                            operation: match entry.operation {
+                                CodeHistoryOperation::Init => OPERATION_INIT,
+                                CodeHistoryOperation::Migrate => OPERATION_MIGRATE,
+                                CodeHistoryOperation::Genesis => OPERATION_GENESIS,
+                            }
+                            .into(),
+                            code_id: Uint64::new(entry.code_id),
+                            updated: Some(position(entry.updated)),
+                            msg: serde_json::from_slice(&entry.message).unwrap_or_default(),
+                        })
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let last_code_id: CosmwasmCodeId = self.codes.keys().next_back().copied().unwrap_or(0);
+        let sequences = vec![
+            Sequence {
+                id_key: Binary::from(LAST_CODE_ID_KEY),
+                value: Uint64::new(last_code_id + 1),
+            },
+            Sequence {
+                id_key: Binary::from(LAST_CONTRACT_ID_KEY),
+                value: Uint64::new(u64::try_from(contracts.len()).unwrap_or(u64::MAX) + 1),
+            },
+        ];
+        Genesis {
+            app_state: AppState {
+                bank: BankGenesis {
+                    balances: self
+                        .db
+                        .bank
+                        .balances
+                        .iter()
+                        .map(|(account, coins)| Balance {
+                            address: account.to_string(),
+                            coins: to_coins(coins),
+                        })
+                        .collect(),
+                    supply: to_coins(&self.db.bank.supply),
+                },
+                wasm: WasmGenesis {
+                    codes,
+                    contracts,
+                    sequences,
+                },
+            },
+        }
+    }
+}
+
+fn position(block_height: u64) -> AbsoluteTxPosition {
+    AbsoluteTxPosition {
+        block_height: Uint64::new(block_height),
+        tx_index: Uint64::zero(),
+    }
+}
+
+fn to_coins(amounts: &BTreeMap<String, u128>) -> Vec<Coin> {
+    amounts
+        .iter()
+        .map(|(denom, amount)| Coin::new(*amount, denom))
+        .collect()
+}
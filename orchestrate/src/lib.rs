@@ -5,12 +5,15 @@
 extern crate alloc;
 
 mod api;
+mod chain;
 pub mod error;
 pub mod fetcher;
+pub mod genesis;
 pub mod ibc;
 pub mod vm;
 mod wasm_builder;
 
 pub use api::*;
+pub use chain::*;
 pub use cosmwasm_std;
 pub use wasm_builder::*;
@@ -2,6 +2,7 @@ use super::{AddressHandler, VmError};
 use alloc::vec::Vec;
 use core::fmt::Display;
 use cosmwasm_std::{Addr, Binary, CanonicalAddr};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 #[derive(Debug, Clone)]
@@ -27,7 +28,8 @@ impl From<CanonicalAccount> for CanonicalAddr {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
 #[allow(clippy::module_name_repetitions)]
 pub struct Account(pub Addr);
 
@@ -1,15 +1,19 @@
 use super::Account;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use cosmwasm_std::Coin;
+use serde::{Deserialize, Serialize};
 
 pub type Denom = String;
 pub type Supply = BTreeMap<Denom, u128>;
 pub type Balances = BTreeMap<Account, Supply>;
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Bank {
     pub supply: Supply,
     pub balances: Balances,
+    /// Accounts whose balances have been fetched from the remote fork.
+    #[serde(skip)]
+    pub fetched: BTreeSet<Account>,
 }
 
 impl Bank {
@@ -17,6 +21,7 @@ impl Bank {
         Self {
             supply,
             balances: initial_balances,
+            fetched: BTreeSet::new(),
         }
     }
 
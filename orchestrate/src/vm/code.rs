@@ -0,0 +1,84 @@
+use super::{Account, State, VmError};
+use cosmwasm_vm::system::CosmwasmCodeId;
+use cosmwasm_vm_wasmi::{lowering::lower_to_mvp, WasmiVMError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Who is allowed to instantiate contracts from a code.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum AccessConfig {
+    Everybody,
+    Nobody,
+    AnyOfAddresses(Vec<Account>),
+}
+
+impl AccessConfig {
+    #[must_use]
+    pub fn allows(&self, account: &Account) -> bool {
+        match self {
+            AccessConfig::Everybody => true,
+            AccessConfig::Nobody => false,
+            AccessConfig::AnyOfAddresses(addresses) => addresses.contains(account),
+        }
+    }
+}
+
+/// What a code keeps on top of its checksum, see [`State::store_code`].
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct CodeRecord {
+    pub creator: Account,
+    pub instantiate_permission: AccessConfig,
+}
+
+impl<CH, AH> State<CH, AH> {
+    /// Upload `code` under the next code id, `sender` being recorded as its creator. Contracts
+    /// can only be instantiated from it by the accounts allowed by `access_config`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `code` is not a valid module, or can't be instrumented while collecting the
+    /// coverage, in which case nothing is stored.
+    pub fn store_code(
+        &mut self,
+        sender: &Account,
+        code: Vec<u8>,
+        access_config: AccessConfig,
+    ) -> Result<CosmwasmCodeId, VmError> {
+        wasmi::Module::from_buffer(lower_to_mvp(&code).map_err(WasmiVMError::from)?)?;
+        let code_id = self
+            .codes
+            .keys()
+            .next_back()
+            .map_or(1, |code_id| code_id + 1);
+        let checksum: Vec<u8> = Sha256::new().chain_update(&code).finalize()[..].into();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.instrument(code_id, &checksum, &code)?;
+        }
+        self.codes.insert(code_id, (checksum, code));
+        self.code_records.insert(
+            code_id,
+            CodeRecord {
+                creator: sender.clone(),
+                instantiate_permission: access_config,
+            },
+        );
+        Ok(code_id)
+    }
+
+    /// Fail if `sender` is not allowed to instantiate a contract from `code_id`. Codes without a
+    /// record, such as the ones of [`crate::StateBuilder::add_code`], can be instantiated by
+    /// everybody.
+    pub(crate) fn ensure_instantiate(
+        &self,
+        sender: &Account,
+        code_id: CosmwasmCodeId,
+    ) -> Result<(), VmError> {
+        match self.code_records.get(&code_id) {
+            Some(record) if !record.instantiate_permission.allows(sender) => {
+                Err(VmError::NotAuthorized)
+            }
+            _ => Ok(()),
+        }
+    }
+}
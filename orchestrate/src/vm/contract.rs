@@ -0,0 +1,115 @@
+use super::{Account, Db, VmError};
+use cosmwasm_std::{Binary, ContractInfoResponse};
+use cosmwasm_vm::system::CosmwasmCodeId;
+use cosmwasm_vm_wasmi::{lowering::export_names, WasmiVMError};
+use serde::{Deserialize, Serialize};
+
+/// Entry point whose export binds an IBC port to the contracts of a code.
+const IBC_CHANNEL_OPEN: &str = "ibc_channel_open";
+
+/// What a contract keeps on top of its [`cosmwasm_vm::system::CosmwasmContractMeta`].
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ContractRecord {
+    pub creator: Account,
+    /// Height of the block the contract has been instantiated in.
+    pub created: u64,
+    /// `wasm.<address>` if the code exports the IBC entry points.
+    pub ibc_port: Option<String>,
+    /// Codes of the contract, the first entry being its instantiation.
+    pub history: Vec<CodeHistoryEntry>,
+}
+
+impl ContractRecord {
+    /// Record a contract instantiated from `code`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the exports of `code` can't be read.
+    pub fn new(
+        contract: &Account,
+        creator: Account,
+        code_id: CosmwasmCodeId,
+        code: &[u8],
+        created: u64,
+        message: &[u8],
+    ) -> Result<Self, VmError> {
+        let ibc_port = export_names(code)
+            .map_err(WasmiVMError::from)?
+            .contains(&IBC_CHANNEL_OPEN.as_bytes())
+            .then(|| format!("wasm.{contract}"));
+        Ok(ContractRecord {
+            creator,
+            created,
+            ibc_port,
+            history: vec![CodeHistoryEntry {
+                operation: CodeHistoryOperation::Init,
+                code_id,
+                updated: created,
+                message: Binary::from(message),
+            }],
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum CodeHistoryOperation {
+    Init,
+    Migrate,
+    /// Imported from a genesis.
+    Genesis,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct CodeHistoryEntry {
+    pub operation: CodeHistoryOperation,
+    pub code_id: CosmwasmCodeId,
+    /// Height of the block the entry has been appended in.
+    pub updated: u64,
+    /// Raw JSON message of the instantiation or migration.
+    pub message: Binary,
+}
+
+impl<CH> Db<CH> {
+    /// Info of `contract` as returned by a `WasmQuery::ContractInfo`, `None` if it doesn't exist.
+    #[must_use]
+    pub fn contract_info(&self, contract: &Account) -> Option<ContractInfoResponse> {
+        let meta = self.contracts.get(contract)?;
+        let record = self.records.get(contract);
+        let mut info = ContractInfoResponse::new(
+            meta.code_id,
+            record
+                .map(|record| record.creator.to_string())
+                .unwrap_or_default(),
+        );
+        info.admin = meta.admin.as_ref().map(ToString::to_string);
+        info.ibc_port = record.and_then(|record| record.ibc_port.clone());
+        Some(info)
+    }
+
+    /// Code history of `contract`, oldest entry first.
+    #[must_use]
+    pub fn code_history(&self, contract: &Account) -> &[CodeHistoryEntry] {
+        self.records
+            .get(contract)
+            .map_or(&[], |record| &record.history)
+    }
+
+    /// Append the migration of `contract` to `code_id` to its code history.
+    pub(crate) fn record_migrate(
+        &mut self,
+        contract: &Account,
+        code_id: CosmwasmCodeId,
+        height: u64,
+        message: &[u8],
+    ) {
+        if let Some(record) = self.records.get_mut(contract) {
+            record.history.push(CodeHistoryEntry {
+                operation: CodeHistoryOperation::Migrate,
+                code_id,
+                updated: height,
+                message: Binary::from(message),
+            });
+        }
+    }
+}
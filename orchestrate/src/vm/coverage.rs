@@ -0,0 +1,187 @@
+use super::VmError;
+use alloc::collections::BTreeMap;
+use core::{fmt::Write, num::NonZeroU64};
+use cosmwasm_vm::system::CosmwasmCodeId;
+use cosmwasm_vm_wasmi::{
+    coverage::{custom_sections, instrument_coverage, CoverageMap},
+    WasmiVMError,
+};
+use gimli::{EndianSlice, LittleEndian, SectionId};
+use std::path::PathBuf;
+
+/// Hits of each line, by source file.
+pub type LineCoverage = BTreeMap<String, BTreeMap<u64, u64>>;
+
+type DwarfReader<'a> = EndianSlice<'a, LittleEndian>;
+
+/// Basic-block coverage of the codes, see [`super::State::enable_coverage`].
+#[derive(Default, Clone, Debug)]
+pub struct Coverage {
+    codes: BTreeMap<CosmwasmCodeId, CodeCoverage>,
+}
+
+#[derive(Clone, Debug)]
+struct CodeCoverage {
+    /// Checksum of `code`, a code replaced under the same id being instrumented again.
+    checksum: Vec<u8>,
+    /// The code as uploaded, holding the DWARF sections.
+    code: Vec<u8>,
+    /// The code executed instead, with the counters injected.
+    instrumented: Vec<u8>,
+    map: CoverageMap,
+    hits: Vec<u64>,
+}
+
+impl Coverage {
+    /// Inject the counters in an uploaded code, returning the code to execute instead. A code is
+    /// only instrumented once, its counters being kept across calls until another code is stored
+    /// under its id.
+    pub(crate) fn instrument(
+        &mut self,
+        code_id: CosmwasmCodeId,
+        checksum: &[u8],
+        code: &[u8],
+    ) -> Result<Vec<u8>, VmError> {
+        match self.codes.get(&code_id) {
+            Some(coverage) if coverage.checksum == checksum => {
+                return Ok(coverage.instrumented.clone())
+            }
+            _ => {}
+        }
+        let (instrumented, map) = instrument_coverage(code).map_err(WasmiVMError::from)?;
+        self.codes.insert(
+            code_id,
+            CodeCoverage {
+                checksum: checksum.into(),
+                code: code.into(),
+                instrumented: instrumented.clone(),
+                hits: vec![0; map.offsets.len()],
+                map,
+            },
+        );
+        Ok(instrumented)
+    }
+
+    /// Drop the coverage of the codes no longer stored under their id, such as the ones uploaded
+    /// by a rolled back transaction.
+    pub(crate) fn retain(&mut self, codes: &BTreeMap<CosmwasmCodeId, (Vec<u8>, Vec<u8>)>) {
+        self.codes.retain(|code_id, coverage| {
+            codes
+                .get(code_id)
+                .map_or(false, |(checksum, _)| *checksum == coverage.checksum)
+        });
+    }
+
+    pub(crate) fn counters(&mut self, code_id: CosmwasmCodeId) -> Option<&mut [u64]> {
+        self.codes
+            .get_mut(&code_id)
+            .map(|code| code.hits.as_mut_slice())
+    }
+
+    /// Hits of the basic blocks of a code, indexed as [`CoverageMap::offsets`].
+    #[must_use]
+    pub fn block_hits(&self, code_id: CosmwasmCodeId) -> Option<&[u64]> {
+        self.codes.get(&code_id).map(|code| code.hits.as_slice())
+    }
+
+    /// Map the counters back to source lines using the DWARF sections of the codes, only codes
+    /// built with debug information contribute.
+    ///
+    /// A line spanning several blocks gets the hits of the most executed one, the hits of a line
+    /// shared by several codes are summed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the debug information of a code is malformed.
+    pub fn lines(&self) -> Result<LineCoverage, VmError> {
+        let mut lines = LineCoverage::new();
+        for code in self.codes.values() {
+            for (file, code_lines) in code.lines()? {
+                let file_lines = lines.entry(file).or_default();
+                for (line, hits) in code_lines {
+                    *file_lines.entry(line).or_default() += hits;
+                }
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Export the coverage as an lcov tracefile.
+    ///
+    /// # Errors
+    ///
+    /// See [`Coverage::lines`].
+    pub fn lcov(&self) -> Result<String, VmError> {
+        let mut output = String::new();
+        for (file, lines) in self.lines()? {
+            let _ = writeln!(output, "SF:{file}");
+            for (line, hits) in &lines {
+                let _ = writeln!(output, "DA:{line},{hits}");
+            }
+            let _ = writeln!(output, "LF:{}", lines.len());
+            let _ = writeln!(
+                output,
+                "LH:{}",
+                lines.values().filter(|hits| **hits > 0).count()
+            );
+            let _ = writeln!(output, "end_of_record");
+        }
+        Ok(output)
+    }
+}
+
+impl CodeCoverage {
+    fn lines(&self) -> Result<LineCoverage, VmError> {
+        let sections = custom_sections(&self.code).map_err(WasmiVMError::from)?;
+        let dwarf = gimli::Dwarf::load(|id: SectionId| {
+            Ok::<_, gimli::Error>(DwarfReader::new(
+                sections
+                    .iter()
+                    .find(|(name, _)| *name == id.name().as_bytes())
+                    .map_or(&[][..], |(_, payload)| *payload),
+                LittleEndian,
+            ))
+        })?;
+        let mut lines = LineCoverage::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                let (Some(line), Some(file)) =
+                    (row.line().map(NonZeroU64::get), row.file(header))
+                else {
+                    continue;
+                };
+                let Some(block) = u32::try_from(row.address())
+                    .ok()
+                    .and_then(|address| self.map.block_at(address))
+                else {
+                    continue;
+                };
+                let mut path = PathBuf::new();
+                if let Some(comp_dir) = &unit.comp_dir {
+                    path.push(&*comp_dir.to_string_lossy());
+                }
+                if let Some(directory) = file.directory(header) {
+                    path.push(&*dwarf.attr_string(&unit, directory)?.to_string_lossy());
+                }
+                path.push(
+                    &*dwarf
+                        .attr_string(&unit, file.path_name())?
+                        .to_string_lossy(),
+                );
+                let hits = lines
+                    .entry(path.to_string_lossy().into())
+                    .or_default()
+                    .entry(line)
+                    .or_default();
+                *hits = (*hits).max(self.hits[block]);
+            }
+        }
+        Ok(lines)
+    }
+}
@@ -0,0 +1,268 @@
+use alloc::vec::Vec;
+use ark_bls12_381::{g1, g2, Bls12_381, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_ec::{
+    hashing::{
+        curve_maps::wb::WBMap, map_to_curve_hasher::MapToCurveBasedHasher, HashToCurve as _,
+    },
+    pairing::Pairing,
+    short_weierstrass::Projective,
+    CurveGroup,
+};
+use ark_ff::{field_hashers::DefaultFieldHasher, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use cosmwasm_vm::{
+    executor::constants::{BLS12_381_G1_POINT_LENGTH, BLS12_381_G2_POINT_LENGTH},
+    vm::CryptoError,
+};
+use ecdsa::RecoveryId;
+use p256::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+type HashToCurve<CurveConfig> =
+    MapToCurveBasedHasher<Projective<CurveConfig>, DefaultFieldHasher<Sha256>, WBMap<CurveConfig>>;
+
+/// Identifier of the sha256 hash function for the hash-to-curve operations.
+const HASH_FUNCTION_SHA256: u32 = 0;
+
+pub fn secp256r1_verify(
+    message_hash: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<bool, CryptoError> {
+    if message_hash.len() != 32 {
+        return Err(CryptoError::InvalidHashFormat);
+    }
+    let mut signature =
+        Signature::from_slice(signature).map_err(|_| CryptoError::InvalidSignatureFormat)?;
+    if let Some(normalized) = signature.normalize_s() {
+        signature = normalized;
+    }
+    let public_key =
+        VerifyingKey::from_sec1_bytes(public_key).map_err(|_| CryptoError::InvalidPubkeyFormat)?;
+    Ok(public_key.verify_prehash(message_hash, &signature).is_ok())
+}
+
+pub fn secp256r1_recover_pubkey(
+    message_hash: &[u8],
+    signature: &[u8],
+    recovery_param: u8,
+) -> Result<Vec<u8>, CryptoError> {
+    if message_hash.len() != 32 {
+        return Err(CryptoError::InvalidHashFormat);
+    }
+    let recovery_id =
+        RecoveryId::from_byte(recovery_param).ok_or(CryptoError::InvalidRecoveryParam)?;
+    let signature =
+        Signature::from_slice(signature).map_err(|_| CryptoError::InvalidSignatureFormat)?;
+    let public_key = VerifyingKey::recover_from_prehash(message_hash, &signature, recovery_id)
+        .map_err(|_| CryptoError::Generic)?;
+    Ok(public_key.to_encoded_point(false).as_bytes().into())
+}
+
+pub fn bls12_381_aggregate_g1(g1s: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if g1s.is_empty() {
+        return Err(CryptoError::AggregationEmpty);
+    }
+    if g1s.len() % BLS12_381_G1_POINT_LENGTH != 0 {
+        return Err(CryptoError::AggregationNotMultiple);
+    }
+    let sum = g1s
+        .chunks_exact(BLS12_381_G1_POINT_LENGTH)
+        .try_fold(G1Projective::zero(), |sum, point| {
+            G1Affine::deserialize_compressed(point).map(|point| sum + point)
+        })
+        .map_err(|_| CryptoError::InvalidPoint)?;
+    compress(&sum.into_affine())
+}
+
+pub fn bls12_381_aggregate_g2(g2s: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if g2s.is_empty() {
+        return Err(CryptoError::AggregationEmpty);
+    }
+    if g2s.len() % BLS12_381_G2_POINT_LENGTH != 0 {
+        return Err(CryptoError::AggregationNotMultiple);
+    }
+    let sum = g2s
+        .chunks_exact(BLS12_381_G2_POINT_LENGTH)
+        .try_fold(G2Projective::zero(), |sum, point| {
+            G2Affine::deserialize_compressed(point).map(|point| sum + point)
+        })
+        .map_err(|_| CryptoError::InvalidPoint)?;
+    compress(&sum.into_affine())
+}
+
+/// Checks `e(p_1, q_1) × … × e(p_n, q_n) = e(r, s)` by verifying that
+/// `e(p_1, q_1) × … × e(p_n, q_n) × e(-r, s)` is the identity.
+pub fn bls12_381_pairing_equality(
+    ps: &[u8],
+    qs: &[u8],
+    r: &[u8],
+    s: &[u8],
+) -> Result<bool, CryptoError> {
+    if ps.len() % BLS12_381_G1_POINT_LENGTH != 0 {
+        return Err(CryptoError::PairingEqualityNotMultipleG1);
+    }
+    if qs.len() % BLS12_381_G2_POINT_LENGTH != 0 {
+        return Err(CryptoError::PairingEqualityNotMultipleG2);
+    }
+    if ps.len() / BLS12_381_G1_POINT_LENGTH != qs.len() / BLS12_381_G2_POINT_LENGTH {
+        return Err(CryptoError::PairingEqualityUnequalPointAmount);
+    }
+    if r.len() != BLS12_381_G1_POINT_LENGTH || s.len() != BLS12_381_G2_POINT_LENGTH {
+        return Err(CryptoError::InvalidPoint);
+    }
+    let r = G1Affine::deserialize_compressed(r).map_err(|_| CryptoError::InvalidPoint)?;
+    let s = G2Affine::deserialize_compressed(s).map_err(|_| CryptoError::InvalidPoint)?;
+    let ps = ps
+        .chunks_exact(BLS12_381_G1_POINT_LENGTH)
+        .map(G1Affine::deserialize_compressed)
+        .chain([Ok(-r)])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| CryptoError::InvalidPoint)?;
+    let qs = qs
+        .chunks_exact(BLS12_381_G2_POINT_LENGTH)
+        .map(G2Affine::deserialize_compressed)
+        .chain([Ok(s)])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| CryptoError::InvalidPoint)?;
+    Ok(Bls12_381::multi_pairing(ps, qs).is_zero())
+}
+
+pub fn bls12_381_hash_to_g1(
+    hash_function: u32,
+    message: &[u8],
+    dst: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    if hash_function != HASH_FUNCTION_SHA256 {
+        return Err(CryptoError::UnknownHashFunction);
+    }
+    let point = HashToCurve::<g1::Config>::new(dst)
+        .and_then(|hasher| hasher.hash(message))
+        .map_err(|_| CryptoError::Generic)?;
+    compress(&point)
+}
+
+pub fn bls12_381_hash_to_g2(
+    hash_function: u32,
+    message: &[u8],
+    dst: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    if hash_function != HASH_FUNCTION_SHA256 {
+        return Err(CryptoError::UnknownHashFunction);
+    }
+    let point = HashToCurve::<g2::Config>::new(dst)
+        .and_then(|hasher| hasher.hash(message))
+        .map_err(|_| CryptoError::Generic)?;
+    compress(&point)
+}
+
+pub fn keccak256(message: &[u8]) -> Vec<u8> {
+    Keccak256::digest(message).to_vec()
+}
+
+fn compress(point: &impl CanonicalSerialize) -> Result<Vec<u8>, CryptoError> {
+    let mut serialized = Vec::with_capacity(point.compressed_size());
+    point
+        .serialize_compressed(&mut serialized)
+        .map_err(|_| CryptoError::Generic)?;
+    Ok(serialized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_ec::AffineRepr;
+    use p256::ecdsa::{signature::hazmat::PrehashSigner, SigningKey};
+
+    #[test]
+    fn secp256r1_roundtrip() {
+        let signing_key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let message_hash = Sha256::digest(b"hello world");
+        let (signature, recovery_id): (Signature, RecoveryId) =
+            signing_key.sign_prehash(&message_hash).unwrap();
+        let public_key = signing_key
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec();
+
+        assert_eq!(
+            secp256r1_verify(&message_hash, &signature.to_bytes(), &public_key),
+            Ok(true)
+        );
+        assert_eq!(
+            secp256r1_verify(&[0; 32], &signature.to_bytes(), &public_key),
+            Ok(false)
+        );
+        assert_eq!(
+            secp256r1_verify(&[0; 31], &signature.to_bytes(), &public_key),
+            Err(CryptoError::InvalidHashFormat)
+        );
+        assert_eq!(
+            secp256r1_verify(&message_hash, &signature.to_bytes(), &public_key[1..]),
+            Err(CryptoError::InvalidPubkeyFormat)
+        );
+        assert_eq!(
+            secp256r1_recover_pubkey(&message_hash, &signature.to_bytes(), recovery_id.to_byte()),
+            Ok(public_key)
+        );
+    }
+
+    #[test]
+    fn bls12_381_generators() {
+        let g1 = compress(&G1Affine::generator()).unwrap();
+        let g2 = compress(&G2Affine::generator()).unwrap();
+        let two_g1 =
+            compress(&(G1Affine::generator() + G1Affine::generator()).into_affine()).unwrap();
+
+        // e(g1, g2) × e(g1, g2) = e(2 × g1, g2)
+        assert_eq!(
+            bls12_381_aggregate_g1(&[g1.clone(), g1.clone()].concat()),
+            Ok(two_g1.clone())
+        );
+        assert!(bls12_381_pairing_equality(
+            &[g1.clone(), g1.clone()].concat(),
+            &[g2.clone(), g2.clone()].concat(),
+            &two_g1,
+            &g2
+        )
+        .unwrap());
+        assert_eq!(
+            bls12_381_pairing_equality(&g1, &g2, &two_g1, &g2),
+            Ok(false)
+        );
+        assert_eq!(
+            bls12_381_pairing_equality(&g1, &[], &two_g1, &g2),
+            Err(CryptoError::PairingEqualityUnequalPointAmount)
+        );
+        assert_eq!(
+            bls12_381_aggregate_g1(&[]),
+            Err(CryptoError::AggregationEmpty)
+        );
+        assert_eq!(
+            bls12_381_aggregate_g1(&g1[1..]),
+            Err(CryptoError::AggregationNotMultiple)
+        );
+        assert_eq!(
+            bls12_381_hash_to_g1(1, b"message", b"dst"),
+            Err(CryptoError::UnknownHashFunction)
+        );
+        assert_eq!(
+            bls12_381_hash_to_g2(HASH_FUNCTION_SHA256, b"message", b"dst").map(|point| point.len()),
+            Ok(BLS12_381_G2_POINT_LENGTH)
+        );
+    }
+
+    #[test]
+    fn keccak256_empty() {
+        assert_eq!(
+            keccak256(&[]),
+            [
+                0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7,
+                0x03, 0xc0, 0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04,
+                0x5d, 0x85, 0xa4, 0x70
+            ]
+        );
+    }
+}
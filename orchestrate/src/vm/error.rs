@@ -4,7 +4,7 @@ use cosmwasm_vm::{
     memory::{MemoryReadError, MemoryWriteError},
     system::{CosmwasmCodeId, SystemError},
 };
-use cosmwasm_vm_wasmi::WasmiVMError;
+use cosmwasm_vm_wasmi::{diagnostics::TrapDiagnostics, WasmiVMError};
 use wasmi::CanResume;
 
 use super::bank;
@@ -35,6 +35,15 @@ pub enum VmError {
     EncodingFailure,
     DecodingFailure,
     NotAuthorized,
+    InvalidDebugInfo(gimli::Error),
+    /// The message at the given index of a transaction failed.
+    MessageFailed(usize, Box<VmError>),
+    Io(std::io::Error),
+    UnsupportedSnapshotVersion(u32),
+    /// A lookup on the remote fork failed.
+    Remote(crate::error::Error),
+    /// A contract trapped or aborted, see [`super::State::enable_diagnostics`].
+    Trap(TrapDiagnostics),
 }
 
 impl From<wasmi::Error> for VmError {
@@ -45,7 +54,10 @@ impl From<wasmi::Error> for VmError {
 
 impl From<WasmiVMError> for VmError {
     fn from(e: WasmiVMError) -> Self {
-        VmError::VMError(e)
+        match e {
+            WasmiVMError::Trap(trap) => VmError::Trap(trap),
+            e => VmError::VMError(e),
+        }
     }
 }
 
@@ -73,6 +85,12 @@ impl From<MemoryWriteError> for VmError {
     }
 }
 
+impl From<gimli::Error> for VmError {
+    fn from(e: gimli::Error) -> Self {
+        Self::InvalidDebugInfo(e)
+    }
+}
+
 impl From<bank::Error> for VmError {
     fn from(e: bank::Error) -> Self {
         Self::BankError(e)
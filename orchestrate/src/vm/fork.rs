@@ -0,0 +1,125 @@
+use super::{Account, Db, VmError};
+use crate::fetcher::RemoteFork;
+use cosmwasm_std::Coin;
+
+impl<CH> Db<CH> {
+    /// Fork the chain served by `remote`: the storage of the contracts and the balances missing
+    /// locally are fetched on their first lookup and cached, local writes shadowing the remote
+    /// values. The contracts themselves must be installed, see [`RemoteFork::contract`].
+    pub fn fork(&mut self, remote: RemoteFork) {
+        self.remote = Some(remote);
+    }
+
+    /// Read `key` in the storage of `contract`, falling through to the remote fork on a miss.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the value can't be fetched from the remote fork.
+    pub fn storage_read(
+        &mut self,
+        contract: &Account,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, VmError> {
+        let Some(remote) = &self.remote else {
+            return Ok(self
+                .storage
+                .get(contract)
+                .and_then(|storage| storage.data.get(key))
+                .cloned());
+        };
+        let storage = self.storage.entry(contract.clone()).or_default();
+        if let Some(value) = storage.data.get(key) {
+            return Ok(Some(value.clone()));
+        }
+        if storage.complete || storage.resolved.contains(key) {
+            return Ok(None);
+        }
+        let value = remote
+            .raw(contract.0.as_str(), key)
+            .map_err(VmError::Remote)?;
+        storage.resolved.insert(key.to_vec());
+        if let Some(value) = &value {
+            storage.data.insert(key.to_vec(), value.clone());
+        }
+        Ok(value)
+    }
+
+    /// Mark the storage of a contract instantiated locally as complete, the remote fork knowing
+    /// nothing about it.
+    pub(crate) fn storage_init(&mut self, contract: &Account) {
+        self.storage.entry(contract.clone()).or_default().complete = true;
+    }
+
+    /// Remove `key` from the storage of `contract`, shadowing its remote value if any.
+    pub fn storage_remove(&mut self, contract: &Account, key: &[u8]) {
+        if self.remote.is_some() {
+            let storage = self.storage.entry(contract.clone()).or_default();
+            storage.data.remove(key);
+            storage.resolved.insert(key.to_vec());
+        } else if let Some(storage) = self.storage.get_mut(contract) {
+            storage.data.remove(key);
+        }
+    }
+
+    /// Merge the whole remote storage of `contract`, required before iterating over it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the storage can't be fetched from the remote fork.
+    pub fn storage_load(&mut self, contract: &Account) -> Result<(), VmError> {
+        let Some(remote) = &self.remote else {
+            return Ok(());
+        };
+        let storage = self.storage.entry(contract.clone()).or_default();
+        if storage.complete {
+            return Ok(());
+        }
+        for model in remote.state(contract.0.as_str()).map_err(VmError::Remote)? {
+            if !storage.resolved.contains(&model.key) {
+                storage.data.entry(model.key).or_insert(model.value.0);
+            }
+        }
+        storage.complete = true;
+        Ok(())
+    }
+
+    /// Credit the remote balances of `account` on its first lookup.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the balances can't be fetched from the remote fork.
+    pub fn balances_load(&mut self, account: &Account) -> Result<(), VmError> {
+        let Some(remote) = &self.remote else {
+            return Ok(());
+        };
+        if self.bank.fetched.contains(account) {
+            return Ok(());
+        }
+        let coins = remote
+            .balances(account.0.as_str())
+            .map_err(VmError::Remote)?;
+        let balances = self.bank.balances.entry(account.clone()).or_default();
+        for Coin { denom, amount } in coins {
+            *balances.entry(denom.clone()).or_default() += amount.u128();
+            *self.bank.supply.entry(denom).or_default() += amount.u128();
+        }
+        self.bank.fetched.insert(account.clone());
+        Ok(())
+    }
+
+    /// Transfer `funds` from `from` to `to`, their remote balances being loaded first.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a balance can't be fetched or `from` doesn't have enough funds.
+    pub fn transfer(
+        &mut self,
+        from: &Account,
+        to: &Account,
+        funds: &[Coin],
+    ) -> Result<(), VmError> {
+        self.balances_load(from)?;
+        self.balances_load(to)?;
+        self.bank.transfer(from, to, funds).map_err(Into::into)
+    }
+}
@@ -1,23 +1,35 @@
 mod account;
 mod address;
 mod bank;
+mod code;
+mod contract;
+mod coverage;
+mod crypto;
 mod error;
+mod fork;
+mod snapshot;
 mod state;
 
 pub use account::*;
 pub use address::*;
+pub use code::*;
+pub use contract::*;
+pub use coverage::*;
 pub use error::*;
+pub use snapshot::SNAPSHOT_VERSION;
 pub use state::*;
 
 use super::ExecutionType;
-use alloc::collections::BTreeMap;
+use crate::fetcher::RemoteFork;
+use alloc::collections::{BTreeMap, BTreeSet};
 use bank::Bank;
-use core::{fmt::Debug, num::NonZeroU32};
+use core::{fmt::Debug, num::NonZeroU32, ops::Bound};
 use cosmwasm_std::{
     Binary, Coin, ContractInfo, ContractInfoResponse, Env, Event, IbcTimeout, MessageInfo, Order,
     Reply, SystemResult,
 };
 use cosmwasm_vm::{
+    codec::{Codec, CosmwasmCodec},
     executor::{
         cosmwasm_call, CosmwasmQueryResult, ExecuteCall, InstantiateCall, MigrateCall, QueryCall,
         QueryResult, ReplyCall,
@@ -26,14 +38,17 @@ use cosmwasm_vm::{
     memory::{Pointable, ReadWriteMemory, ReadableMemory, WritableMemory},
     system::{cosmwasm_system_run, CosmwasmContractMeta, SystemError},
     transaction::Transactional,
-    vm::{VMBase, VmErrorOf, VmGas, VmGasCheckpoint},
+    vm::{CryptoError, VMBase, VmCodecOf, VmErrorOf, VmGas, VmGasCheckpoint},
 };
 use cosmwasm_vm_wasmi::{
-    host_functions, new_wasmi_vm, WasmiHostFunction, WasmiHostFunctionIndex, WasmiImportResolver,
-    WasmiInput, WasmiModule, WasmiModuleExecutor, WasmiOutput, WasmiVM, WasmiVMError,
+    allocation::AllocationTracker, crypto::crypto_error, diagnostics::Diagnostics, host_functions,
+    WasmiHostFunction, WasmiHostFunctionIndex, WasmiImportResolver, WasmiInput, WasmiModule,
+    WasmiModuleExecutor, WasmiOutput, WasmiVM, WasmiVMError,
 };
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::time::Instant;
 use wasm_instrument::gas_metering::Rules;
+use wasmi::memory_units::Bytes;
 
 #[derive(Default, Clone, PartialEq, Eq, Debug)]
 pub struct Gas {
@@ -131,32 +146,77 @@ impl CustomHandler for () {
     }
 }
 
-#[derive(Default, Clone, Debug)]
+/// Iterator over the storage of a contract, created by `db_scan`. It is lazy: each step looks up
+/// the entry following the last returned key within `[start, end)`, in `order`.
+#[derive(Clone, Debug)]
 pub struct Iter {
-    pub data: Vec<(Vec<u8>, Vec<u8>)>,
-    pub position: usize,
+    pub start: Option<Vec<u8>>,
+    pub end: Option<Vec<u8>>,
+    pub order: Order,
+    /// Last key returned, `None` if the iteration hasn't started.
+    pub last: Option<Vec<u8>>,
 }
 
+impl Iter {
+    /// Step to the next entry of `data`, if any.
+    pub fn next(&mut self, data: &BTreeMap<Vec<u8>, Vec<u8>>) -> Option<(Vec<u8>, Vec<u8>)> {
+        let mut lower = self
+            .start
+            .as_deref()
+            .map_or(Bound::Unbounded, Bound::Included);
+        let mut upper = self
+            .end
+            .as_deref()
+            .map_or(Bound::Unbounded, Bound::Excluded);
+        // Resume right after the last returned key.
+        if let Some(last) = self.last.as_deref() {
+            match self.order {
+                Order::Ascending => lower = Bound::Excluded(last),
+                Order::Descending => upper = Bound::Excluded(last),
+            }
+        }
+        // `BTreeMap::range` panics on an inverted range.
+        if let (Bound::Included(l) | Bound::Excluded(l), Bound::Included(u) | Bound::Excluded(u)) =
+            (lower, upper)
+        {
+            if l >= u {
+                return None;
+            }
+        }
+        let mut range = data.range::<[u8], _>((lower, upper));
+        let (key, value) = match self.order {
+            Order::Ascending => range.next(),
+            Order::Descending => range.next_back(),
+        }?;
+        self.last = Some(key.clone());
+        Some((key.clone(), value.clone()))
+    }
+}
+
+/// Storage of a contract, only its `data` being serialized.
 #[derive(Default, Clone, Debug)]
 pub struct Storage {
     pub data: BTreeMap<Vec<u8>, Vec<u8>>,
-    pub iterators: BTreeMap<u32, Iter>,
+    /// Keys looked up on the remote fork or written locally, which are never fetched again.
+    pub resolved: BTreeSet<Vec<u8>>,
+    /// Whether the whole remote storage has been merged in `data`.
+    pub complete: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IbcPacket {
     pub data: Binary,
     pub timeout: IbcTimeout,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IbcTransfer {
     pub to_address: String,
     pub amount: Coin,
     pub timeout: IbcTimeout,
 }
 
-#[derive(Default, Clone, Debug, PartialEq, Eq)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IbcState {
     pub packets: Vec<IbcPacket>,
     pub transfers: Vec<IbcTransfer>,
@@ -173,13 +233,19 @@ impl IbcState {
 
 pub type IbcChannelId = String;
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "CH: Serialize", deserialize = "CH: DeserializeOwned"))]
 pub struct Db<CH> {
     pub ibc: BTreeMap<IbcChannelId, IbcState>,
     pub contracts: BTreeMap<Account, CosmwasmContractMeta<Account>>,
+    /// Creator, creation height, IBC port and code history of the contracts.
+    pub records: BTreeMap<Account, ContractRecord>,
     pub storage: BTreeMap<Account, Storage>,
     pub bank: Bank,
     pub custom_handler: CH,
+    /// Chain from which the missing storage and balances are lazily fetched, see [`Db::fork`].
+    #[serde(skip)]
+    pub remote: Option<RemoteFork>,
 }
 
 impl<CH: CustomHandler> Debug for Db<CH> {
@@ -187,6 +253,7 @@ impl<CH: CustomHandler> Debug for Db<CH> {
         f.debug_struct("Db")
             .field("ibc", &self.ibc)
             .field("contracts", &self.contracts)
+            .field("records", &self.records)
             .field("bank", &self.bank)
             .finish()
     }
@@ -198,6 +265,30 @@ pub struct Context<'a, CH: CustomHandler, AH: AddressHandler> {
     pub env: Env,
     pub info: MessageInfo,
     pub state: &'a mut State<CH, AH>,
+    /// Iterators of this call, the id of an iterator being its index plus one. They are dropped
+    /// along with the call.
+    pub iterators: Vec<Iter>,
+    /// Shadow call stack of the module, see [`State::enable_diagnostics`].
+    pub diagnostics: Option<Diagnostics>,
+    /// Regions exchanged with the module, reported to [`State::allocations`] once the call is
+    /// dropped.
+    pub allocation_tracker: Option<AllocationTracker>,
+}
+
+impl<'a, CH: CustomHandler, AH: AddressHandler> Drop for Context<'a, CH, AH> {
+    fn drop(&mut self) {
+        if let (Some(tracker), Some(allocations)) =
+            (&mut self.allocation_tracker, &mut self.state.allocations)
+        {
+            let contract = Account::unchecked(self.env.contract.address.as_str());
+            allocations.extend(
+                tracker
+                    .finish()
+                    .into_iter()
+                    .map(|report| (contract.clone(), report)),
+            );
+        }
+    }
 }
 
 impl<'a, CH: CustomHandler, AH: AddressHandler> WasmiModuleExecutor for Context<'a, CH, AH> {
@@ -207,6 +298,22 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> WasmiModuleExecutor for Context<
     fn host_function(&self, index: WasmiHostFunctionIndex) -> Option<&WasmiHostFunction<Self>> {
         self.host_functions.get(&index)
     }
+    fn deadline_exceeded(&self) -> bool {
+        self.state
+            .deadline
+            .map_or(false, |deadline| Instant::now() >= deadline)
+    }
+    fn allocation_tracker(&mut self) -> Option<&mut AllocationTracker> {
+        self.allocation_tracker.as_mut()
+    }
+    fn diagnostics(&mut self) -> Option<&mut Diagnostics> {
+        self.diagnostics.as_mut()
+    }
+    fn coverage_counters(&mut self) -> Option<&mut [u64]> {
+        let contract = Account::try_from(self.env.contract.address.clone()).ok()?;
+        let code_id = self.state.db.contracts.get(&contract)?.code_id;
+        self.state.coverage.as_mut()?.counters(code_id)
+    }
 }
 
 impl<'a, CH: CustomHandler, AH: AddressHandler> Pointable for Context<'a, CH, AH> {
@@ -221,6 +328,9 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> ReadableMemory for Context<'a, C
             .get_into(offset, buffer)
             .map_err(|_| WasmiVMError::LowLevelMemoryReadError.into())
     }
+    fn size(&self) -> usize {
+        Bytes::from(self.executing_module.memory.current_size()).0
+    }
 }
 
 impl<'a, CH: CustomHandler, AH: AddressHandler> WritableMemory for Context<'a, CH, AH> {
@@ -254,20 +364,15 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> Context<'a, CH, AH> {
                 .get(&address)
                 .cloned()
                 .ok_or_else(|| VmError::ContractNotFound(address.clone()))?;
-        let code = self
-            .state
-            .codes
-            .get(&code_id)
-            .ok_or(VmError::CodeNotFound(code_id))
-            .cloned()?;
+        let (module, diagnostics) = load_module(self.state, code_id)?;
         let host_functions_definitions =
             WasmiImportResolver(host_functions::definitions::<Context<CH, AH>>());
-        let module = new_wasmi_vm(&host_functions_definitions, &code.1)?;
         let mut sub_vm: WasmiVM<Context<CH, AH>> = WasmiVM(Context {
             host_functions: host_functions_definitions
                 .0
                 .into_iter()
                 .flat_map(|(_, modules)| modules.into_values())
+                .map(|(index, function, _)| (index, function))
                 .collect(),
             executing_module: module,
             env: Env {
@@ -281,7 +386,14 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> Context<'a, CH, AH> {
                 sender: self.env.contract.address.clone(),
                 funds,
             },
+            allocation_tracker: self
+                .state
+                .allocations
+                .as_ref()
+                .map(|_| AllocationTracker::new()),
             state: self.state,
+            iterators: Vec::new(),
+            diagnostics,
         });
         Ok(f(&mut sub_vm))
     }
@@ -297,6 +409,7 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
     type CanonicalAddress = CanonicalAccount;
     type StorageKey = Vec<u8>;
     type StorageValue = Vec<u8>;
+    type Codec = CosmwasmCodec;
     type Error = VmError;
 
     fn running_contract_meta(&mut self) -> Result<Self::ContractMeta, Self::Error> {
@@ -373,17 +486,30 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         message: &[u8],
         event_handler: &mut dyn FnMut(Event),
     ) -> Result<(Self::Address, Option<Binary>), Self::Error> {
-        let (_, code_hash) = &self
+        let creator = self.env.contract.address.clone().try_into()?;
+        self.state
+            .ensure_instantiate(&creator, contract_meta.code_id)?;
+        let (_, code) = &self
             .state
             .codes
             .get(&contract_meta.code_id)
             .ok_or(VmError::CodeNotFound(contract_meta.code_id))?;
-        let address = Account::generate::<AH>(code_hash, message)?;
+        let address = Account::generate::<AH>(code, message)?;
+        let record = ContractRecord::new(
+            &address,
+            creator,
+            contract_meta.code_id,
+            code,
+            self.env.block.height,
+            message,
+        )?;
 
         self.state
             .db
             .contracts
             .insert(address.clone(), contract_meta);
+        self.state.db.records.insert(address.clone(), record);
+        self.state.db.storage_init(&address);
 
         self.load_subvm(address.clone(), funds, |sub_vm| {
             cosmwasm_system_run::<InstantiateCall<Self::MessageCustom>, _>(
@@ -401,6 +527,11 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         message: &[u8],
         event_handler: &mut dyn FnMut(Event),
     ) -> Result<Option<Binary>, Self::Error> {
+        // The code id has already been updated by the system.
+        let CosmwasmContractMeta { code_id, .. } = self.contract_meta(address.clone())?;
+        self.state
+            .db
+            .record_migrate(&address, code_id, self.env.block.height, message);
         self.load_subvm(address, vec![], |sub_vm| {
             cosmwasm_system_run::<MigrateCall<Self::MessageCustom>, _>(
                 sub_vm,
@@ -421,7 +552,7 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
             |sub_vm| {
                 cosmwasm_system_run::<ReplyCall<Self::MessageCustom>, _>(
                     sub_vm,
-                    &serde_json::to_vec(&message).map_err(|_| VmError::CannotDeserialize)?,
+                    &VmCodecOf::<Self>::encode(&message).map_err(|_| VmError::CannotSerialize)?,
                     event_handler,
                 )
             },
@@ -448,15 +579,7 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         address: Self::Address,
         key: Self::StorageKey,
     ) -> Result<Option<Self::StorageValue>, Self::Error> {
-        Ok(self
-            .state
-            .db
-            .storage
-            .get(&address)
-            .unwrap_or(&Storage::default())
-            .data
-            .get(&key)
-            .cloned())
+        self.state.db.storage_read(&address, &key)
     }
 
     fn transfer_from(
@@ -466,11 +589,7 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         funds: &[Coin],
     ) -> Result<(), Self::Error> {
         log::debug!("Transfer: {:?} -> {:?}\n{:?}", from, to, funds);
-        self.state
-            .db
-            .bank
-            .transfer(from, to, funds)
-            .map_err(Into::into)
+        self.state.db.transfer(from, to, funds)
     }
 
     fn transfer(&mut self, to: &Self::Address, funds: &[Coin]) -> Result<(), Self::Error> {
@@ -480,15 +599,14 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
 
     fn burn(&mut self, funds: &[Coin]) -> Result<(), Self::Error> {
         log::debug!("Burn: {:?}\n{:?}", self.env.contract.address, funds);
-        self.state
-            .db
-            .bank
-            .burn(&self.env.contract.address.clone().try_into()?, funds)
-            .map_err(Into::into)
+        let account = self.env.contract.address.clone().try_into()?;
+        self.state.db.balances_load(&account)?;
+        self.state.db.bank.burn(&account, funds).map_err(Into::into)
     }
 
     fn balance(&mut self, account: &Self::Address, denom: String) -> Result<Coin, Self::Error> {
         log::debug!("Query balance.");
+        self.state.db.balances_load(account)?;
         Ok(Coin::new(
             self.state.db.bank.balance(account, &denom),
             denom,
@@ -497,11 +615,15 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
 
     fn all_balance(&mut self, account: &Self::Address) -> Result<Vec<Coin>, Self::Error> {
         log::debug!("Query all balance.");
+        self.state.db.balances_load(account)?;
         Ok(self.state.db.bank.all_balances(account))
     }
 
-    fn query_info(&mut self, _: Self::Address) -> Result<ContractInfoResponse, Self::Error> {
-        Err(VmError::Unsupported)
+    fn query_info(&mut self, address: Self::Address) -> Result<ContractInfoResponse, Self::Error> {
+        self.state
+            .db
+            .contract_info(&address)
+            .ok_or(VmError::ContractNotFound(address))
     }
 
     fn debug(&mut self, message: Vec<u8>) -> Result<(), Self::Error> {
@@ -511,32 +633,24 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
 
     fn db_scan(
         &mut self,
-        _start: Option<Self::StorageKey>,
-        _end: Option<Self::StorageKey>,
-        _order: Order,
+        start: Option<Self::StorageKey>,
+        end: Option<Self::StorageKey>,
+        order: Order,
     ) -> Result<u32, Self::Error> {
         let contract_addr = self.env.contract.address.clone().try_into()?;
-        let mut empty = Storage::default();
-        let storage = self
-            .state
-            .db
-            .storage
-            .get_mut(&contract_addr)
-            .unwrap_or(&mut empty);
-
-        let data = storage.data.clone().into_iter().collect::<Vec<_>>();
+        self.state.db.storage_load(&contract_addr)?;
+        self.iterators.push(Iter {
+            start,
+            end,
+            order,
+            last: None,
+        });
         // Exceeding u32 size is fatal
-        let last_id: u32 = storage
+        Ok(self
             .iterators
             .len()
             .try_into()
-            .expect("Found more iterator IDs than supported");
-
-        let new_id = last_id + 1;
-        let iter = Iter { data, position: 0 };
-        storage.iterators.insert(new_id, iter);
-
-        Ok(new_id)
+            .expect("Found more iterator IDs than supported"))
     }
 
     fn db_next(
@@ -544,26 +658,19 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         iterator_id: u32,
     ) -> Result<(Self::StorageKey, Self::StorageValue), Self::Error> {
         let contract_addr = self.env.contract.address.clone().try_into()?;
-        let storage = self
+        let iterator = iterator_id
+            .checked_sub(1)
+            .and_then(|index| self.iterators.get_mut(usize::try_from(index).ok()?))
+            .ok_or(VmError::IteratorDoesNotExist)?;
+        let empty = BTreeMap::new();
+        let data = self
             .state
             .db
             .storage
-            .get_mut(&contract_addr)
-            .ok_or(VmError::IteratorDoesNotExist)?;
-
-        let iterator = storage
-            .iterators
-            .get_mut(&iterator_id)
-            .ok_or(VmError::IteratorDoesNotExist)?;
-
-        let position = iterator.position;
-        if iterator.data.len() > position {
-            iterator.position += 1;
-            Ok(iterator.data[position].clone())
-        } else {
-            // Empty data works like `None` in rust iterators
-            Ok((Vec::default(), Vec::default()))
-        }
+            .get(&contract_addr)
+            .map_or(&empty, |storage| &storage.data);
+        // Empty data works like `None` in rust iterators
+        Ok(iterator.next(data).unwrap_or_default())
     }
 
     fn secp256k1_verify(
@@ -571,9 +678,11 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         message_hash: &[u8],
         signature: &[u8],
         public_key: &[u8],
-    ) -> Result<bool, Self::Error> {
-        cosmwasm_crypto::secp256k1_verify(message_hash, signature, public_key)
-            .map_err(|_| VmError::CryptoError)
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Ok(
+            cosmwasm_crypto::secp256k1_verify(message_hash, signature, public_key)
+                .map_err(crypto_error),
+        )
     }
 
     fn secp256k1_recover_pubkey(
@@ -581,10 +690,10 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         message_hash: &[u8],
         signature: &[u8],
         recovery_param: u8,
-    ) -> Result<Result<Vec<u8>, ()>, Self::Error> {
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
         Ok(
             cosmwasm_crypto::secp256k1_recover_pubkey(message_hash, signature, recovery_param)
-                .map_err(|_| ()),
+                .map_err(crypto_error),
         )
     }
 
@@ -593,9 +702,8 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         message: &[u8],
         signature: &[u8],
         public_key: &[u8],
-    ) -> Result<bool, Self::Error> {
-        cosmwasm_crypto::ed25519_verify(message, signature, public_key)
-            .map_err(|_| VmError::CryptoError)
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Ok(cosmwasm_crypto::ed25519_verify(message, signature, public_key).map_err(crypto_error))
     }
 
     fn ed25519_batch_verify(
@@ -603,9 +711,83 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         messages: &[&[u8]],
         signatures: &[&[u8]],
         public_keys: &[&[u8]],
-    ) -> Result<bool, Self::Error> {
-        cosmwasm_crypto::ed25519_batch_verify(messages, signatures, public_keys)
-            .map_err(|_| VmError::CryptoError)
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Ok(
+            cosmwasm_crypto::ed25519_batch_verify(messages, signatures, public_keys)
+                .map_err(crypto_error),
+        )
+    }
+
+    fn secp256r1_verify(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Ok(crypto::secp256r1_verify(
+            message_hash,
+            signature,
+            public_key,
+        ))
+    }
+
+    fn secp256r1_recover_pubkey(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        recovery_param: u8,
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Ok(crypto::secp256r1_recover_pubkey(
+            message_hash,
+            signature,
+            recovery_param,
+        ))
+    }
+
+    fn bls12_381_aggregate_g1(
+        &mut self,
+        g1s: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Ok(crypto::bls12_381_aggregate_g1(g1s))
+    }
+
+    fn bls12_381_aggregate_g2(
+        &mut self,
+        g2s: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Ok(crypto::bls12_381_aggregate_g2(g2s))
+    }
+
+    fn bls12_381_pairing_equality(
+        &mut self,
+        ps: &[u8],
+        qs: &[u8],
+        r: &[u8],
+        s: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Ok(crypto::bls12_381_pairing_equality(ps, qs, r, s))
+    }
+
+    fn bls12_381_hash_to_g1(
+        &mut self,
+        hash_function: u32,
+        message: &[u8],
+        dst: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Ok(crypto::bls12_381_hash_to_g1(hash_function, message, dst))
+    }
+
+    fn bls12_381_hash_to_g2(
+        &mut self,
+        hash_function: u32,
+        message: &[u8],
+        dst: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Ok(crypto::bls12_381_hash_to_g2(hash_function, message, dst))
+    }
+
+    fn keccak256(&mut self, message: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        Ok(crypto::keccak256(message))
     }
 
     fn addr_validate(&mut self, input: &str) -> Result<Result<(), Self::Error>, Self::Error> {
@@ -637,16 +819,7 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
         key: Self::StorageKey,
     ) -> Result<Option<Self::StorageValue>, Self::Error> {
         let contract_addr = self.env.contract.address.clone().try_into()?;
-        let empty = Storage::default();
-        Ok(self
-            .state
-            .db
-            .storage
-            .get(&contract_addr)
-            .unwrap_or(&empty)
-            .data
-            .get(&key)
-            .cloned())
+        self.state.db.storage_read(&contract_addr, &key)
     }
 
     fn db_write(
@@ -667,11 +840,7 @@ impl<'a, CH: CustomHandler, AH: AddressHandler> VMBase for Context<'a, CH, AH> {
 
     fn db_remove(&mut self, key: Self::StorageKey) -> Result<(), Self::Error> {
         let contract_addr = self.env.contract.address.clone().try_into()?;
-        self.state
-            .db
-            .storage
-            .get_mut(&contract_addr)
-            .map(|contract_storage| contract_storage.data.remove(&key));
+        self.state.db.storage_remove(&contract_addr, &key);
         Ok(())
     }
 
@@ -0,0 +1,185 @@
+//! Snapshots of a [`State`], dumped as JSON:
+//!
+//! ```json
+//! {
+//!   "version": 3,
+//!   "state": {
+//!     "codes": { "1": { "checksum": "<base64>", "code": "<base64>" } },
+//!     "code_records": {
+//!       "1": { "creator": "<address>", "instantiate_permission": { "AnyOfAddresses": ["<address>"] } }
+//!     },
+//!     "db": {
+//!       "ibc": { "channel-0": { "packets": [], "transfers": [], "request_close": false } },
+//!       "contracts": { "<address>": { "code_id": 1, "admin": null, "label": "test-label" } },
+//!       "records": {
+//!         "<address>": {
+//!           "creator": "<address>",
+//!           "created": 1,
+//!           "ibc_port": null,
+//!           "history": [{ "operation": "Init", "code_id": 1, "updated": 1, "message": "<base64>" }]
+//!         }
+//!       },
+//!       "storage": { "<address>": [["<base64 key>", "<base64 value>"]] },
+//!       "bank": {
+//!         "supply": { "uatom": 100 },
+//!         "balances": { "<address>": { "uatom": 100 } }
+//!       },
+//!       "custom_handler": null
+//!     },
+//!     "execution_timeout": null
+//!   }
+//! }
+//! ```
+//!
+//! The gas meter, the pending transactions and the coverage counters are not part of a snapshot.
+//! Any change of this format bumps [`SNAPSHOT_VERSION`].
+
+use super::{State, Storage, VmError};
+use cosmwasm_std::Binary;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+/// Version of the snapshot format written by [`State::save`].
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+#[derive(Serialize)]
+struct SnapshotRef<'a, T> {
+    version: u32,
+    state: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct Snapshot<T> {
+    state: T,
+}
+
+impl<CH: Serialize + DeserializeOwned, AH> State<CH, AH> {
+    /// Write a snapshot of the state to `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the state can't be serialized or written.
+    pub fn to_writer<W: io::Write>(&self, writer: W) -> Result<(), VmError> {
+        serde_json::to_writer(
+            writer,
+            &SnapshotRef {
+                version: SNAPSHOT_VERSION,
+                state: self,
+            },
+        )
+        .map_err(|e| {
+            if e.is_io() {
+                VmError::Io(e.into())
+            } else {
+                VmError::CannotSerialize
+            }
+        })
+    }
+
+    /// Read a state from a snapshot written by [`State::to_writer`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the snapshot can't be read, is malformed or has another version.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, VmError> {
+        let mut snapshot = Vec::new();
+        reader.read_to_end(&mut snapshot).map_err(VmError::Io)?;
+        // Check the version first, as the rest of an older snapshot is likely not to parse.
+        let Version { version } =
+            serde_json::from_slice(&snapshot).map_err(|_| VmError::CannotDeserialize)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(VmError::UnsupportedSnapshotVersion(version));
+        }
+        let Snapshot { state } =
+            serde_json::from_slice(&snapshot).map_err(|_| VmError::CannotDeserialize)?;
+        Ok(state)
+    }
+
+    /// Dump the state to the file at `path`, see [`State::to_writer`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be created or the state can't be serialized.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), VmError> {
+        self.to_writer(io::BufWriter::new(File::create(path).map_err(VmError::Io)?))
+    }
+
+    /// Reload a state dumped with [`State::save`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be opened or doesn't hold a snapshot of this version.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, VmError> {
+        Self::from_reader(File::open(path).map_err(VmError::Io)?)
+    }
+}
+
+/// Only the data of a storage is kept, as base64 key/value pairs.
+impl Serialize for Storage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            self.data
+                .iter()
+                .map(|(key, value)| (Binary::from(&key[..]), Binary::from(&value[..]))),
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for Storage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Storage {
+            data: Vec::<(Binary, Binary)>::deserialize(deserializer)?
+                .into_iter()
+                .map(|(key, value)| (key.0, value.0))
+                .collect(),
+            ..Storage::default()
+        })
+    }
+}
+
+/// Uploaded codes along with their checksum, as base64.
+pub(crate) mod codes {
+    use alloc::collections::BTreeMap;
+    use cosmwasm_std::Binary;
+    use cosmwasm_vm::system::CosmwasmCodeId;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Code {
+        checksum: Binary,
+        code: Binary,
+    }
+
+    pub fn serialize<S: Serializer>(
+        codes: &BTreeMap<CosmwasmCodeId, (Vec<u8>, Vec<u8>)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(codes.iter().map(|(code_id, (checksum, code))| {
+            (
+                code_id,
+                Code {
+                    checksum: Binary::from(&checksum[..]),
+                    code: Binary::from(&code[..]),
+                },
+            )
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<CosmwasmCodeId, (Vec<u8>, Vec<u8>)>, D::Error> {
+        Ok(BTreeMap::<CosmwasmCodeId, Code>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(code_id, Code { checksum, code })| (code_id, (checksum.0, code.0)))
+            .collect())
+    }
+}
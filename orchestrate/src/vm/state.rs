@@ -1,25 +1,33 @@
 use super::{
     bank::{self, Bank},
-    Account, AddressHandler, Context, CustomHandler, Db, ExecutionType, Gas, IbcChannelId,
-    IbcState, VmError,
+    snapshot, Account, AddressHandler, CodeRecord, Context, ContractRecord, Coverage,
+    CustomHandler, Db, ExecutionType, Gas, IbcChannelId, IbcState, VmError,
 };
 use alloc::collections::{BTreeMap, VecDeque};
 use core::fmt::Debug;
 use core::marker::PhantomData;
+use core::time::Duration;
 use cosmwasm_std::{BlockInfo, Coin, ContractInfo, Env, MessageInfo, Timestamp, TransactionInfo};
 use cosmwasm_vm::{
     executor::{
         cosmwasm_call, CosmwasmCallInput, CosmwasmCallWithoutInfoInput, DeserializeLimit,
-        ExecuteCall, HasInfo, InstantiateCall, QueryCall, QueryResult, ReadLimit,
+        ExecuteCall, HasInfo, InstantiateCall, MigrateCall, QueryCall, QueryResult, ReadLimit,
     },
     input::Input,
     memory::PointerOf,
     system::{self, CosmwasmCallVM, CosmwasmCodeId, CosmwasmContractMeta, StargateCosmwasmCallVM},
     vm::{VmErrorOf, VmInputOf, VmMessageCustomOf},
 };
-use cosmwasm_vm_wasmi::{host_functions, new_wasmi_vm, WasmiBaseVM, WasmiImportResolver, WasmiVM};
-use serde::de::DeserializeOwned;
+use cosmwasm_vm_wasmi::{
+    allocation::{AllocationReport, AllocationTracker},
+    deadline::instrument_deadline,
+    diagnostics::Diagnostics,
+    host_functions, new_wasmi_vm, new_wasmi_vm_with_diagnostics, WasmiBaseVM, WasmiImportResolver,
+    WasmiModule, WasmiVM, WasmiVMError,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::time::Instant;
 
 #[allow(clippy::module_name_repetitions)]
 pub trait VmState<'a, VM: WasmiBaseVM>
@@ -107,12 +115,40 @@ where
             >;
 }
 
-#[derive(Clone)]
+/// The state of the chain, which can be dumped to a file, see [`State::save`].
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "CH: Serialize", deserialize = "CH: DeserializeOwned"))]
 pub struct State<CH, AH> {
+    #[serde(skip)]
     pub transactions: VecDeque<Db<CH>>,
     pub db: Db<CH>,
+    #[serde(with = "snapshot::codes")]
     pub codes: BTreeMap<CosmwasmCodeId, (Vec<u8>, Vec<u8>)>,
+    /// Creator and instantiate permission of the codes uploaded with [`State::store_code`].
+    pub code_records: BTreeMap<CosmwasmCodeId, CodeRecord>,
+    #[serde(skip, default = "default_gas")]
     pub gas: Gas,
+    /// Wall-clock budget of a top-level call, enforced on gas instrumentation callbacks and on
+    /// the checks injected in the codes while it is set, see [`State::executable_code`].
+    pub execution_timeout: Option<Duration>,
+    #[serde(skip)]
+    pub(crate) deadline: Option<Instant>,
+    /// Codes with the deadline checks injected, along with the checksum they have been derived
+    /// from and whether the coverage counters were injected first.
+    #[serde(skip)]
+    deadline_codes: BTreeMap<CosmwasmCodeId, (Vec<u8>, bool, Vec<u8>)>,
+    /// Basic-block coverage of the codes, see [`State::enable_coverage`].
+    #[serde(skip)]
+    pub coverage: Option<Coverage>,
+    /// Whether failed calls are reported with the functions active at the time, see
+    /// [`State::enable_diagnostics`].
+    #[serde(skip)]
+    pub diagnostics: bool,
+    /// Allocations of the top-level entrypoints called so far, along with the contract they
+    /// ran in, see [`State::enable_allocation_tracking`].
+    #[serde(skip)]
+    pub allocations: Option<Vec<(Account, AllocationReport)>>,
+    #[serde(skip)]
     _marker: PhantomData<AH>,
 }
 
@@ -132,19 +168,32 @@ where
         gas: u64,
         message: &[u8],
     ) -> Result<(Account, E::Output<Context<'a, CH, AH>>), VmError> {
+        let creator = info.sender.clone().try_into()?;
+        self.ensure_instantiate(&creator, code_id)?;
+        let (module, diagnostics) = load_module(self, code_id)?;
+        let (_, code) = self
+            .codes
+            .get(&code_id)
+            .ok_or(VmError::CodeNotFound(code_id))?;
         let contract_addr = if let Some(contract) = contract {
             contract
         } else {
-            let (_, code_hash) = self
-                .codes
-                .get(&code_id)
-                .ok_or(VmError::CodeNotFound(code_id))?;
-            Account::generate::<AH>(code_hash, message)?
+            Account::generate::<AH>(code, message)?
         };
         self.gas = Gas::new(gas);
         if self.db.contracts.contains_key(&contract_addr) {
             return Err(VmError::AlreadyInstantiated);
         }
+        let record = ContractRecord::new(
+            &contract_addr,
+            creator,
+            code_id,
+            code,
+            block.height,
+            message,
+        )?;
+        self.db.records.insert(contract_addr.clone(), record);
+        self.db.storage_init(&contract_addr);
         self.db.contracts.insert(
             contract_addr.clone(),
             CosmwasmContractMeta {
@@ -153,8 +202,10 @@ where
                 label: String::from("test-label"),
             },
         );
-        let mut vm = create_vm(
+        let mut vm = create_vm_with_module(
             self,
+            module,
+            diagnostics,
             Env {
                 block,
                 transaction,
@@ -173,6 +224,7 @@ where
             Ok(output) => Ok((contract_addr, output)),
             Err(e) => {
                 vm.0.state.db.contracts.remove(&contract_addr);
+                vm.0.state.db.records.remove(&contract_addr);
                 Err(e)
             }
         }
@@ -186,7 +238,7 @@ where
         message: &[u8],
     ) -> Result<E::Output<Context<'a, CH, AH>>, VmError> {
         self.gas = Gas::new(gas);
-        let mut vm = create_vm(self, env, info);
+        let mut vm = create_vm(self, env, info)?;
         E::raw_system_call::<_, ExecuteCall<VmMessageCustomOf<WasmiVM<Context<'a, CH, AH>>>>>(
             &mut vm, message,
         )
@@ -204,21 +256,41 @@ where
 
         let contract: Account = env.contract.address.clone().try_into()?;
         let sender: Account = info.sender.clone().try_into()?;
-        let mut vm = create_vm(self, env, info);
-
-        let mut meta = vm.contract_meta(contract.clone())?;
+        let height = env.block.height;
+        let meta = self
+            .db
+            .contracts
+            .get(&contract)
+            .cloned()
+            .ok_or_else(|| VmError::ContractNotFound(contract.clone()))?;
         // Only admin can call this entrypoint
         if meta.admin != Some(sender) {
             return Err(VmError::NotAuthorized);
         }
-        // Update the `code_id` if necessary
-        if meta.code_id != code_id {
-            meta.code_id = code_id;
-            vm.set_contract_meta(contract, meta)?;
-        }
-        E::raw_system_call::<_, ExecuteCall<VmMessageCustomOf<WasmiVM<Context<'a, CH, AH>>>>>(
+        // The `migrate` entry point of the new code is the one being called.
+        let (module, diagnostics) = load_module(self, code_id)?;
+        self.db.contracts.insert(
+            contract.clone(),
+            CosmwasmContractMeta {
+                code_id,
+                ..meta.clone()
+            },
+        );
+        let mut vm = create_vm_with_module(self, module, diagnostics, env, info);
+        match E::raw_system_call::<_, MigrateCall<VmMessageCustomOf<WasmiVM<Context<'a, CH, AH>>>>>(
             &mut vm, message,
-        )
+        ) {
+            Ok(output) => {
+                vm.0.state
+                    .db
+                    .record_migrate(&contract, code_id, height, message);
+                Ok(output)
+            }
+            Err(e) => {
+                vm.0.state.db.contracts.insert(contract, meta);
+                Err(e)
+            }
+        }
     }
 
     fn do_update_admin(
@@ -244,7 +316,7 @@ where
             sender: sender.clone().into(),
             funds: vec![],
         };
-        let mut vm = create_vm(self, env, info.clone());
+        let mut vm = create_vm(self, env, info.clone())?;
         system::update_admin(&mut vm, &info.sender, contract_addr.clone(), new_admin)
     }
 
@@ -259,7 +331,7 @@ where
         WasmiVM<Context<'a, CH, AH>>: CosmwasmCallVM<I> + StargateCosmwasmCallVM,
     {
         self.gas = Gas::new(gas);
-        let mut vm = create_vm(self, env, info);
+        let mut vm = create_vm(self, env, info)?;
         E::raw_system_call::<Context<'a, CH, AH>, I>(&mut vm, message)
     }
 
@@ -269,7 +341,7 @@ where
         info: MessageInfo,
         message: &[u8],
     ) -> Result<QueryResult, VmError> {
-        let mut vm = create_vm(self, env, info);
+        let mut vm = create_vm(self, env, info)?;
         cosmwasm_call::<QueryCall, WasmiVM<Context<CH, AH>>>(&mut vm, message)
     }
 
@@ -292,7 +364,7 @@ where
             >,
     {
         self.gas = Gas::new(gas);
-        let mut vm = create_vm(self, env, info);
+        let mut vm = create_vm(self, env, info)?;
         cosmwasm_call::<I, WasmiVM<Context<'a, CH, AH>>>(&mut vm, message)
     }
 }
@@ -325,7 +397,8 @@ impl<CH: CustomHandler, AH: AddressHandler> State<CH, AH> {
                     (code_id, (code_hash, code))
                 })
                 .collect::<BTreeMap<_, _>>(),
-            gas: Gas::new(100_000_000),
+            code_records: BTreeMap::new(),
+            gas: default_gas(),
             db: Db {
                 bank: if initial_balances.is_empty() {
                     Bank::default()
@@ -357,45 +430,158 @@ impl<CH: CustomHandler, AH: AddressHandler> State<CH, AH> {
                 ..Default::default()
             },
             transactions: VecDeque::default(),
+            execution_timeout: None,
+            deadline: None,
+            deadline_codes: BTreeMap::new(),
+            coverage: None,
+            diagnostics: false,
+            allocations: None,
             _marker: PhantomData,
         }
     }
+
+    /// Collect the coverage of the codes, the counters are injected when loading a code and
+    /// mapped back to source lines by [`Coverage::lcov`] using the DWARF sections of a debug build.
+    /// [`State::codes`] are left untouched.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a code already uploaded can't be instrumented.
+    pub fn enable_coverage(&mut self) -> Result<(), VmError> {
+        if self.coverage.is_some() {
+            return Ok(());
+        }
+        let mut coverage = Coverage::default();
+        for (code_id, (checksum, code)) in &self.codes {
+            coverage.instrument(*code_id, checksum, code)?;
+        }
+        self.coverage = Some(coverage);
+        Ok(())
+    }
+
+    /// Maintain a shadow call stack in the contracts, traps and aborts failing a call with a
+    /// [`VmError::Trap`] holding the functions active at the time.
+    pub fn enable_diagnostics(&mut self) {
+        self.diagnostics = true;
+    }
+
+    /// Track the regions the host exchanges with the contracts, each top-level entrypoint being
+    /// reported to [`State::allocations`].
+    pub fn enable_allocation_tracking(&mut self) {
+        self.allocations.get_or_insert_with(Vec::new);
+    }
+}
+
+impl<CH, AH> State<CH, AH> {
+    /// Code to load for `code_id`. The coverage counters are injected while the
+    /// [`State::coverage`] is collected, and the deadline checks at function entries and loop
+    /// headers while an [`State::execution_timeout`] is set, so that contracts without gas
+    /// metering are interrupted as well. Both are only injected once per code.
+    pub(crate) fn executable_code(&mut self, code_id: CosmwasmCodeId) -> Result<Vec<u8>, VmError> {
+        let (checksum, code) = self
+            .codes
+            .get(&code_id)
+            .ok_or(VmError::CodeNotFound(code_id))?;
+        let covered = self.coverage.is_some();
+        let deadline = self.execution_timeout.is_some();
+        if deadline {
+            if let Some((_, _, instrumented)) =
+                self.deadline_codes
+                    .get(&code_id)
+                    .filter(|(source, source_covered, _)| {
+                        source == checksum && *source_covered == covered
+                    })
+            {
+                return Ok(instrumented.clone());
+            }
+        }
+        let code = match &mut self.coverage {
+            Some(coverage) => coverage.instrument(code_id, checksum, code)?,
+            None => code.clone(),
+        };
+        if deadline {
+            let instrumented = instrument_deadline(&code).map_err(WasmiVMError::from)?;
+            self.deadline_codes
+                .insert(code_id, (checksum.clone(), covered, instrumented.clone()));
+            Ok(instrumented)
+        } else {
+            Ok(code)
+        }
+    }
+}
+
+fn default_gas() -> Gas {
+    Gas::new(100_000_000)
 }
 
 fn create_vm<CH: CustomHandler, AH: AddressHandler>(
     extension: &mut State<CH, AH>,
     env: Env,
     info: MessageInfo,
+) -> Result<WasmiVM<Context<CH, AH>>, VmError> {
+    let contract: Account = env.contract.address.clone().try_into()?;
+    let code_id = extension
+        .db
+        .contracts
+        .get(&contract)
+        .ok_or(VmError::ContractNotFound(contract))?
+        .code_id;
+    let (module, diagnostics) = load_module(extension, code_id)?;
+    Ok(create_vm_with_module(
+        extension,
+        module,
+        diagnostics,
+        env,
+        info,
+    ))
+}
+
+/// Load the module of `code_id`, see [`State::executable_code`]. The module is instrumented to
+/// maintain a shadow call stack when [`State::diagnostics`] are enabled.
+pub(crate) fn load_module<CH: CustomHandler, AH: AddressHandler>(
+    state: &mut State<CH, AH>,
+    code_id: CosmwasmCodeId,
+) -> Result<(WasmiModule, Option<Diagnostics>), VmError> {
+    let code = state.executable_code(code_id)?;
+    let host_functions_definitions =
+        WasmiImportResolver(host_functions::definitions::<Context<CH, AH>>());
+    if state.diagnostics {
+        let (module, diagnostics) =
+            new_wasmi_vm_with_diagnostics(&host_functions_definitions, &code)?;
+        Ok((module, Some(diagnostics)))
+    } else {
+        Ok((new_wasmi_vm(&host_functions_definitions, &code)?, None))
+    }
+}
+
+fn create_vm_with_module<CH: CustomHandler, AH: AddressHandler>(
+    extension: &mut State<CH, AH>,
+    module: WasmiModule,
+    diagnostics: Option<Diagnostics>,
+    env: Env,
+    info: MessageInfo,
 ) -> WasmiVM<Context<CH, AH>> {
-    let code = extension
-        .codes
-        .get(
-            &extension
-                .db
-                .contracts
-                .get(
-                    &env.clone()
-                        .contract
-                        .address
-                        .try_into()
-                        .expect("Invalid address"),
-                )
-                .expect("contract should have been uploaded")
-                .code_id,
-        )
-        .expect("contract should have been uploaded");
     let host_functions_definitions = WasmiImportResolver(host_functions::definitions());
-    let module = new_wasmi_vm(&host_functions_definitions, &code.1).unwrap();
+    extension.deadline = extension
+        .execution_timeout
+        .map(|timeout| Instant::now() + timeout);
     WasmiVM(Context {
         host_functions: host_functions_definitions
             .0
             .clone()
             .into_iter()
             .flat_map(|(_, modules)| modules.into_values())
+            .map(|(index, function, _)| (index, function))
             .collect(),
         executing_module: module,
         env,
         info,
+        allocation_tracker: extension
+            .allocations
+            .as_ref()
+            .map(|_| AllocationTracker::new()),
         state: extension,
+        iterators: Vec::new(),
+        diagnostics,
     })
 }
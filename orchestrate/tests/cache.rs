@@ -0,0 +1,125 @@
+use cosmwasm_orchestrate::{
+    error::Error,
+    fetcher::{CodeCache, CosmosFetcher},
+};
+use flate2::{write::GzEncoder, Compression};
+use sha2::{Digest, Sha256};
+use std::{io::Write, path::PathBuf};
+
+mod common;
+
+const CODE: &[u8] = include_bytes!("../../fixtures/cw20_base.wasm");
+
+fn cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("orchestrate-cache-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[tokio::test]
+async fn cache_serves_codes_offline() {
+    let endpoint = common::lcd(vec![
+        ("/cw20.wasm".to_string(), CODE.to_vec()),
+        (
+            "/cosmwasm/wasm/v1/code/3".to_string(),
+            format!(r#"{{"data":"{}"}}"#, base64::encode(CODE)).into_bytes(),
+        ),
+        (
+            "/cosmwasm/wasm/v1/contract/wasm1contract".to_string(),
+            br#"{"contract_info":{"code_id":"3"}}"#.to_vec(),
+        ),
+    ]);
+    let url = format!("{endpoint}/cw20.wasm");
+    let checksum = hex::encode(Sha256::digest(CODE));
+    let dir = cache_dir("offline");
+
+    let cache = CodeCache::new(&dir);
+    assert_eq!(cache.from_url(&url, Some(&checksum)).await.unwrap(), CODE);
+    assert_eq!(
+        cache
+            .from_code_id::<CosmosFetcher>("localwasm", &endpoint, 3, None)
+            .await
+            .unwrap(),
+        CODE
+    );
+
+    assert_eq!(
+        cache
+            .from_contract_addr::<CosmosFetcher>("localwasm", &endpoint, "wasm1contract", None)
+            .await
+            .unwrap(),
+        CODE
+    );
+    let offline = CodeCache::new(&dir).set_offline(true);
+    assert_eq!(offline.from_url(&url, Some(&checksum)).await.unwrap(), CODE);
+    assert_eq!(
+        offline
+            .from_code_id::<CosmosFetcher>("localwasm", &endpoint, 3, None)
+            .await
+            .unwrap(),
+        CODE
+    );
+    assert_eq!(
+        offline
+            .from_contract_addr::<CosmosFetcher>("localwasm", &endpoint, "wasm1contract", None)
+            .await
+            .unwrap(),
+        CODE
+    );
+    assert!(matches!(
+        offline
+            .from_code_id::<CosmosFetcher>("otherchain", &endpoint, 3, None)
+            .await,
+        Err(Error::CacheMiss)
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn cache_verifies_checksums() {
+    let endpoint = common::lcd(vec![("/cw20.wasm".to_string(), CODE.to_vec())]);
+    let dir = cache_dir("checksum");
+    assert!(matches!(
+        CodeCache::new(&dir)
+            .from_url(&format!("{endpoint}/cw20.wasm"), Some(&"00".repeat(32)))
+            .await,
+        Err(Error::ChecksumMismatch)
+    ));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn cache_decompresses_gzipped_codes() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(CODE).unwrap();
+    let endpoint = common::lcd(vec![(
+        "/cw20.wasm.gz".to_string(),
+        encoder.finish().unwrap(),
+    )]);
+    let dir = cache_dir("gzip");
+    assert_eq!(
+        CodeCache::new(&dir)
+            .set_decompress(true)
+            .from_url(&format!("{endpoint}/cw20.wasm.gz"), None)
+            .await
+            .unwrap(),
+        CODE
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn cache_rejects_failed_and_non_wasm_responses() {
+    let endpoint = common::lcd(vec![("/page.html".to_string(), b"<html></html>".to_vec())]);
+    let dir = cache_dir("invalid");
+    let cache = CodeCache::new(&dir);
+    assert!(matches!(
+        cache.from_url(&format!("{endpoint}/cw20.wasm"), None).await,
+        Err(Error::Status(404))
+    ));
+    assert!(matches!(
+        cache.from_url(&format!("{endpoint}/page.html"), None).await,
+        Err(Error::NotWasm)
+    ));
+    assert!(!dir.exists());
+}
@@ -0,0 +1,164 @@
+use cosmwasm_orchestrate::{
+    vm::{AccessConfig, Account, VmError, WasmAddressHandler},
+    MessageResponse, StateBuilder, TxMessage, TxResponse, WasmChain,
+};
+use cosmwasm_std::{Coin, Timestamp, Uint128};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg};
+
+mod common;
+
+fn setup() -> (Account, Account, WasmChain) {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let (cw20, chain) = common::cw20_chain(
+        StateBuilder::new().add_balance(sender.clone(), Coin::new(100, "uatom")),
+        &sender,
+        None,
+    );
+    (sender, cw20, chain)
+}
+
+fn balance(chain: &mut WasmChain, cw20: &Account, account: &Account) -> Uint128 {
+    let BalanceResponse { balance } = chain
+        .query(
+            cw20,
+            Cw20QueryMsg::Balance {
+                address: account.clone().into(),
+            },
+        )
+        .unwrap();
+    balance
+}
+
+#[test]
+fn chain_derives_env_from_current_block() {
+    let (sender, cw20, mut chain) = setup();
+    let recipient = Account::generate_from_seed::<WasmAddressHandler>("recipient").unwrap();
+    assert_eq!(chain.transaction_index(), 1);
+
+    chain
+        .execute(
+            &sender,
+            &cw20,
+            Cw20ExecuteMsg::Transfer {
+                recipient: recipient.clone().into(),
+                amount: Uint128::new(400),
+            },
+            vec![],
+        )
+        .unwrap();
+    assert_eq!(chain.transaction_index(), 2);
+    assert_eq!(balance(&mut chain, &cw20, &sender), Uint128::new(600));
+    assert_eq!(balance(&mut chain, &cw20, &recipient), Uint128::new(400));
+    assert_eq!(chain.transaction_index(), 2);
+
+    chain.next_block();
+    let block = chain.block();
+    assert_eq!(block.height, 2);
+    assert_eq!(block.time, Timestamp::from_seconds(6));
+    assert_eq!(chain.transaction_index(), 0);
+    assert_eq!(chain.env(&cw20).block, block);
+}
+
+#[test]
+fn chain_reverts_funds_of_failed_calls() {
+    let (sender, cw20, mut chain) = setup();
+    let transfer = |amount| Cw20ExecuteMsg::Transfer {
+        recipient: sender.clone().into(),
+        amount: Uint128::new(amount),
+    };
+
+    assert!(chain
+        .execute(
+            &sender,
+            &cw20,
+            transfer(10_000),
+            vec![Coin::new(10, "uatom")]
+        )
+        .is_err());
+    assert_eq!(chain.state.db.bank.balance(&sender, "uatom"), 100);
+    assert_eq!(chain.state.db.bank.balance(&cw20, "uatom"), 0);
+
+    chain
+        .execute(&sender, &cw20, transfer(10), vec![Coin::new(10, "uatom")])
+        .unwrap();
+    assert_eq!(chain.state.db.bank.balance(&sender, "uatom"), 90);
+    assert_eq!(chain.state.db.bank.balance(&cw20, "uatom"), 10);
+}
+
+#[test]
+fn chain_runs_transactions_atomically() {
+    let (sender, cw20, mut chain) = setup();
+    let recipient = Account::generate_from_seed::<WasmAddressHandler>("recipient").unwrap();
+    let transfer = |amount| {
+        TxMessage::execute(
+            cw20.clone(),
+            Cw20ExecuteMsg::Transfer {
+                recipient: recipient.clone().into(),
+                amount: Uint128::new(amount),
+            },
+            vec![],
+        )
+        .unwrap()
+    };
+    let send = TxMessage::BankSend {
+        to: recipient.clone(),
+        amount: vec![Coin::new(10, "uatom")],
+    };
+
+    assert!(matches!(
+        chain.tx(&sender, vec![send.clone(), transfer(400), transfer(10_000)]),
+        Err(VmError::MessageFailed(2, _))
+    ));
+    assert_eq!(balance(&mut chain, &cw20, &recipient), Uint128::zero());
+    assert_eq!(chain.state.db.bank.balance(&recipient, "uatom"), 0);
+
+    let TxResponse {
+        gas_used,
+        responses,
+    } = chain
+        .tx(&sender, vec![send, transfer(400), transfer(200)])
+        .unwrap();
+    assert!(gas_used > 0);
+    assert_eq!(responses.len(), 3);
+    assert!(matches!(&responses[0], MessageResponse::BankSend { events } if events.len() == 1));
+    assert!(matches!(&responses[1], MessageResponse::Execute { .. }));
+    assert_eq!(balance(&mut chain, &cw20, &recipient), Uint128::new(600));
+    assert_eq!(chain.state.db.bank.balance(&recipient, "uatom"), 10);
+    // Both transactions are indexed, the failing one included.
+    assert_eq!(chain.transaction_index(), 3);
+}
+
+#[test]
+fn chain_rolls_back_stored_codes() {
+    let (sender, cw20, mut chain) = setup();
+    chain.state.enable_coverage().unwrap();
+    let store = TxMessage::StoreCode {
+        code: common::CW20.into(),
+        instantiate_permission: AccessConfig::Everybody,
+    };
+    let transfer = TxMessage::execute(
+        cw20,
+        Cw20ExecuteMsg::Transfer {
+            recipient: sender.clone().into(),
+            amount: Uint128::new(10_000),
+        },
+        vec![],
+    )
+    .unwrap();
+
+    assert!(matches!(
+        chain.tx(&sender, vec![store.clone(), transfer]),
+        Err(VmError::MessageFailed(1, _))
+    ));
+    assert_eq!(chain.state.codes.keys().collect::<Vec<_>>(), [&1]);
+    assert!(chain.state.code_records.is_empty());
+    let coverage = chain.state.coverage.as_ref().unwrap();
+    assert!(coverage.block_hits(1).is_some());
+    assert!(coverage.block_hits(2).is_none());
+
+    let response = chain.tx(&sender, vec![store]).unwrap();
+    assert_eq!(
+        response.responses,
+        [MessageResponse::StoreCode { code_id: 2 }]
+    );
+}
@@ -0,0 +1,138 @@
+use common::{cw20_instantiate_msg, CW20};
+use cosmwasm_orchestrate::{
+    vm::{AccessConfig, Account, VmError, WasmAddressHandler},
+    ChainConfig, MessageResponse, StateBuilder, TxMessage, WasmChain,
+};
+
+mod common;
+
+#[test]
+fn stored_code_enforces_instantiate_permission() {
+    let governance = Account::generate_from_seed::<WasmAddressHandler>("governance").unwrap();
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let state = StateBuilder::new().add_code(CW20).build();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+
+    let gated = chain
+        .store_code(
+            &governance,
+            CW20,
+            AccessConfig::AnyOfAddresses(vec![governance.clone()]),
+        )
+        .unwrap();
+    let frozen = chain
+        .store_code(&governance, CW20, AccessConfig::Nobody)
+        .unwrap();
+    assert_eq!((gated, frozen), (2, 3));
+    let record = &chain.state.code_records[&gated];
+    assert_eq!(record.creator, governance);
+    assert_eq!(chain.state.codes[&gated].0, chain.state.codes[&1].0);
+
+    assert!(matches!(
+        chain.instantiate(
+            &sender,
+            gated,
+            None,
+            cw20_instantiate_msg([sender.to_string()], 1000),
+            vec![]
+        ),
+        Err(VmError::NotAuthorized)
+    ));
+    assert!(matches!(
+        chain.instantiate(
+            &governance,
+            frozen,
+            None,
+            cw20_instantiate_msg([sender.to_string()], 1000),
+            vec![]
+        ),
+        Err(VmError::NotAuthorized)
+    ));
+    chain
+        .instantiate(
+            &governance,
+            gated,
+            None,
+            cw20_instantiate_msg([sender.to_string()], 1000),
+            vec![],
+        )
+        .unwrap();
+    // Codes of the builder can be instantiated by everybody.
+    chain
+        .instantiate(
+            &sender,
+            1,
+            None,
+            cw20_instantiate_msg([sender.to_string()], 1000),
+            vec![],
+        )
+        .unwrap();
+}
+
+#[test]
+fn stored_code_is_rolled_back_with_its_transaction() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let state = StateBuilder::<WasmAddressHandler>::new().build();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    let store = TxMessage::StoreCode {
+        code: CW20.into(),
+        instantiate_permission: AccessConfig::Everybody,
+    };
+
+    assert!(matches!(
+        chain.tx(
+            &sender,
+            vec![
+                store.clone(),
+                TxMessage::instantiate(
+                    1,
+                    None,
+                    cw20_instantiate_msg([sender.to_string()], 1000),
+                    vec![]
+                )
+                .unwrap(),
+                TxMessage::instantiate(
+                    2,
+                    None,
+                    cw20_instantiate_msg([sender.to_string()], 1000),
+                    vec![]
+                )
+                .unwrap(),
+            ],
+        ),
+        Err(VmError::MessageFailed(2, _))
+    ));
+    assert!(chain.state.codes.is_empty());
+    assert!(chain.state.code_records.is_empty());
+
+    let response = chain.tx(&sender, vec![store]).unwrap();
+    assert_eq!(
+        response.responses,
+        [MessageResponse::StoreCode { code_id: 1 }]
+    );
+    assert_eq!(chain.state.code_records[&1].creator, sender);
+}
+
+#[test]
+fn stored_code_is_validated() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let state = StateBuilder::<WasmAddressHandler>::new().build();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+
+    assert!(matches!(
+        chain.store_code(&sender, b"\0asm\x01\0\0\0\x01", AccessConfig::Everybody),
+        Err(VmError::VMError(_))
+    ));
+    assert!(matches!(
+        chain.tx(
+            &sender,
+            vec![TxMessage::StoreCode {
+                code: CW20[..CW20.len() / 2].into(),
+                instantiate_permission: AccessConfig::Everybody,
+            }],
+        ),
+        Err(VmError::MessageFailed(0, _))
+    ));
+    assert!(chain.state.codes.is_empty());
+    assert!(chain.state.code_records.is_empty());
+}
@@ -0,0 +1,115 @@
+// Each test crate only uses some of the helpers.
+#![allow(dead_code)]
+
+use cosmwasm_orchestrate::{
+    vm::{Account, WasmAddressHandler},
+    ChainConfig, StateBuilder, WasmChain,
+};
+use cosmwasm_std::Uint128;
+use cw20::Cw20Coin;
+use cw20_base::msg::InstantiateMsg;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    thread,
+};
+
+pub const CW20: &[u8] = include_bytes!("../../../fixtures/cw20_base.wasm");
+
+/// Instantiate message of the Picasso token, minting `amount` to each holder.
+pub fn cw20_instantiate_msg(
+    holders: impl IntoIterator<Item = String>,
+    amount: u128,
+) -> InstantiateMsg {
+    InstantiateMsg {
+        name: "Picasso".into(),
+        symbol: "PICA".into(),
+        decimals: 12,
+        initial_balances: holders
+            .into_iter()
+            .map(|address| Cw20Coin {
+                address,
+                amount: Uint128::new(amount),
+            })
+            .collect(),
+        mint: None,
+        marketing: None,
+    }
+}
+
+/// Build a chain with the cw20 code added after the codes of `builder`, and instantiate the
+/// Picasso token from code 1 with 1000 tokens held by `sender`.
+pub fn cw20_chain(
+    builder: StateBuilder<WasmAddressHandler>,
+    sender: &Account,
+    admin: Option<Account>,
+) -> (Account, WasmChain) {
+    let mut chain = WasmChain::new(builder.add_code(CW20).build(), ChainConfig::default());
+    let (cw20, _) = chain
+        .instantiate(
+            sender,
+            1,
+            admin,
+            cw20_instantiate_msg([sender.to_string()], 1000),
+            vec![],
+        )
+        .unwrap();
+    (cw20, chain)
+}
+
+/// Serve canned LCD responses, by exact path and query, on a local port.
+pub fn lcd<B: AsRef<[u8]> + Send + 'static>(routes: Vec<(String, B)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            // Drain the headers, the requests having no body.
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                header.clear();
+            }
+            let target = request_line.split(' ').nth(1).unwrap();
+            let (status, body) = routes
+                .iter()
+                .find(|(route, _)| route == target)
+                .map_or(("404 Not Found", &b"{}"[..]), |(_, body)| {
+                    ("200 OK", body.as_ref())
+                });
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+        }
+    });
+    endpoint
+}
+
+/// A contract whose `instantiate` entry point runs `instantiate`, given in the text format, along
+/// with a bump allocator.
+pub fn contract(instantiate: &str) -> Vec<u8> {
+    wat::parse_str(format!(
+        r#"(module
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+  (func (export "interface_version_8"))
+  (func (export "allocate") (param $size i32) (result i32)
+    (local $region i32)
+    (local.set $region (global.get $heap))
+    (i32.store (local.get $region) (i32.add (local.get $region) (i32.const 12)))
+    (i32.store offset=4 (local.get $region) (local.get $size))
+    (i32.store offset=8 (local.get $region) (i32.const 0))
+    (global.set $heap
+      (i32.add (local.get $region) (i32.add (local.get $size) (i32.const 12))))
+    (local.get $region))
+  (func (export "deallocate") (param i32))
+  {instantiate})"#
+    ))
+    .unwrap()
+}
@@ -0,0 +1,162 @@
+use cosmwasm_orchestrate::{
+    vm::{AccessConfig, Account, CodeHistoryOperation, ContractRecord, WasmAddressHandler},
+    ChainConfig, MessageResponse, StateBuilder, TxMessage, WasmChain,
+};
+use cosmwasm_std::{from_binary, Binary, ContractInfoResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+mod common;
+
+const HACKATOM: &[u8] = include_bytes!("../../fixtures/hackatom.wasm");
+const REFLECT: &[u8] = include_bytes!("../../fixtures/reflect.wasm");
+
+#[derive(Deserialize)]
+struct ChainResponse {
+    data: Binary,
+}
+
+#[derive(Deserialize)]
+struct VerifierResponse {
+    verifier: String,
+}
+
+fn hackatom_chain(sender: &Account, admin: &Account) -> (Account, WasmChain) {
+    let state = StateBuilder::new()
+        .add_code(HACKATOM)
+        .add_code(HACKATOM)
+        .add_code(REFLECT)
+        .build();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    let (hackatom, _) = chain
+        .instantiate(
+            sender,
+            1,
+            Some(admin.clone()),
+            json!({ "verifier": sender, "beneficiary": sender }),
+            vec![],
+        )
+        .unwrap();
+    (hackatom, chain)
+}
+
+#[test]
+fn contract_info_tracks_creator_and_code_history() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let admin = Account::generate_from_seed::<WasmAddressHandler>("admin").unwrap();
+    let (hackatom, mut chain) = hackatom_chain(&sender, &admin);
+    let created = chain.block().height;
+
+    let info = chain.state.db.contract_info(&hackatom).unwrap();
+    assert_eq!(info.code_id, 1);
+    assert_eq!(info.creator, sender.to_string());
+    assert_eq!(info.admin, Some(admin.to_string()));
+    assert_eq!(info.ibc_port, None);
+    assert_eq!(chain.state.db.records[&hackatom].created, created);
+    assert!(chain
+        .state
+        .db
+        .contract_info(&Account::unchecked("wasm1unknown"))
+        .is_none());
+
+    chain.next_block();
+    chain
+        .tx(
+            &admin,
+            vec![TxMessage::migrate(hackatom.clone(), 2, json!({ "verifier": admin })).unwrap()],
+        )
+        .unwrap();
+
+    let VerifierResponse { verifier } = chain.query(&hackatom, json!({ "verifier": {} })).unwrap();
+    assert_eq!(verifier, admin.to_string());
+    assert_eq!(chain.state.db.contract_info(&hackatom).unwrap().code_id, 2);
+    let history = chain
+        .state
+        .db
+        .code_history(&hackatom)
+        .iter()
+        .map(|entry| (entry.operation, entry.code_id, entry.updated))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        history,
+        [
+            (CodeHistoryOperation::Init, 1, created),
+            (CodeHistoryOperation::Migrate, 2, created + 1)
+        ]
+    );
+}
+
+#[test]
+fn migration_runs_the_new_code() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let (hackatom, mut chain) = hackatom_chain(&sender, &sender);
+    // Only the `migrate` of the new code accepts `{}`, answering with `v2` as data.
+    let migrated = common::contract(
+        r#"(data (i32.const 64) "\50\00\00\00\40\00\00\00\40\00\00\00")
+           (data (i32.const 80) "{\"ok\":{\"messages\":[],\"attributes\":[],\"events\":[],\"data\":\"djI=\"}}")
+           (func (export "migrate") (param i32 i32) (result i32)
+             i32.const 64)"#,
+    );
+    let code_id = chain
+        .store_code(&sender, &migrated, AccessConfig::Everybody)
+        .unwrap();
+
+    let response = chain
+        .tx(
+            &sender,
+            vec![TxMessage::migrate(hackatom.clone(), code_id, json!({})).unwrap()],
+        )
+        .unwrap();
+    assert!(matches!(
+        &response.responses[..],
+        [MessageResponse::Migrate { data: Some(data), .. }] if data.as_slice() == b"v2"
+    ));
+    assert_eq!(
+        chain.state.db.contract_info(&hackatom).unwrap().code_id,
+        code_id
+    );
+}
+
+#[test]
+fn contract_info_is_queried_by_contracts() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let (hackatom, mut chain) = hackatom_chain(&sender, &sender);
+    let (reflect, _) = chain
+        .instantiate(&sender, 3, None, json!({}), vec![])
+        .unwrap();
+
+    let ChainResponse { data } = chain
+        .query(
+            &reflect,
+            json!({
+                "chain": {
+                    "request": { "wasm": { "contract_info": { "contract_addr": hackatom } } }
+                }
+            }),
+        )
+        .unwrap();
+    assert_eq!(
+        from_binary::<ContractInfoResponse>(&data).unwrap(),
+        chain.state.db.contract_info(&hackatom).unwrap()
+    );
+}
+
+#[test]
+fn contract_record_binds_an_ibc_port_to_ibc_contracts() {
+    let contract = Account::unchecked("wasm1contract");
+    let creator = Account::unchecked("wasm1creator");
+    let record = |code: &[u8]| {
+        ContractRecord::new(&contract, creator.clone(), 1, code, 0, b"{}")
+            .unwrap()
+            .ibc_port
+    };
+
+    assert_eq!(record(&common::contract("")), None);
+    assert_eq!(
+        record(&common::contract(
+            r#"(func (export "ibc_channel_open") (param i32 i32) (result i32) unreachable)"#
+        )),
+        Some("wasm.wasm1contract".into())
+    );
+    assert!(ContractRecord::new(&contract, creator, 1, b"\0asm", 0, b"{}").is_err());
+}
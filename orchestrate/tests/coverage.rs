@@ -0,0 +1,168 @@
+mod common;
+
+use cosmwasm_orchestrate::{
+    vm::{AccessConfig, Account, WasmAddressHandler},
+    ChainConfig, StateBuilder, TxMessage, WasmChain,
+};
+use cosmwasm_std::Empty;
+
+const SECTION_CODE: u8 = 10;
+
+fn leb128(bytes: &[u8], position: &mut usize) -> u32 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*position];
+        *position += 1;
+        value |= u32::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Offset of each function body, relative to the payload of the code section as addressed by
+/// DWARF, along with the end of the last one.
+fn function_offsets(code: &[u8]) -> (Vec<u32>, u32) {
+    let mut position = 8;
+    loop {
+        let id = code[position];
+        position += 1;
+        let len = leb128(code, &mut position) as usize;
+        if id == SECTION_CODE {
+            let payload = &code[position..position + len];
+            let mut position = 0;
+            let offsets = (0..leb128(payload, &mut position))
+                .map(|_| {
+                    let len = leb128(payload, &mut position) as usize;
+                    let offset = position as u32;
+                    position += len;
+                    offset
+                })
+                .collect();
+            return (offsets, len as u32);
+        }
+        position += len;
+    }
+}
+
+fn custom_section(code: &mut Vec<u8>, name: &str, payload: &[u8]) {
+    let mut section = vec![name.len() as u8];
+    section.extend_from_slice(name.as_bytes());
+    section.extend_from_slice(payload);
+    code.push(0);
+    code.push(section.len() as u8);
+    code.extend_from_slice(&section);
+}
+
+/// Append minimal DWARF 4 sections mapping each `(address, line)` to `/src/lib.rs`.
+fn with_debug_info(mut code: Vec<u8>, rows: &[(u32, u8)], end: u32) -> Vec<u8> {
+    // A compile unit with a name, a compilation directory and a line program.
+    let abbrev = [
+        0x01, 0x11, 0x00, 0x03, 0x08, 0x1B, 0x08, 0x10, 0x17, 0x00, 0x00, 0x00,
+    ];
+    let mut unit = vec![0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x01];
+    unit.extend_from_slice(b"lib.rs\0/src\0");
+    unit.extend_from_slice(&0_u32.to_le_bytes());
+    let mut info = (unit.len() as u32).to_le_bytes().to_vec();
+    info.extend_from_slice(&unit);
+
+    // Version 4 header, with `lib.rs` in the compilation directory as only file.
+    let mut header = vec![0x01, 0x01, 0x01, 0xFB, 0x0E, 0x0D];
+    header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+    header.push(0x00);
+    header.extend_from_slice(b"lib.rs\0\0\0\0\0");
+    let set_address = |program: &mut Vec<u8>, address: u32| {
+        program.extend_from_slice(&[0x00, 0x05, 0x02]);
+        program.extend_from_slice(&address.to_le_bytes());
+    };
+    let mut program = Vec::new();
+    let mut line = 1;
+    for &(address, row_line) in rows {
+        set_address(&mut program, address);
+        // DW_LNS_advance_line, then DW_LNS_copy.
+        program.extend_from_slice(&[0x03, (i32::from(row_line) - line) as u8 & 0x7F, 0x01]);
+        line = i32::from(row_line);
+    }
+    set_address(&mut program, end);
+    program.extend_from_slice(&[0x00, 0x01, 0x01]);
+    let mut unit = vec![0x04, 0x00];
+    unit.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    unit.extend_from_slice(&header);
+    unit.extend_from_slice(&program);
+    let mut line_section = (unit.len() as u32).to_le_bytes().to_vec();
+    line_section.extend_from_slice(&unit);
+
+    custom_section(&mut code, ".debug_abbrev", &abbrev);
+    custom_section(&mut code, ".debug_info", &info);
+    custom_section(&mut code, ".debug_line", &line_section);
+    code
+}
+
+#[test]
+fn lcov_maps_hits_to_source_lines() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let code = common::contract(
+        r#"(data (i32.const 64) "\50\00\00\00\32\00\00\00\32\00\00\00")
+           (data (i32.const 80) "{\"ok\":{\"messages\":[],\"attributes\":[],\"events\":[]}}")
+           (func $unused)
+           (func (export "instantiate") (param i32 i32 i32) (result i32)
+             i32.const 64)"#,
+    );
+    // `interface_version_8`, `allocate`, `deallocate`, `unused` and `instantiate`.
+    let (offsets, end) = function_offsets(&code);
+    let code = with_debug_info(code, &[(offsets[3], 7), (offsets[4], 3)], end);
+    let mut state = StateBuilder::new().add_code(&code).build();
+    state.enable_coverage().unwrap();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    chain
+        .instantiate(&sender, 1, None, Empty {}, vec![])
+        .unwrap();
+
+    // The code is instrumented when loaded, the uploaded one is kept as is.
+    assert_eq!(chain.state.codes[&1].1, code);
+    assert_eq!(
+        chain.state.coverage.as_ref().unwrap().lcov().unwrap(),
+        "SF:/src/lib.rs\nDA:3,1\nDA:7,0\nLF:2\nLH:1\nend_of_record\n"
+    );
+}
+
+#[test]
+fn coverage_follows_codes_stored_under_a_reused_id() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let trapping = common::contract(
+        r#"(func (export "instantiate") (param i32 i32 i32) (result i32) unreachable)"#,
+    );
+    let succeeding = common::contract(
+        r#"(data (i32.const 64) "\50\00\00\00\32\00\00\00\32\00\00\00")
+           (data (i32.const 80) "{\"ok\":{\"messages\":[],\"attributes\":[],\"events\":[]}}")
+           (func (export "instantiate") (param i32 i32 i32) (result i32)
+             i32.const 64)"#,
+    );
+    let mut state = StateBuilder::<WasmAddressHandler>::new().build();
+    state.enable_coverage().unwrap();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+
+    // The trapping code is instrumented as code 1, then rolled back along with its transaction.
+    assert!(chain
+        .tx(
+            &sender,
+            vec![
+                TxMessage::StoreCode {
+                    code: trapping,
+                    instantiate_permission: AccessConfig::Everybody,
+                },
+                TxMessage::instantiate(1, None, Empty {}, vec![]).unwrap(),
+            ],
+        )
+        .is_err());
+
+    let code_id = chain
+        .store_code(&sender, &succeeding, AccessConfig::Everybody)
+        .unwrap();
+    assert_eq!(code_id, 1);
+    chain
+        .instantiate(&sender, code_id, None, Empty {}, vec![])
+        .unwrap();
+}
@@ -143,6 +143,38 @@ fn cosmos_signature_verify_fails() {
     assert_eq!(res, VerifyResponse { verifies: false });
 }
 
+#[test]
+fn cosmos_signature_verify_errors() {
+    let (addr, mut state) = setup();
+
+    let message = hex::decode(SECP256K1_MESSAGE_HEX).unwrap();
+    let signature = hex::decode(SECP256K1_SIGNATURE_HEX).unwrap();
+    let public_key = vec![];
+
+    let verify_msg = format!(
+        r#"
+    {{
+        "verify_cosmos_signature": {{
+            "message": "{}",
+            "signature": "{}",
+            "public_key": "{}"
+        }}
+    }}
+    "#,
+        Binary(message),
+        Binary(signature),
+        Binary(public_key)
+    );
+
+    // The invalid public key is reported to the contract instead of aborting the execution.
+    let err = <Api<Direct>>::query_raw(&mut state, get_env(&addr), verify_msg.as_bytes())
+        .unwrap()
+        .0
+        .into_result()
+        .unwrap_err();
+    assert!(err.contains("Invalid public key format"));
+}
+
 #[test]
 fn ethereum_signature_verify_works() {
     let (addr, mut state) = setup();
@@ -0,0 +1,31 @@
+mod common;
+
+use cosmwasm_orchestrate::{
+    vm::{Account, VmError, WasmAddressHandler},
+    ChainConfig, StateBuilder, WasmChain,
+};
+use cosmwasm_std::Empty;
+use cosmwasm_vm_wasmi::WasmiVMError;
+use std::time::{Duration, Instant};
+
+#[test]
+fn execution_timeout_interrupts_uninstrumented_loop() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    // Spins forever, without gas metering.
+    let code = common::contract(
+        r#"(func (export "instantiate") (param i32 i32 i32) (result i32)
+             (loop (br 0))
+             unreachable)"#,
+    );
+    let state = StateBuilder::new()
+        .add_code(&code)
+        .set_execution_timeout(Duration::from_millis(50))
+        .build();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    let start = Instant::now();
+    assert!(matches!(
+        chain.instantiate(&sender, 1, None, Empty {}, vec![]),
+        Err(VmError::VMError(WasmiVMError::DeadlineExceeded))
+    ));
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
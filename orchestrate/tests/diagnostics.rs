@@ -0,0 +1,61 @@
+mod common;
+
+use cosmwasm_orchestrate::{
+    vm::{Account, VmError, WasmAddressHandler},
+    ChainConfig, StateBuilder, WasmChain,
+};
+use cosmwasm_std::Empty;
+use cosmwasm_vm_wasmi::diagnostics::TrapDiagnostics;
+use cw20::{Cw20QueryMsg, TokenInfoResponse};
+
+#[test]
+fn trap_reports_active_functions() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let code = common::contract(
+        r#"(func $inner unreachable)
+           (func $outer call $inner)
+           (func (export "instantiate") (param i32 i32 i32) (result i32)
+             call $outer
+             i32.const 0)"#,
+    );
+    let state = StateBuilder::new()
+        .add_code(&code)
+        .enable_diagnostics()
+        .build();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    assert!(matches!(
+        chain.instantiate(&sender, 1, None, Empty {}, vec![]),
+        Err(VmError::Trap(TrapDiagnostics { frames, abort: None, .. }))
+            if frames == ["inner", "outer", "instantiate"]
+    ));
+}
+
+#[test]
+fn allocations_are_reported_per_entrypoint() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let mut state = StateBuilder::new().add_code(common::CW20).build();
+    state.enable_allocation_tracking();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    let (cw20, _) = chain
+        .instantiate(
+            &sender,
+            1,
+            None,
+            common::cw20_instantiate_msg([sender.to_string()], 1000),
+            vec![],
+        )
+        .unwrap();
+    let _: TokenInfoResponse = chain.query(&cw20, Cw20QueryMsg::TokenInfo {}).unwrap();
+
+    let allocations = chain.state.allocations.as_ref().unwrap();
+    assert_eq!(
+        allocations
+            .iter()
+            .map(|(contract, report)| (contract, report.entrypoint.as_str()))
+            .collect::<Vec<_>>(),
+        [(&cw20, "instantiate"), (&cw20, "query")]
+    );
+    assert!(allocations
+        .iter()
+        .all(|(_, report)| report.leaked.is_empty() && report.double_freed.is_empty()));
+}
@@ -0,0 +1,61 @@
+use cosmwasm_orchestrate::{
+    fetcher::{CosmosApi, CosmosFetcher},
+    vm::{Account, WasmAddressHandler},
+    StateBuilder,
+};
+
+mod common;
+
+const CONTRACT: &str = "wasm1contract";
+
+fn routes() -> Vec<(String, String)> {
+    let code = base64::encode(include_bytes!("../../fixtures/cw20_base.wasm"));
+    vec![
+        (
+            format!("/cosmwasm/wasm/v1/contract/{CONTRACT}"),
+            r#"{"address":"wasm1contract","contract_info":{"code_id":"12","creator":"wasm1creator","admin":"wasm1admin","label":"production","created":null,"ibc_port_id":"","extension":null}}"#.into(),
+        ),
+        (
+            "/cosmwasm/wasm/v1/code/12".into(),
+            format!(r#"{{"code_info":{{"code_id":"12","creator":"wasm1creator","data_hash":"","instantiate_permission":{{"permission":"Everybody","address":"","addresses":[]}}}},"data":"{code}"}}"#),
+        ),
+        (
+            format!("/cosmwasm/wasm/v1/contract/{CONTRACT}/state"),
+            r#"{"models":[{"key":"01","value":"AQ=="}],"pagination":{"next_key":"Ag==","total":"0"}}"#.into(),
+        ),
+        (
+            format!("/cosmwasm/wasm/v1/contract/{CONTRACT}/state?pagination.key=Ag%3D%3D"),
+            r#"{"models":[{"key":"02","value":"Ag=="}],"pagination":{"next_key":null,"total":"0"}}"#.into(),
+        ),
+        (
+            format!("/cosmos/bank/v1beta1/balances/{CONTRACT}"),
+            r#"{"balances":[{"denom":"uatom","amount":"1000"}],"pagination":{"next_key":null,"total":"1"}}"#.into(),
+        ),
+    ]
+}
+
+#[tokio::test]
+async fn fetched_contract_is_installed_with_its_state() {
+    let endpoint = common::lcd(routes());
+    let genesis = CosmosFetcher::fetch_contract(&endpoint, CONTRACT)
+        .await
+        .unwrap();
+    let mut state = StateBuilder::<WasmAddressHandler>::new()
+        .set_genesis(genesis)
+        .build();
+
+    let contract = Account::unchecked(CONTRACT);
+    let meta = &state.db.contracts[&contract];
+    assert_eq!(meta.code_id, 12);
+    assert_eq!(meta.admin, Some(Account::unchecked("wasm1admin")));
+    assert_eq!(meta.label, "production");
+    assert_eq!(
+        state.codes[&12].1,
+        include_bytes!("../../fixtures/cw20_base.wasm")
+    );
+    assert_eq!(
+        state.db.storage[&contract].data,
+        [(vec![1], vec![1]), (vec![2], vec![2])].into()
+    );
+    assert_eq!(state.db.bank.balance(&contract, "uatom"), 1000);
+}
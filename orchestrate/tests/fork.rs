@@ -0,0 +1,139 @@
+use cosmwasm_orchestrate::{
+    fetcher::{CosmosFetcher, RemoteFork},
+    vm::{Account, WasmAddressHandler},
+    ChainConfig, Error, StateBuilder, TxMessage, WasmChain,
+};
+use cosmwasm_std::{Coin, Uint128};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg};
+
+mod common;
+
+const CONTRACT: &str = "wasm1contract";
+
+/// Key of the cw20 balance of `account`.
+fn balance_key(account: &Account) -> Vec<u8> {
+    [b"\x00\x07balance", account.0.as_bytes()].concat()
+}
+
+fn routes(alice: &Account, bob: &Account) -> Vec<(String, String)> {
+    let code = base64::encode(include_bytes!("../../fixtures/cw20_base.wasm"));
+    let raw = |key: &[u8]| {
+        format!(
+            "/cosmwasm/wasm/v1/contract/{CONTRACT}/raw/{}",
+            base64::encode(key).replace('/', "%2F")
+        )
+    };
+    let balances = |account: &str, coins: &str| {
+        (
+            format!("/cosmos/bank/v1beta1/balances/{account}"),
+            format!(r#"{{"balances":[{coins}],"pagination":{{"next_key":null,"total":"0"}}}}"#),
+        )
+    };
+    vec![
+        (
+            format!("/cosmwasm/wasm/v1/contract/{CONTRACT}"),
+            r#"{"address":"wasm1contract","contract_info":{"code_id":"1","creator":"wasm1creator","admin":"","label":"cw20","created":null,"ibc_port_id":"","extension":null}}"#.into(),
+        ),
+        (
+            "/cosmwasm/wasm/v1/code/1".into(),
+            format!(r#"{{"code_info":{{"code_id":"1","creator":"wasm1creator","data_hash":""}},"data":"{code}"}}"#),
+        ),
+        (
+            raw(&balance_key(alice)),
+            format!(r#"{{"data":"{}"}}"#, base64::encode(br#""1000""#)),
+        ),
+        (raw(&balance_key(bob)), r#"{"data":null}"#.into()),
+        balances(alice.0.as_str(), r#"{"denom":"uatom","amount":"50"}"#),
+        balances(bob.0.as_str(), ""),
+        balances(CONTRACT, ""),
+    ]
+}
+
+fn balance(chain: &mut WasmChain, cw20: &Account, account: &Account) -> Uint128 {
+    let BalanceResponse { balance } = chain
+        .query(
+            cw20,
+            Cw20QueryMsg::Balance {
+                address: account.clone().into(),
+            },
+        )
+        .unwrap();
+    balance
+}
+
+#[test]
+fn fork_fetches_missing_storage_and_balances() {
+    let alice = Account::generate_from_seed::<WasmAddressHandler>("alice").unwrap();
+    let bob = Account::generate_from_seed::<WasmAddressHandler>("bob").unwrap();
+    let remote = RemoteFork::new::<CosmosFetcher>(&common::lcd(routes(&alice, &bob)));
+    let mut state = StateBuilder::<WasmAddressHandler>::new()
+        .set_genesis(remote.contract(CONTRACT).unwrap())
+        .build();
+    state.db.fork(remote);
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    let cw20 = Account::unchecked(CONTRACT);
+
+    assert_eq!(balance(&mut chain, &cw20, &alice), Uint128::new(1000));
+    assert!(chain.state.db.storage[&cw20]
+        .resolved
+        .contains(&balance_key(&alice)));
+
+    chain
+        .execute(
+            &alice,
+            &cw20,
+            Cw20ExecuteMsg::Transfer {
+                recipient: bob.clone().into(),
+                amount: Uint128::new(400),
+            },
+            vec![],
+        )
+        .unwrap();
+    // Local writes shadow the remote values.
+    assert_eq!(balance(&mut chain, &cw20, &alice), Uint128::new(600));
+    assert_eq!(balance(&mut chain, &cw20, &bob), Uint128::new(400));
+
+    chain
+        .tx(
+            &alice,
+            vec![TxMessage::BankSend {
+                to: bob.clone(),
+                amount: vec![Coin::new(10, "uatom")],
+            }],
+        )
+        .unwrap();
+    assert_eq!(chain.state.db.bank.balance(&alice, "uatom"), 40);
+    assert_eq!(chain.state.db.bank.balance(&bob, "uatom"), 10);
+    assert_eq!(chain.state.db.bank.supply["uatom"], 50);
+}
+
+#[test]
+fn fork_never_fetches_the_storage_of_local_contracts() {
+    let alice = Account::generate_from_seed::<WasmAddressHandler>("alice").unwrap();
+    let bob = Account::generate_from_seed::<WasmAddressHandler>("bob").unwrap();
+    let message = common::cw20_instantiate_msg([alice.to_string()], 1000);
+    let local = Account::generate::<WasmAddressHandler>(
+        common::CW20,
+        &serde_json::to_vec(&message).unwrap(),
+    )
+    .unwrap();
+    let mut routes = routes(&alice, &bob);
+    // The balances of the new contract are looked up before it is instantiated.
+    routes.push((
+        format!("/cosmos/bank/v1beta1/balances/{local}"),
+        r#"{"balances":[],"pagination":null}"#.into(),
+    ));
+    let remote = RemoteFork::new::<CosmosFetcher>(&common::lcd(routes));
+    assert_eq!(remote.raw(CONTRACT, b"missing"), Err(Error::Status(404)));
+    let mut state = StateBuilder::<WasmAddressHandler>::new()
+        .set_genesis(remote.contract(CONTRACT).unwrap())
+        .build();
+    state.db.fork(remote);
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+
+    let (cw20, _) = chain.instantiate(&alice, 1, None, message, vec![]).unwrap();
+    assert_eq!(cw20, local);
+    // The LCD answers the missing keys of the local contract with a 404.
+    assert_eq!(balance(&mut chain, &cw20, &bob), Uint128::zero());
+    assert_eq!(balance(&mut chain, &cw20, &alice), Uint128::new(1000));
+}
@@ -0,0 +1,104 @@
+use cosmwasm_orchestrate::{
+    genesis::Genesis,
+    vm::{Account, WasmAddressHandler},
+    ChainConfig, StateBuilder, WasmChain,
+};
+use cosmwasm_std::{Coin, Uint128};
+use cw20::{BalanceResponse, Cw20QueryMsg};
+
+mod common;
+
+#[test]
+fn genesis_import_reads_wasmd_export() {
+    let genesis = br#"{
+        "chain_id": "localwasm",
+        "app_state": {
+            "auth": { "accounts": [] },
+            "bank": {
+                "params": { "default_send_enabled": true },
+                "balances": [
+                    { "address": "wasm1alice", "coins": [{ "denom": "ustake", "amount": "100" }] },
+                    { "address": "wasm1bob", "coins": [{ "denom": "ustake", "amount": "50" }] }
+                ],
+                "supply": [{ "denom": "ustake", "amount": "1000" }],
+                "denom_metadata": []
+            },
+            "wasm": {
+                "params": { "code_upload_access": { "permission": "Everybody", "address": "" } },
+                "codes": [],
+                "contracts": [{
+                    "contract_address": "wasm1contract",
+                    "contract_info": {
+                        "code_id": "7",
+                        "creator": "wasm1alice",
+                        "admin": "",
+                        "label": "imported",
+                        "created": { "block_height": "42", "tx_index": "0" },
+                        "ibc_port_id": "",
+                        "extension": null
+                    },
+                    "contract_state": [{ "key": "0006636F6E666967", "value": "eyJvd25lciI6Indhc20xYWxpY2UifQ==" }],
+                    "contract_code_history": []
+                }],
+                "sequences": [{ "id_key": "BGxhc3RDb2RlSWQ=", "value": "8" }],
+                "gen_msgs": []
+            }
+        }
+    }"#;
+    let mut state = StateBuilder::<WasmAddressHandler>::new()
+        .load_genesis(genesis)
+        .unwrap()
+        .build();
+
+    let contract = Account::unchecked("wasm1contract");
+    let meta = &state.db.contracts[&contract];
+    assert_eq!(meta.code_id, 7);
+    assert_eq!(meta.admin, None);
+    assert_eq!(meta.label, "imported");
+    assert_eq!(
+        state.db.storage[&contract].data[&b"\x00\x06config"[..]],
+        br#"{"owner":"wasm1alice"}"#
+    );
+    assert_eq!(
+        state
+            .db
+            .bank
+            .balance(&Account::unchecked("wasm1alice"), "ustake"),
+        100
+    );
+    assert_eq!(state.db.bank.supply["ustake"], 1000);
+}
+
+#[test]
+fn genesis_export_roundtrips() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let (cw20, chain) = common::cw20_chain(
+        StateBuilder::new().add_balance(sender.clone(), Coin::new(100, "uatom")),
+        &sender,
+        Some(sender.clone()),
+    );
+
+    let genesis = serde_json::to_vec(&chain.state.export_genesis()).unwrap();
+    assert_eq!(
+        Genesis::from_slice(&genesis).unwrap(),
+        chain.state.export_genesis()
+    );
+    let state = StateBuilder::<WasmAddressHandler>::new()
+        .load_genesis(&genesis)
+        .unwrap()
+        .build();
+    assert_eq!(state.codes, chain.state.codes);
+    assert_eq!(state.db.contracts, chain.state.db.contracts);
+
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    assert_eq!(chain.state.db.bank.balance(&sender, "uatom"), 100);
+    let BalanceResponse { balance } = chain
+        .query(
+            &cw20,
+            Cw20QueryMsg::Balance {
+                address: sender.into(),
+            },
+        )
+        .unwrap();
+    assert_eq!(balance, Uint128::new(1000));
+}
@@ -0,0 +1,79 @@
+use cosmwasm_orchestrate::{
+    vm::{Account, Iter, WasmAddressHandler},
+    ChainConfig, StateBuilder, WasmChain,
+};
+use cosmwasm_std::Order;
+use cw20::{AllAccountsResponse, Cw20QueryMsg};
+use std::collections::BTreeMap;
+
+mod common;
+
+#[test]
+fn iterator_honours_bounds_and_order() {
+    let data = (1..=5_u8)
+        .map(|i| (vec![i], vec![i * 10]))
+        .collect::<BTreeMap<_, _>>();
+    let collect = |start: Option<u8>, end: Option<u8>, order| {
+        let mut iter = Iter {
+            start: start.map(|k| vec![k]),
+            end: end.map(|k| vec![k]),
+            order,
+            last: None,
+        };
+        core::iter::from_fn(|| iter.next(&data))
+            .map(|(key, _)| key[0])
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(collect(None, None, Order::Ascending), [1, 2, 3, 4, 5]);
+    assert_eq!(collect(None, None, Order::Descending), [5, 4, 3, 2, 1]);
+    assert_eq!(collect(Some(2), Some(4), Order::Ascending), [2, 3]);
+    assert_eq!(collect(Some(2), Some(4), Order::Descending), [3, 2]);
+    assert_eq!(collect(Some(4), Some(2), Order::Ascending), []);
+    assert_eq!(collect(Some(3), Some(3), Order::Descending), []);
+}
+
+#[test]
+fn contract_paginates_over_its_storage() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let mut accounts = (0..5)
+        .map(|i| {
+            Account::generate_from_seed::<WasmAddressHandler>(&format!("account-{i}"))
+                .unwrap()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    let state = StateBuilder::new().add_code(common::CW20).build();
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    let (cw20, _) = chain
+        .instantiate(
+            &sender,
+            1,
+            None,
+            common::cw20_instantiate_msg(accounts.iter().cloned(), 1),
+            vec![],
+        )
+        .unwrap();
+
+    let mut pages = Vec::new();
+    let mut start_after = None;
+    loop {
+        let AllAccountsResponse { accounts } = chain
+            .query(
+                &cw20,
+                Cw20QueryMsg::AllAccounts {
+                    start_after: start_after.clone(),
+                    limit: Some(2),
+                },
+            )
+            .unwrap();
+        if accounts.is_empty() {
+            break;
+        }
+        start_after = accounts.last().cloned();
+        pages.push(accounts);
+    }
+    accounts.sort();
+    assert_eq!(pages.len(), 3);
+    assert_eq!(pages.concat(), accounts);
+}
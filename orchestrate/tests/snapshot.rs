@@ -0,0 +1,62 @@
+use cosmwasm_orchestrate::{
+    vm::{Account, State, VmError, WasmAddressHandler, SNAPSHOT_VERSION},
+    ChainConfig, StateBuilder, WasmChain,
+};
+use cosmwasm_std::{Coin, Uint128};
+use cw20::{BalanceResponse, Cw20QueryMsg};
+
+mod common;
+
+#[test]
+fn snapshot_restores_the_state() {
+    let sender = Account::generate_from_seed::<WasmAddressHandler>("sender").unwrap();
+    let (cw20, chain) = common::cw20_chain(
+        StateBuilder::new()
+            .add_balance(sender.clone(), Coin::new(u128::from(u64::MAX) + 1, "uatom"))
+            .add_channel("channel-0".into()),
+        &sender,
+        Some(sender.clone()),
+    );
+
+    let path =
+        std::env::temp_dir().join(format!("orchestrate-snapshot-{}.json", std::process::id()));
+    chain.state.save(&path).unwrap();
+    let mut state = State::<(), WasmAddressHandler>::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(state.codes, chain.state.codes);
+    assert_eq!(state.db.contracts, chain.state.db.contracts);
+    assert_eq!(state.db.ibc, chain.state.db.ibc);
+    assert_eq!(
+        state.db.bank.balance(&sender, "uatom"),
+        u128::from(u64::MAX) + 1
+    );
+    let mut chain = WasmChain::new(state, ChainConfig::default());
+    let BalanceResponse { balance } = chain
+        .query(
+            &cw20,
+            Cw20QueryMsg::Balance {
+                address: sender.into(),
+            },
+        )
+        .unwrap();
+    assert_eq!(balance, Uint128::new(1000));
+}
+
+#[test]
+fn snapshot_rejects_other_versions() {
+    let mut snapshot = Vec::new();
+    StateBuilder::<WasmAddressHandler>::new()
+        .build()
+        .to_writer(&mut snapshot)
+        .unwrap();
+    let snapshot = String::from_utf8(snapshot).unwrap().replacen(
+        &format!("\"version\":{SNAPSHOT_VERSION}"),
+        "\"version\":0",
+        1,
+    );
+    assert!(matches!(
+        State::<(), WasmAddressHandler>::from_reader(snapshot.as_bytes()),
+        Err(VmError::UnsupportedSnapshotVersion(0))
+    ));
+}
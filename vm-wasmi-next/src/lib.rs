@@ -0,0 +1,1017 @@
+// lib.rs ---
+
+// Copyright (C) 2022 Hussein Ait-Lahcen
+
+// Author: Hussein Ait-Lahcen <hussein.aitlahcen@gmail.com>
+
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// Except as contained in this notice, the name(s) of the above copyright
+// holders shall not be used in advertising or otherwise to promote the sale,
+// use or other dealings in this Software without prior written authorization.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+// THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+#![no_std]
+#![feature(trait_alias)]
+#![cfg_attr(test, feature(assert_matches))]
+
+//! Execution backend running the contracts on the register-based [`wasmi`] interpreter.
+//!
+//! Gas is metered with the native fuel of the interpreter instead of the `env.gas` calls injected
+//! by `wasm-instrument`: the store is refueled with the gas left to the VM whenever the contract
+//! is entered and the fuel consumed is charged as [`VmGas::Instrumentation`] whenever it leaves,
+//! be it for a host function or on return. The host functions are the ones of
+//! [`cosmwasm_vm_wasmi::host_functions`], dispatched through [`WasmiFuelVM`].
+
+extern crate alloc;
+
+#[cfg(test)]
+mod semantic;
+
+use alloc::{string::String, vec, vec::Vec};
+use core::{cell::RefCell, marker::PhantomData};
+#[cfg(feature = "iterator")]
+use cosmwasm_std::Order;
+use cosmwasm_std::{
+    Addr, Binary, CanonicalAddr, Coin, ContractInfoResponse, Env, Event, MessageInfo, Reply,
+    SystemResult,
+};
+use cosmwasm_vm::{
+    executor::{
+        constants, AllocateCall, AsFunctionName, CosmwasmCallInput, CosmwasmCallWithoutInfoInput,
+        CosmwasmQueryResult, DeallocateCall, ExecutorError, QueryResult, Unit,
+    },
+    has::Has,
+    memory::{
+        MemoryReadError, MemoryWriteError, Pointable, ReadWriteMemory, ReadableMemory,
+        WritableMemory,
+    },
+    system::{CosmwasmContractMeta, SystemError},
+    tagged::Tagged,
+    transaction::{Transactional, TransactionalErrorOf},
+    vm::{
+        CryptoError, VMBase, VmAddressOf, VmCanonicalAddressOf, VmCodecOf, VmContracMetaOf,
+        VmErrorOf, VmGas, VmGasCheckpoint, VmMessageCustomOf, VmQueryCustomOf, VmStorageKeyOf,
+        VmStorageValueOf, VM,
+    },
+};
+use cosmwasm_vm_wasmi::{
+    diagnostics::Diagnostics,
+    host_functions::{host_function, signatures},
+    WasmiFunctionName, WasmiHostFunctionIndex, WasmiHostHooks, WasmiModuleName, WasmiVMError,
+};
+use legacy_wasmi::{RuntimeValue, ValueType};
+use wasmi::{
+    core::{HostError, TrapCode},
+    AsContextMut, Caller, Config, Engine, FuncType, Instance, Linker, Memory, Module, Store, Val,
+    ValType,
+};
+
+/// Gas accounting the fuel of the store is synchronized with.
+pub trait FuelMetering {
+    /// Gas left to the running contract, the store is refueled with it whenever the contract is
+    /// entered.
+    fn gas_left(&self) -> u64;
+}
+
+pub trait FuelBaseVM = 'static
+    + FuelMetering
+    + WasmiHostHooks
+    + VMBase<
+        ContractMeta = CosmwasmContractMeta<VmAddressOf<Self>>,
+        StorageKey = Vec<u8>,
+        StorageValue = Vec<u8>,
+    >
+    + Transactional
+    + Has<Env>
+    + Has<MessageInfo>
+where
+    VmAddressOf<Self>: Clone + TryFrom<String, Error = VmErrorOf<Self>> + Into<Addr>,
+    VmCanonicalAddressOf<Self>:
+        Clone + TryFrom<Vec<u8>, Error = VmErrorOf<Self>> + Into<CanonicalAddr>,
+    VmErrorOf<Self>: From<wasmi::Error>
+        + From<WasmiVMError>
+        + From<MemoryReadError>
+        + From<MemoryWriteError>
+        + From<ExecutorError>
+        + From<SystemError>
+        + From<TransactionalErrorOf<Self>>
+        + HostError;
+
+/// Data of the store: the VM running the contract along with the instance it runs.
+pub struct FuelState<T> {
+    pub vm: T,
+    instance: Option<Instance>,
+    memory: Option<Memory>,
+    /// Fuel of the store when it was last synchronized with the gas left to `vm`.
+    fuel: u64,
+}
+
+/// Context the contract runs in, either the [`Store`] or the [`Caller`] of a host function.
+pub trait FuelContext: AsContextMut {
+    type Vm;
+    fn state(&self) -> &FuelState<Self::Vm>;
+    fn state_mut(&mut self) -> &mut FuelState<Self::Vm>;
+    /// # Errors
+    ///
+    /// Fails if fuel metering is disabled on the engine.
+    fn fuel(&self) -> Result<u64, wasmi::Error>;
+    /// # Errors
+    ///
+    /// Fails if fuel metering is disabled on the engine.
+    fn set_fuel(&mut self, fuel: u64) -> Result<(), wasmi::Error>;
+}
+
+impl<T> FuelContext for Store<FuelState<T>> {
+    type Vm = T;
+    fn state(&self) -> &FuelState<T> {
+        self.data()
+    }
+    fn state_mut(&mut self) -> &mut FuelState<T> {
+        self.data_mut()
+    }
+    fn fuel(&self) -> Result<u64, wasmi::Error> {
+        self.get_fuel()
+    }
+    fn set_fuel(&mut self, fuel: u64) -> Result<(), wasmi::Error> {
+        Store::set_fuel(self, fuel)
+    }
+}
+
+impl<T> FuelContext for Caller<'_, FuelState<T>> {
+    type Vm = T;
+    fn state(&self) -> &FuelState<T> {
+        self.data()
+    }
+    fn state_mut(&mut self) -> &mut FuelState<T> {
+        self.data_mut()
+    }
+    fn fuel(&self) -> Result<u64, wasmi::Error> {
+        self.get_fuel()
+    }
+    fn set_fuel(&mut self, fuel: u64) -> Result<(), wasmi::Error> {
+        Caller::set_fuel(self, fuel)
+    }
+}
+
+/// The VM seen by the contract and the host functions. The context is behind a [`RefCell`] as
+/// writing to the memory of the contract requires a mutable access to the store.
+pub struct WasmiFuelVM<C>(pub RefCell<C>);
+
+pub struct FuelInput<'a, T>(String, Vec<Val>, PhantomData<(&'a (), T)>);
+
+pub struct FuelOutput<'a, T>(Option<Val>, PhantomData<(&'a (), T)>);
+
+impl<'a, C> TryFrom<FuelOutput<'a, WasmiFuelVM<C>>> for Unit
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    type Error = VmErrorOf<C::Vm>;
+    fn try_from(FuelOutput(value, _): FuelOutput<'a, WasmiFuelVM<C>>) -> Result<Self, Self::Error> {
+        match value {
+            None => Ok(Unit),
+            Some(_) => Err(WasmiVMError::ExpectedUnit.into()),
+        }
+    }
+}
+
+impl<'a, C> TryFrom<FuelOutput<'a, WasmiFuelVM<C>>> for u32
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    type Error = VmErrorOf<C::Vm>;
+    fn try_from(FuelOutput(value, _): FuelOutput<'a, WasmiFuelVM<C>>) -> Result<Self, Self::Error> {
+        // we target wasm32 so this will not truncate
+        #[allow(clippy::cast_sign_loss)]
+        match value {
+            Some(Val::I32(value)) => Ok(value as u32),
+            _ => Err(WasmiVMError::ExpectedPointer.into()),
+        }
+    }
+}
+
+impl<'a, C> TryFrom<AllocateCall<u32>> for FuelInput<'a, WasmiFuelVM<C>>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    type Error = VmErrorOf<C::Vm>;
+    #[allow(clippy::cast_possible_wrap)]
+    fn try_from(AllocateCall(ptr): AllocateCall<u32>) -> Result<Self, Self::Error> {
+        Ok(FuelInput(
+            AllocateCall::<u32>::NAME.into(),
+            vec![Val::I32(ptr as i32)],
+            PhantomData,
+        ))
+    }
+}
+
+impl<'a, C> TryFrom<DeallocateCall<u32>> for FuelInput<'a, WasmiFuelVM<C>>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    type Error = VmErrorOf<C::Vm>;
+    #[allow(clippy::cast_possible_wrap)]
+    fn try_from(DeallocateCall(ptr): DeallocateCall<u32>) -> Result<Self, Self::Error> {
+        Ok(FuelInput(
+            DeallocateCall::<u32>::NAME.into(),
+            vec![Val::I32(ptr as i32)],
+            PhantomData,
+        ))
+    }
+}
+
+impl<'a, I, C> TryFrom<CosmwasmCallInput<'a, u32, I>> for FuelInput<'a, WasmiFuelVM<C>>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+    I: AsFunctionName,
+{
+    type Error = VmErrorOf<C::Vm>;
+    #[allow(clippy::cast_possible_wrap)]
+    fn try_from(
+        CosmwasmCallInput(Tagged(env_ptr, _), Tagged(info_ptr, _), Tagged(msg_ptr, _), _): CosmwasmCallInput<'a, u32, I>,
+    ) -> Result<Self, Self::Error> {
+        Ok(FuelInput(
+            I::NAME.into(),
+            vec![
+                Val::I32(env_ptr as i32),
+                Val::I32(info_ptr as i32),
+                Val::I32(msg_ptr as i32),
+            ],
+            PhantomData,
+        ))
+    }
+}
+
+impl<'a, I, C> TryFrom<CosmwasmCallWithoutInfoInput<'a, u32, I>> for FuelInput<'a, WasmiFuelVM<C>>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+    I: AsFunctionName,
+{
+    type Error = VmErrorOf<C::Vm>;
+    #[allow(clippy::cast_possible_wrap)]
+    fn try_from(
+        CosmwasmCallWithoutInfoInput(Tagged(env_ptr, _), Tagged(msg_ptr, _), _): CosmwasmCallWithoutInfoInput<
+            'a,
+            u32,
+            I,
+        >,
+    ) -> Result<Self, Self::Error> {
+        Ok(FuelInput(
+            I::NAME.into(),
+            vec![Val::I32(env_ptr as i32), Val::I32(msg_ptr as i32)],
+            PhantomData,
+        ))
+    }
+}
+
+impl<C> VM for WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    fn raw_call<'a, O>(
+        &mut self,
+        FuelInput(function_name, function_args, _): Self::Input<'a>,
+    ) -> Result<O, Self::Error>
+    where
+        O: for<'x> TryFrom<Self::Output<'x>, Error = VmErrorOf<Self>>,
+    {
+        log::trace!("Function name: {}", function_name);
+        self.sync_fuel()?;
+        let context = self.0.get_mut();
+        let function = context
+            .state()
+            .instance
+            .and_then(|instance| instance.get_func(&*context, &function_name))
+            .ok_or(WasmiVMError::ExportNotFound(function_name))?;
+        let mut results = function
+            .ty(&*context)
+            .results()
+            .iter()
+            .copied()
+            .map(Val::default)
+            .collect::<Vec<_>>();
+        let outcome = function.call(&mut *context, &function_args, &mut results);
+        let synced = self.sync_fuel();
+        if let Err(e) = outcome {
+            return Err(self.call_failed(e));
+        }
+        synced?;
+        let value = match results.len() {
+            0 | 1 => results.pop(),
+            _ => return Err(WasmiVMError::InvalidValue.into()),
+        };
+        O::try_from(FuelOutput(value, PhantomData))
+    }
+}
+
+impl<C> WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    /// The VM running the contract.
+    pub fn vm(&mut self) -> &mut C::Vm {
+        &mut self.0.get_mut().state_mut().vm
+    }
+
+    /// Charge the fuel consumed since the last synchronization, then refuel the store with the
+    /// gas left.
+    fn sync_fuel(&mut self) -> Result<(), VmErrorOf<C::Vm>> {
+        let context = self.0.get_mut();
+        let consumed = context.state().fuel.saturating_sub(context.fuel()?);
+        self.charge_fuel(consumed)?;
+        let context = self.0.get_mut();
+        let fuel = context.state().vm.gas_left();
+        context.set_fuel(fuel)?;
+        context.state_mut().fuel = fuel;
+        Ok(())
+    }
+
+    fn charge_fuel(&mut self, mut fuel: u64) -> Result<(), VmErrorOf<C::Vm>> {
+        while fuel > 0 {
+            let metered = u32::try_from(fuel).unwrap_or(u32::MAX);
+            self.vm().charge(VmGas::Instrumentation { metered })?;
+            fuel -= u64::from(metered);
+        }
+        Ok(())
+    }
+
+    /// Recover the error of a failed call: the error raised by a host function if any, the out of
+    /// gas error of the VM if the store ran out of fuel, the trap otherwise.
+    fn call_failed(&mut self, error: wasmi::Error) -> VmErrorOf<C::Vm> {
+        if error.downcast_ref::<VmErrorOf<C::Vm>>().is_some() {
+            return error
+                .downcast::<VmErrorOf<C::Vm>>()
+                .expect("the error has been raised by a host function");
+        }
+        if error.as_trap_code() == Some(TrapCode::OutOfFuel) {
+            // Charging more than the gas left raises the error `env.gas` would have raised.
+            let gas_left = self.0.get_mut().state().vm.gas_left();
+            if let Err(e) = self.charge_fuel(gas_left.saturating_add(1)) {
+                return e;
+            }
+        }
+        error.into()
+    }
+
+    /// Run the host function registered under `index`, on behalf of the contract.
+    fn host_call(
+        &mut self,
+        index: WasmiHostFunctionIndex,
+        params: &[Val],
+        results: &mut [Val],
+    ) -> Result<(), VmErrorOf<C::Vm>> {
+        self.sync_fuel()?;
+        if self.deadline_exceeded() {
+            return Err(WasmiVMError::DeadlineExceeded.into());
+        }
+        let function =
+            host_function::<Self>(index).ok_or(WasmiVMError::HostFunctionNotFound(index))?;
+        let params = params
+            .iter()
+            .map(|param| match param {
+                Val::I32(value) => Ok(RuntimeValue::I32(*value)),
+                Val::I64(value) => Ok(RuntimeValue::I64(*value)),
+                _ => Err(WasmiVMError::InvalidHostSignature),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match (function(self, &params)?, results) {
+            (None, []) => {}
+            (Some(RuntimeValue::I32(value)), [result]) => *result = Val::I32(value),
+            (Some(RuntimeValue::I64(value)), [result]) => *result = Val::I64(value),
+            _ => return Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+        self.sync_fuel()
+    }
+
+    fn memory(&self) -> Result<Memory, WasmiVMError> {
+        self.0
+            .borrow()
+            .state()
+            .memory
+            .ok_or(WasmiVMError::MemoryNotExported)
+    }
+}
+
+impl<T> WasmiFuelVM<Store<FuelState<T>>> {
+    /// Give back the VM running the contract, dropping the store.
+    pub fn into_vm(self) -> T {
+        self.0.into_inner().into_data().vm
+    }
+}
+
+impl<C> VMBase for WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    type Input<'x> = FuelInput<'x, Self>;
+    type Output<'x> = FuelOutput<'x, Self>;
+    type QueryCustom = VmQueryCustomOf<C::Vm>;
+    type MessageCustom = VmMessageCustomOf<C::Vm>;
+    type ContractMeta = VmContracMetaOf<C::Vm>;
+    type Address = VmAddressOf<C::Vm>;
+    type CanonicalAddress = VmCanonicalAddressOf<C::Vm>;
+    type StorageKey = VmStorageKeyOf<C::Vm>;
+    type StorageValue = VmStorageValueOf<C::Vm>;
+    type Codec = VmCodecOf<C::Vm>;
+    type Error = VmErrorOf<C::Vm>;
+
+    fn running_contract_meta(&mut self) -> Result<Self::ContractMeta, Self::Error> {
+        self.charge(VmGas::GetContractMeta)?;
+        self.vm().running_contract_meta()
+    }
+
+    #[cfg(feature = "iterator")]
+    fn db_scan(
+        &mut self,
+        start: Option<Self::StorageKey>,
+        end: Option<Self::StorageKey>,
+        order: Order,
+    ) -> Result<u32, Self::Error> {
+        self.charge(VmGas::DbScan)?;
+        self.vm().db_scan(start, end, order)
+    }
+
+    #[cfg(feature = "iterator")]
+    fn db_next(
+        &mut self,
+        iterator_id: u32,
+    ) -> Result<(Self::StorageKey, Self::StorageValue), Self::Error> {
+        self.charge(VmGas::DbNext)?;
+        self.vm().db_next(iterator_id)
+    }
+
+    #[cfg(feature = "iterator")]
+    fn db_next_key(&mut self, iterator_id: u32) -> Result<Self::StorageKey, Self::Error> {
+        self.charge(VmGas::DbNextKey)?;
+        self.vm().db_next_key(iterator_id)
+    }
+
+    #[cfg(feature = "iterator")]
+    fn db_next_value(&mut self, iterator_id: u32) -> Result<Self::StorageValue, Self::Error> {
+        self.charge(VmGas::DbNextValue)?;
+        self.vm().db_next_value(iterator_id)
+    }
+
+    fn set_contract_meta(
+        &mut self,
+        address: Self::Address,
+        new_contract_meta: Self::ContractMeta,
+    ) -> Result<(), Self::Error> {
+        self.charge(VmGas::SetContractMeta)?;
+        self.vm().set_contract_meta(address, new_contract_meta)
+    }
+
+    fn contract_meta(&mut self, address: Self::Address) -> Result<Self::ContractMeta, Self::Error> {
+        self.charge(VmGas::GetContractMeta)?;
+        self.vm().contract_meta(address)
+    }
+
+    fn continue_query(
+        &mut self,
+        address: Self::Address,
+        message: &[u8],
+    ) -> Result<QueryResult, Self::Error> {
+        self.charge(VmGas::ContinueQuery)?;
+        self.vm().continue_query(address, message)
+    }
+
+    fn continue_execute(
+        &mut self,
+        address: Self::Address,
+        funds: Vec<Coin>,
+        message: &[u8],
+        event_handler: &mut dyn FnMut(Event),
+    ) -> Result<Option<Binary>, Self::Error> {
+        self.charge(VmGas::ContinueExecute {
+            nb_of_coins: u32::try_from(funds.len()).map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.vm()
+            .continue_execute(address, funds, message, event_handler)
+    }
+
+    fn continue_instantiate(
+        &mut self,
+        contract_meta: Self::ContractMeta,
+        funds: Vec<Coin>,
+        message: &[u8],
+        event_handler: &mut dyn FnMut(Event),
+    ) -> Result<(Self::Address, Option<Binary>), Self::Error> {
+        self.charge(VmGas::ContinueInstantiate {
+            nb_of_coins: u32::try_from(funds.len()).map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.vm()
+            .continue_instantiate(contract_meta, funds, message, event_handler)
+    }
+
+    fn continue_migrate(
+        &mut self,
+        address: Self::Address,
+        message: &[u8],
+        event_handler: &mut dyn FnMut(Event),
+    ) -> Result<Option<Binary>, Self::Error> {
+        self.charge(VmGas::ContinueMigrate)?;
+        self.vm().continue_migrate(address, message, event_handler)
+    }
+
+    fn continue_reply(
+        &mut self,
+        message: Reply,
+        event_handler: &mut dyn FnMut(Event),
+    ) -> Result<Option<Binary>, Self::Error> {
+        self.charge(VmGas::ContinueReply)?;
+        self.vm().continue_reply(message, event_handler)
+    }
+
+    fn query_custom(
+        &mut self,
+        query: Self::QueryCustom,
+    ) -> Result<SystemResult<CosmwasmQueryResult>, Self::Error> {
+        self.charge(VmGas::QueryCustom)?;
+        self.vm().query_custom(query)
+    }
+
+    fn message_custom(
+        &mut self,
+        message: Self::MessageCustom,
+        event_handler: &mut dyn FnMut(Event),
+    ) -> Result<Option<Binary>, Self::Error> {
+        self.charge(VmGas::MessageCustom)?;
+        self.vm().message_custom(message, event_handler)
+    }
+
+    fn query_raw(
+        &mut self,
+        address: Self::Address,
+        key: Self::StorageKey,
+    ) -> Result<Option<Self::StorageValue>, Self::Error> {
+        self.charge(VmGas::QueryRaw)?;
+        self.vm().query_raw(address, key)
+    }
+
+    fn transfer_from(
+        &mut self,
+        from: &Self::Address,
+        to: &Self::Address,
+        funds: &[Coin],
+    ) -> Result<(), Self::Error> {
+        self.charge(VmGas::Transfer {
+            nb_of_coins: u32::try_from(funds.len()).map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.vm().transfer_from(from, to, funds)
+    }
+
+    fn transfer(&mut self, to: &Self::Address, funds: &[Coin]) -> Result<(), Self::Error> {
+        self.charge(VmGas::Transfer {
+            nb_of_coins: u32::try_from(funds.len()).map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.vm().transfer(to, funds)
+    }
+
+    fn burn(&mut self, funds: &[Coin]) -> Result<(), Self::Error> {
+        self.charge(VmGas::Burn)?;
+        self.vm().burn(funds)
+    }
+
+    fn balance(&mut self, account: &Self::Address, denom: String) -> Result<Coin, Self::Error> {
+        self.charge(VmGas::Balance)?;
+        self.vm().balance(account, denom)
+    }
+
+    fn all_balance(&mut self, account: &Self::Address) -> Result<Vec<Coin>, Self::Error> {
+        self.charge(VmGas::AllBalance)?;
+        self.vm().all_balance(account)
+    }
+
+    fn query_info(&mut self, address: Self::Address) -> Result<ContractInfoResponse, Self::Error> {
+        self.charge(VmGas::QueryInfo)?;
+        self.vm().query_info(address)
+    }
+
+    fn debug(&mut self, message: Vec<u8>) -> Result<(), Self::Error> {
+        self.charge(VmGas::Debug)?;
+        self.vm().debug(message)
+    }
+
+    fn db_read(
+        &mut self,
+        key: Self::StorageKey,
+    ) -> Result<Option<Self::StorageValue>, Self::Error> {
+        self.charge(VmGas::DbRead)?;
+        self.vm().db_read(key)
+    }
+
+    fn db_write(
+        &mut self,
+        key: Self::StorageKey,
+        value: Self::StorageValue,
+    ) -> Result<(), Self::Error> {
+        self.vm().charge(VmGas::DbWrite)?;
+        self.vm().db_write(key, value)
+    }
+
+    fn db_remove(&mut self, key: Self::StorageKey) -> Result<(), Self::Error> {
+        self.vm().charge(VmGas::DbRemove)?;
+        self.vm().db_remove(key)
+    }
+
+    fn addr_validate(&mut self, input: &str) -> Result<Result<(), Self::Error>, Self::Error> {
+        self.vm().charge(VmGas::AddrValidate)?;
+        self.vm().addr_validate(input)
+    }
+
+    fn addr_canonicalize(
+        &mut self,
+        input: &str,
+    ) -> Result<Result<Self::CanonicalAddress, Self::Error>, Self::Error> {
+        self.vm().charge(VmGas::AddrCanonicalize)?;
+        self.vm().addr_canonicalize(input)
+    }
+
+    fn addr_humanize(
+        &mut self,
+        addr: &Self::CanonicalAddress,
+    ) -> Result<Result<Self::Address, Self::Error>, Self::Error> {
+        self.vm().charge(VmGas::AddrHumanize)?;
+        self.vm().addr_humanize(addr)
+    }
+
+    fn abort(&mut self, message: String) -> Result<(), Self::Error> {
+        self.vm().abort(message)
+    }
+
+    fn charge(&mut self, value: VmGas) -> Result<(), Self::Error> {
+        self.vm().charge(value)
+    }
+
+    fn gas_checkpoint_push(&mut self, checkpoint: VmGasCheckpoint) -> Result<(), Self::Error> {
+        self.vm().gas_checkpoint_push(checkpoint)
+    }
+
+    fn gas_checkpoint_pop(&mut self) -> Result<(), Self::Error> {
+        self.vm().gas_checkpoint_pop()
+    }
+
+    fn gas_ensure_available(&mut self) -> Result<(), Self::Error> {
+        self.vm().gas_ensure_available()
+    }
+
+    fn secp256k1_verify(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Secp256k1Verify)?;
+        self.vm()
+            .secp256k1_verify(message_hash, signature, public_key)
+    }
+
+    fn secp256k1_recover_pubkey(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        recovery_param: u8,
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Secp256k1RecoverPubkey)?;
+        self.vm()
+            .secp256k1_recover_pubkey(message_hash, signature, recovery_param)
+    }
+
+    fn ed25519_verify(
+        &mut self,
+        message: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Ed25519Verify)?;
+        self.vm().ed25519_verify(message, signature, public_key)
+    }
+
+    fn ed25519_batch_verify(
+        &mut self,
+        messages: &[&[u8]],
+        signatures: &[&[u8]],
+        public_keys: &[&[u8]],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Ed25519BatchVerify)?;
+        self.vm()
+            .ed25519_batch_verify(messages, signatures, public_keys)
+    }
+
+    fn secp256r1_verify(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Secp256r1Verify)?;
+        self.vm()
+            .secp256r1_verify(message_hash, signature, public_key)
+    }
+
+    fn secp256r1_recover_pubkey(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        recovery_param: u8,
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Secp256r1RecoverPubkey)?;
+        self.vm()
+            .secp256r1_recover_pubkey(message_hash, signature, recovery_param)
+    }
+
+    fn bls12_381_aggregate_g1(
+        &mut self,
+        g1s: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Bls12381AggregateG1 {
+            nb_of_points: u32::try_from(g1s.len() / constants::BLS12_381_G1_POINT_LENGTH)
+                .map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.vm().bls12_381_aggregate_g1(g1s)
+    }
+
+    fn bls12_381_aggregate_g2(
+        &mut self,
+        g2s: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Bls12381AggregateG2 {
+            nb_of_points: u32::try_from(g2s.len() / constants::BLS12_381_G2_POINT_LENGTH)
+                .map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.vm().bls12_381_aggregate_g2(g2s)
+    }
+
+    fn bls12_381_pairing_equality(
+        &mut self,
+        ps: &[u8],
+        qs: &[u8],
+        r: &[u8],
+        s: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Bls12381PairingEquality {
+            nb_of_pairs: u32::try_from(ps.len() / constants::BLS12_381_G1_POINT_LENGTH)
+                .map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.vm().bls12_381_pairing_equality(ps, qs, r, s)
+    }
+
+    fn bls12_381_hash_to_g1(
+        &mut self,
+        hash_function: u32,
+        message: &[u8],
+        dst: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Bls12381HashToG1)?;
+        self.vm().bls12_381_hash_to_g1(hash_function, message, dst)
+    }
+
+    fn bls12_381_hash_to_g2(
+        &mut self,
+        hash_function: u32,
+        message: &[u8],
+        dst: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.vm().charge(VmGas::Bls12381HashToG2)?;
+        self.vm().bls12_381_hash_to_g2(hash_function, message, dst)
+    }
+
+    fn keccak256(&mut self, message: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        self.vm().charge(VmGas::Keccak256 {
+            nb_of_bytes: u32::try_from(message.len())
+                .map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.vm().keccak256(message)
+    }
+
+    #[cfg(feature = "stargate")]
+    fn ibc_transfer(
+        &mut self,
+        channel_id: String,
+        to_address: String,
+        amount: Coin,
+        timeout: cosmwasm_std::IbcTimeout,
+    ) -> Result<(), Self::Error> {
+        self.vm().charge(VmGas::IbcTransfer)?;
+        self.vm()
+            .ibc_transfer(channel_id, to_address, amount, timeout)
+    }
+
+    #[cfg(feature = "stargate")]
+    fn ibc_send_packet(
+        &mut self,
+        channel_id: String,
+        data: Binary,
+        timeout: cosmwasm_std::IbcTimeout,
+    ) -> Result<(), Self::Error> {
+        self.vm().charge(VmGas::IbcSendPacket)?;
+        self.vm().ibc_send_packet(channel_id, data, timeout)
+    }
+
+    #[cfg(feature = "stargate")]
+    fn ibc_close_channel(&mut self, channel_id: String) -> Result<(), Self::Error> {
+        self.vm().charge(VmGas::IbcCloseChannel)?;
+        self.vm().ibc_close_channel(channel_id)
+    }
+}
+
+impl<C> Transactional for WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    type Error = TransactionalErrorOf<C::Vm>;
+    fn transaction_begin(&mut self) -> Result<(), Self::Error> {
+        self.vm().transaction_begin()
+    }
+    fn transaction_commit(&mut self) -> Result<(), Self::Error> {
+        self.vm().transaction_commit()
+    }
+    fn transaction_rollback(&mut self) -> Result<(), Self::Error> {
+        self.vm().transaction_rollback()
+    }
+}
+
+impl<C, U> Has<U> for WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: Has<U>,
+{
+    fn get(&self) -> U {
+        self.0.borrow().state().vm.get()
+    }
+}
+
+/// The hooks of the VM running the contract. The shadow call stack of [`Diagnostics`] is only
+/// maintained for modules instrumented with
+/// [`cosmwasm_vm_wasmi::diagnostics::instrument_call_stack`], and the deadline is only checked
+/// when the contract calls a host function.
+impl<C> WasmiHostHooks for WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    fn deadline_exceeded(&self) -> bool {
+        self.0.borrow().state().vm.deadline_exceeded()
+    }
+    fn diagnostics(&mut self) -> Option<&mut Diagnostics> {
+        self.vm().diagnostics()
+    }
+    fn coverage_counters(&mut self) -> Option<&mut [u64]> {
+        self.vm().coverage_counters()
+    }
+}
+
+impl<C> Pointable for WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    type Pointer = u32;
+}
+
+impl<C> ReadableMemory for WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    type Error = VmErrorOf<C::Vm>;
+    fn read(&self, offset: Self::Pointer, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        let memory = self.memory()?;
+        memory
+            .read(&*self.0.borrow(), offset as usize, buffer)
+            .map_err(|_| WasmiVMError::LowLevelMemoryReadError.into())
+    }
+    fn size(&self) -> usize {
+        self.memory()
+            .map_or(0, |memory| memory.data_size(&*self.0.borrow()))
+    }
+}
+
+impl<C> WritableMemory for WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+    type Error = VmErrorOf<C::Vm>;
+    fn write(&self, offset: Self::Pointer, buffer: &[u8]) -> Result<(), Self::Error> {
+        let memory = self.memory()?;
+        memory
+            .write(&mut *self.0.borrow_mut(), offset as usize, buffer)
+            .map_err(|_| WasmiVMError::LowLevelMemoryWriteError.into())
+    }
+}
+
+impl<C> ReadWriteMemory for WasmiFuelVM<C>
+where
+    C: FuelContext,
+    C::Vm: FuelBaseVM,
+{
+}
+
+/// Engine with fuel metering enabled, as required by [`new_wasmi_fuel_vm`].
+#[must_use]
+pub fn new_fuel_engine() -> Engine {
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    Engine::new(&config)
+}
+
+/// Instantiate a contract on the fuel backend, the returned VM owns the store the contract lives
+/// in. The start function, if any, is metered against the gas left to `vm`.
+///
+/// # Errors
+///
+/// Fails if the code is invalid, imports an unknown host function or doesn't export its memory.
+pub fn new_wasmi_fuel_vm<T>(
+    engine: &Engine,
+    code: &[u8],
+    vm: T,
+) -> Result<WasmiFuelVM<Store<FuelState<T>>>, VmErrorOf<T>>
+where
+    T: FuelBaseVM,
+{
+    let module = Module::new(engine, code)?;
+    let mut store = Store::new(
+        engine,
+        FuelState {
+            vm,
+            instance: None,
+            memory: None,
+            fuel: 0,
+        },
+    );
+    let fuel = store.state().vm.gas_left();
+    FuelContext::set_fuel(&mut store, fuel)?;
+    store.state_mut().fuel = fuel;
+    let instance = linker::<T>(engine)?
+        .instantiate(&mut store, &module)?
+        .start(&mut store)?;
+    let memory = instance
+        .get_memory(&store, "memory")
+        .ok_or(WasmiVMError::MemoryNotExported)?;
+    let state = store.state_mut();
+    state.instance = Some(instance);
+    state.memory = Some(memory);
+    Ok(WasmiFuelVM(RefCell::new(store)))
+}
+
+/// Define the host functions of [`signatures`], each call being dispatched to the shared
+/// implementation through a [`WasmiFuelVM`] over the [`Caller`].
+fn linker<T>(engine: &Engine) -> Result<Linker<FuelState<T>>, VmErrorOf<T>>
+where
+    T: FuelBaseVM,
+{
+    let value_type = |value_type: &ValueType| match value_type {
+        ValueType::I32 => ValType::I32,
+        ValueType::I64 => ValType::I64,
+        ValueType::F32 => ValType::F32,
+        ValueType::F64 => ValType::F64,
+    };
+    let mut linker = Linker::new(engine);
+    for (WasmiModuleName(module), functions) in signatures() {
+        for (WasmiFunctionName(name), (index, signature)) in functions {
+            let function_type = FuncType::new(
+                signature.params().iter().map(value_type),
+                signature.return_type().as_ref().map(value_type),
+            );
+            linker
+                .func_new(
+                    &module,
+                    &name,
+                    function_type,
+                    move |caller: Caller<'_, FuelState<T>>, params: &[Val], results: &mut [Val]| {
+                        WasmiFuelVM(RefCell::new(caller))
+                            .host_call(index, params, results)
+                            .map_err(wasmi::Error::host)
+                    },
+                )
+                .map_err(wasmi::Error::from)?;
+        }
+    }
+    Ok(linker)
+}
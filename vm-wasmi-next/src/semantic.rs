@@ -0,0 +1,629 @@
+extern crate std;
+
+use super::{
+    new_fuel_engine, new_wasmi_fuel_vm, FuelInput, FuelMetering, FuelOutput, FuelState,
+    WasmiFuelVM, WasmiHostHooks, WasmiVMError,
+};
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use core::{assert_matches::assert_matches, fmt::Display, marker::PhantomData};
+#[cfg(feature = "iterator")]
+use cosmwasm_std::Order;
+use cosmwasm_std::{
+    Addr, Binary, BlockInfo, CanonicalAddr, Coin, ContractInfo, ContractInfoResponse, Empty, Env,
+    Event, MessageInfo, Reply, SystemResult, Timestamp,
+};
+use cosmwasm_vm::{
+    codec::CosmwasmCodec,
+    executor::{
+        cosmwasm_call, CosmwasmExecutionResult, CosmwasmQueryResult, ExecuteCall, ExecuteResult,
+        ExecutorError, InstantiateCall, InstantiateResult, QueryCall, QueryResult, Unit,
+    },
+    has::Has,
+    memory::{MemoryReadError, MemoryWriteError},
+    system::{CosmwasmContractMeta, SystemError},
+    transaction::Transactional,
+    vm::{CryptoError, VMBase, VmGas, VmGasCheckpoint, VM},
+};
+use wasmi::{core::HostError, Store};
+
+#[derive(Debug)]
+enum TestVMError {
+    Interpreter(wasmi::Error),
+    VMError(WasmiVMError),
+    Aborted(String),
+    OutOfGas,
+    Unsupported,
+}
+impl From<wasmi::Error> for TestVMError {
+    fn from(e: wasmi::Error) -> Self {
+        Self::Interpreter(e)
+    }
+}
+impl From<WasmiVMError> for TestVMError {
+    fn from(e: WasmiVMError) -> Self {
+        Self::VMError(e)
+    }
+}
+impl From<SystemError> for TestVMError {
+    fn from(e: SystemError) -> Self {
+        Self::VMError(e.into())
+    }
+}
+impl From<ExecutorError> for TestVMError {
+    fn from(e: ExecutorError) -> Self {
+        Self::VMError(e.into())
+    }
+}
+impl From<MemoryReadError> for TestVMError {
+    fn from(e: MemoryReadError) -> Self {
+        Self::VMError(e.into())
+    }
+}
+impl From<MemoryWriteError> for TestVMError {
+    fn from(e: MemoryWriteError) -> Self {
+        Self::VMError(e.into())
+    }
+}
+impl Display for TestVMError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+impl HostError for TestVMError {}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Account(String);
+
+impl TryFrom<String> for Account {
+    type Error = TestVMError;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ok(Account(value))
+    }
+}
+
+impl From<Account> for Addr {
+    fn from(Account(value): Account) -> Self {
+        Addr::unchecked(value)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct CanonicalAccount(Vec<u8>);
+
+impl TryFrom<Vec<u8>> for CanonicalAccount {
+    type Error = TestVMError;
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        Ok(CanonicalAccount(value))
+    }
+}
+
+impl From<CanonicalAccount> for CanonicalAddr {
+    fn from(CanonicalAccount(value): CanonicalAccount) -> Self {
+        CanonicalAddr::from(value)
+    }
+}
+
+/// A VM recording what the contract asked for, the fuel is charged as consumed while any other
+/// operation costs one unit of gas.
+#[derive(Default)]
+struct TestVM {
+    gas_left: u64,
+    charged: Vec<VmGas>,
+    debug: Vec<Vec<u8>>,
+    storage: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl FuelMetering for TestVM {
+    fn gas_left(&self) -> u64 {
+        self.gas_left
+    }
+}
+
+impl WasmiHostHooks for TestVM {}
+
+impl Transactional for TestVM {
+    type Error = TestVMError;
+    fn transaction_begin(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    fn transaction_commit(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    fn transaction_rollback(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl Has<Env> for TestVM {
+    fn get(&self) -> Env {
+        Env {
+            block: BlockInfo {
+                height: 1,
+                time: Timestamp::from_seconds(1),
+                chain_id: "test".into(),
+            },
+            transaction: None,
+            contract: ContractInfo {
+                address: Addr::unchecked("contract"),
+            },
+        }
+    }
+}
+
+impl Has<MessageInfo> for TestVM {
+    fn get(&self) -> MessageInfo {
+        MessageInfo {
+            sender: Addr::unchecked("sender"),
+            funds: vec![],
+        }
+    }
+}
+
+impl VMBase for TestVM {
+    type Input<'x> = ();
+    type Output<'x> = ();
+    type QueryCustom = Empty;
+    type MessageCustom = Empty;
+    type ContractMeta = CosmwasmContractMeta<Account>;
+    type Address = Account;
+    type CanonicalAddress = CanonicalAccount;
+    type StorageKey = Vec<u8>;
+    type StorageValue = Vec<u8>;
+    type Codec = CosmwasmCodec;
+    type Error = TestVMError;
+
+    fn running_contract_meta(&mut self) -> Result<Self::ContractMeta, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    #[cfg(feature = "iterator")]
+    fn db_scan(
+        &mut self,
+        _: Option<Self::StorageKey>,
+        _: Option<Self::StorageKey>,
+        _: Order,
+    ) -> Result<u32, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    #[cfg(feature = "iterator")]
+    fn db_next(&mut self, _: u32) -> Result<(Self::StorageKey, Self::StorageValue), Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn set_contract_meta(
+        &mut self,
+        _: Self::Address,
+        _: Self::ContractMeta,
+    ) -> Result<(), Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn contract_meta(&mut self, _: Self::Address) -> Result<Self::ContractMeta, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn continue_query(&mut self, _: Self::Address, _: &[u8]) -> Result<QueryResult, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn continue_execute(
+        &mut self,
+        _: Self::Address,
+        _: Vec<Coin>,
+        _: &[u8],
+        _: &mut dyn FnMut(Event),
+    ) -> Result<Option<Binary>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn continue_instantiate(
+        &mut self,
+        _: Self::ContractMeta,
+        _: Vec<Coin>,
+        _: &[u8],
+        _: &mut dyn FnMut(Event),
+    ) -> Result<(Self::Address, Option<Binary>), Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn continue_migrate(
+        &mut self,
+        _: Self::Address,
+        _: &[u8],
+        _: &mut dyn FnMut(Event),
+    ) -> Result<Option<Binary>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn continue_reply(
+        &mut self,
+        _: Reply,
+        _: &mut dyn FnMut(Event),
+    ) -> Result<Option<Binary>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn query_custom(
+        &mut self,
+        _: Self::QueryCustom,
+    ) -> Result<SystemResult<CosmwasmQueryResult>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn message_custom(
+        &mut self,
+        _: Self::MessageCustom,
+        _: &mut dyn FnMut(Event),
+    ) -> Result<Option<Binary>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn query_raw(
+        &mut self,
+        _: Self::Address,
+        _: Self::StorageKey,
+    ) -> Result<Option<Self::StorageValue>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn transfer_from(
+        &mut self,
+        _: &Self::Address,
+        _: &Self::Address,
+        _: &[Coin],
+    ) -> Result<(), Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn transfer(&mut self, _: &Self::Address, _: &[Coin]) -> Result<(), Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn burn(&mut self, _: &[Coin]) -> Result<(), Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn balance(&mut self, _: &Self::Address, _: String) -> Result<Coin, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn all_balance(&mut self, _: &Self::Address) -> Result<Vec<Coin>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn query_info(&mut self, _: Self::Address) -> Result<ContractInfoResponse, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn debug(&mut self, message: Vec<u8>) -> Result<(), Self::Error> {
+        self.debug.push(message);
+        Ok(())
+    }
+
+    fn db_read(
+        &mut self,
+        key: Self::StorageKey,
+    ) -> Result<Option<Self::StorageValue>, Self::Error> {
+        Ok(self.storage.get(&key).cloned())
+    }
+
+    fn db_write(
+        &mut self,
+        key: Self::StorageKey,
+        value: Self::StorageValue,
+    ) -> Result<(), Self::Error> {
+        self.storage.insert(key, value);
+        Ok(())
+    }
+
+    fn db_remove(&mut self, key: Self::StorageKey) -> Result<(), Self::Error> {
+        self.storage.remove(&key);
+        Ok(())
+    }
+
+    fn addr_validate(&mut self, input: &str) -> Result<Result<(), Self::Error>, Self::Error> {
+        Ok(if input.is_empty() {
+            Err(TestVMError::Unsupported)
+        } else {
+            Ok(())
+        })
+    }
+
+    fn addr_canonicalize(
+        &mut self,
+        input: &str,
+    ) -> Result<Result<Self::CanonicalAddress, Self::Error>, Self::Error> {
+        Ok(Ok(CanonicalAccount(input.as_bytes().to_vec())))
+    }
+
+    fn addr_humanize(
+        &mut self,
+        CanonicalAccount(addr): &Self::CanonicalAddress,
+    ) -> Result<Result<Self::Address, Self::Error>, Self::Error> {
+        Ok(String::from_utf8(addr.clone())
+            .map(Account)
+            .map_err(|_| TestVMError::Unsupported))
+    }
+
+    fn abort(&mut self, message: String) -> Result<(), Self::Error> {
+        Err(TestVMError::Aborted(message))
+    }
+
+    fn charge(&mut self, value: VmGas) -> Result<(), Self::Error> {
+        let cost = match value {
+            VmGas::Instrumentation { metered } => u64::from(metered),
+            _ => 1,
+        };
+        self.gas_left = self
+            .gas_left
+            .checked_sub(cost)
+            .ok_or(TestVMError::OutOfGas)?;
+        self.charged.push(value);
+        Ok(())
+    }
+
+    fn gas_checkpoint_push(&mut self, _: VmGasCheckpoint) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn gas_checkpoint_pop(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn gas_ensure_available(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn secp256k1_verify(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn secp256k1_recover_pubkey(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: u8,
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn ed25519_verify(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn ed25519_batch_verify(
+        &mut self,
+        _: &[&[u8]],
+        _: &[&[u8]],
+        _: &[&[u8]],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn secp256r1_verify(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn secp256r1_recover_pubkey(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: u8,
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn bls12_381_aggregate_g1(
+        &mut self,
+        _: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn bls12_381_aggregate_g2(
+        &mut self,
+        _: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn bls12_381_pairing_equality(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn bls12_381_hash_to_g1(
+        &mut self,
+        _: u32,
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn bls12_381_hash_to_g2(
+        &mut self,
+        _: u32,
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    fn keccak256(&mut self, _: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    #[cfg(feature = "stargate")]
+    fn ibc_transfer(
+        &mut self,
+        _: String,
+        _: String,
+        _: Coin,
+        _: cosmwasm_std::IbcTimeout,
+    ) -> Result<(), Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    #[cfg(feature = "stargate")]
+    fn ibc_send_packet(
+        &mut self,
+        _: String,
+        _: Binary,
+        _: cosmwasm_std::IbcTimeout,
+    ) -> Result<(), Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+
+    #[cfg(feature = "stargate")]
+    fn ibc_close_channel(&mut self, _: String) -> Result<(), Self::Error> {
+        Err(TestVMError::Unsupported)
+    }
+}
+
+/// `hello` in a region at 8, and a few exports exercising the host functions.
+const CODE: &str = r#"(module
+  (import "env" "debug" (func $debug (param i32)))
+  (import "env" "addr_validate" (func $addr_validate (param i32) (result i32)))
+  (import "env" "abort" (func $abort (param i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 8) "\20\00\00\00\05\00\00\00\05\00\00\00")
+  (data (i32.const 32) "hello")
+  (func (export "greet") (result i32)
+    (call $debug (i32.const 8))
+    (call $addr_validate (i32.const 8)))
+  (func (export "spin") (loop (br 0)))
+  (func (export "fail") (call $abort (i32.const 8))))"#;
+
+type TestFuelVM = WasmiFuelVM<Store<FuelState<TestVM>>>;
+
+fn create_vm(gas_left: u64) -> TestFuelVM {
+    let code = wat::parse_str(CODE).unwrap();
+    new_wasmi_fuel_vm(
+        &new_fuel_engine(),
+        &code,
+        TestVM {
+            gas_left,
+            ..TestVM::default()
+        },
+    )
+    .unwrap()
+}
+
+fn call<O>(vm: &mut TestFuelVM, name: &str) -> Result<O, TestVMError>
+where
+    O: for<'x> TryFrom<FuelOutput<'x, TestFuelVM>, Error = TestVMError>,
+{
+    vm.raw_call::<O>(FuelInput(name.into(), vec![], PhantomData))
+}
+
+#[test]
+fn test_host_import_round_trip() {
+    let mut vm = create_vm(1_000_000);
+    assert_matches!(call::<u32>(&mut vm, "greet"), Ok(0));
+    let vm = vm.into_vm();
+    assert_eq!(vm.debug, [b"hello".to_vec()]);
+    assert!(vm.charged.contains(&VmGas::Debug));
+    assert!(vm.charged.contains(&VmGas::AddrValidate));
+}
+
+#[test]
+fn test_fuel_charged_as_instrumentation() {
+    let mut vm = create_vm(1_000_000);
+    call::<u32>(&mut vm, "greet").unwrap();
+    let vm = vm.into_vm();
+    let (metered, host) = vm
+        .charged
+        .iter()
+        .fold((0, 0), |(metered, host), gas| match gas {
+            VmGas::Instrumentation { metered: fuel } => (metered + u64::from(*fuel), host),
+            _ => (metered, host + 1),
+        });
+    assert!(metered > 0);
+    assert_eq!(vm.gas_left, 1_000_000 - metered - host);
+}
+
+#[test]
+fn test_out_of_fuel_is_out_of_gas() {
+    let mut vm = create_vm(10_000);
+    assert_matches!(call::<Unit>(&mut vm, "spin"), Err(TestVMError::OutOfGas));
+}
+
+#[test]
+fn test_host_error_returned_as_is() {
+    let mut vm = create_vm(1_000_000);
+    assert_matches!(
+        call::<Unit>(&mut vm, "fail"),
+        Err(TestVMError::Aborted(message)) if message == "hello"
+    );
+}
+
+#[test]
+fn test_cw20_round_trip() {
+    let mut vm = new_wasmi_fuel_vm(
+        &new_fuel_engine(),
+        include_bytes!("../../fixtures/cw20_base.wasm"),
+        TestVM {
+            gas_left: 100_000_000_000,
+            ..TestVM::default()
+        },
+    )
+    .unwrap();
+    assert_matches!(
+        cosmwasm_call::<InstantiateCall<Empty>, TestFuelVM>(
+            &mut vm,
+            br#"{"name":"Picasso","symbol":"PICA","decimals":12,"initial_balances":[{"address":"sender","amount":"1000"}]}"#,
+        )
+        .unwrap(),
+        InstantiateResult(CosmwasmExecutionResult::Ok(_))
+    );
+    assert_matches!(
+        cosmwasm_call::<ExecuteCall<Empty>, TestFuelVM>(
+            &mut vm,
+            br#"{"transfer":{"recipient":"recipient","amount":"400"}}"#,
+        )
+        .unwrap(),
+        ExecuteResult(CosmwasmExecutionResult::Ok(_))
+    );
+    let mut balance = |address: &str| {
+        cosmwasm_call::<QueryCall, TestFuelVM>(
+            &mut vm,
+            alloc::format!(r#"{{"balance":{{"address":"{address}"}}}}"#).as_bytes(),
+        )
+        .unwrap()
+    };
+    assert_eq!(
+        balance("sender"),
+        QueryResult(CosmwasmQueryResult::Ok(Binary(
+            br#"{"balance":"600"}"#.to_vec()
+        )))
+    );
+    assert_eq!(
+        balance("recipient"),
+        QueryResult(CosmwasmQueryResult::Ok(Binary(
+            br#"{"balance":"400"}"#.to_vec()
+        )))
+    );
+    assert!(vm.into_vm().charged.contains(&VmGas::DbWrite));
+}
@@ -0,0 +1,152 @@
+// allocation.rs ---
+
+// Copyright (C) 2022 Hussein Ait-Lahcen
+
+// Author: Hussein Ait-Lahcen <hussein.aitlahcen@gmail.com>
+
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// Except as contained in this notice, the name(s) of the above copyright
+// holders shall not be used in advertising or otherwise to promote the sale,
+// use or other dealings in this Software without prior written authorization.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+// THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+/// Diagnostic of the regions the host allocated in a contract memory around a top-level
+/// entrypoint, from its start until the next one starts or the tracker is finished.
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct AllocationReport {
+    /// Name of the entrypoint export.
+    pub entrypoint: String,
+    /// Regions owned by the host that have not been deallocated, along with their length.
+    /// The length of regions allocated by the contract (entrypoint results) is unknown and reported as `0`.
+    pub leaked: Vec<(u32, u32)>,
+    /// Pointers given to `deallocate` without being owned by the host.
+    pub double_freed: Vec<u32>,
+    /// Growth of the linear memory, in bytes, observed during the entrypoint.
+    pub peak_memory_growth: usize,
+}
+
+/// Tracks the ownership of the regions exchanged through `allocate`/`deallocate`.
+///
+/// Regions allocated by the host outside of an entrypoint are owned by the host until they are
+/// either given to an entrypoint, which consumes them, or deallocated. The region returned by an
+/// entrypoint is owned by the host, which must deallocate it. Regions allocated by host functions
+/// while an entrypoint is running are directly handed over to the contract and are not tracked.
+#[derive(Default, Clone, Debug)]
+pub struct AllocationTracker {
+    depth: u32,
+    memory_size_before: usize,
+    owned: BTreeMap<u32, u32>,
+    /// Report of the last top-level entrypoint, its leaks being collected once it is closed.
+    current: Option<AllocationReport>,
+    /// Pointers double freed before the first entrypoint, attributed to it.
+    double_freed: Vec<u32>,
+    reports: Vec<AllocationReport>,
+}
+
+impl AllocationTracker {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of the report of the last top-level entrypoint, if any.
+    #[must_use]
+    pub fn report(&self) -> Option<AllocationReport> {
+        self.current.clone().map(|report| self.leaking(report))
+    }
+
+    /// Close the report of the last top-level entrypoint and take the reports of all the
+    /// entrypoints executed since the tracker was last finished.
+    pub fn finish(&mut self) -> Vec<AllocationReport> {
+        self.close();
+        core::mem::take(&mut self.reports)
+    }
+
+    fn leaking(&self, report: AllocationReport) -> AllocationReport {
+        AllocationReport {
+            leaked: self
+                .owned
+                .iter()
+                .map(|(&pointer, &len)| (pointer, len))
+                .collect(),
+            ..report
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(report) = self.current.take() {
+            let report = self.leaking(report);
+            self.reports.push(report);
+        } else if !self.owned.is_empty() {
+            log::warn!("Regions leaked before entrypoint: {:?}", self.owned);
+        }
+        self.owned.clear();
+    }
+
+    pub(crate) fn allocated(&mut self, pointer: u32, len: u32) {
+        if self.depth == 0 {
+            self.owned.insert(pointer, len);
+        }
+    }
+
+    pub(crate) fn deallocated(&mut self, pointer: u32) {
+        if self.owned.remove(&pointer).is_none() {
+            log::warn!("Deallocating a region not owned by the host: {}", pointer);
+            match &mut self.current {
+                Some(report) => report.double_freed.push(pointer),
+                None => self.double_freed.push(pointer),
+            }
+        }
+    }
+
+    pub(crate) fn entrypoint_started(
+        &mut self,
+        entrypoint: &str,
+        arguments: &[u32],
+        memory_size: usize,
+    ) {
+        if self.depth == 0 {
+            for pointer in arguments {
+                self.owned.remove(pointer);
+            }
+            self.close();
+            self.current = Some(AllocationReport {
+                entrypoint: entrypoint.into(),
+                double_freed: core::mem::take(&mut self.double_freed),
+                ..AllocationReport::default()
+            });
+            self.memory_size_before = memory_size;
+        }
+        self.depth += 1;
+    }
+
+    pub(crate) fn entrypoint_finished(&mut self, result: Option<u32>, memory_size: usize) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            if let Some(report) = &mut self.current {
+                report.peak_memory_growth = memory_size.saturating_sub(self.memory_size_before);
+            }
+            if let Some(pointer) = result {
+                self.owned.insert(pointer, 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,360 @@
+// coverage.rs ---
+
+// Copyright (C) 2022 Hussein Ait-Lahcen
+
+// Author: Hussein Ait-Lahcen <hussein.aitlahcen@gmail.com>
+
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// Except as contained in this notice, the name(s) of the above copyright
+// holders shall not be used in advertising or otherwise to promote the sale,
+// use or other dealings in this Software without prior written authorization.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+// THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Basic-block coverage of contracts.
+//!
+//! [`instrument_coverage`] injects a call to the [`COVERAGE_HIT`] import at the beginning of each
+//! basic block, with the index of the block as argument. Blocks are identified by the offset of
+//! their first instruction in the original code section, the addressing used by the DWARF sections
+//! of a debug build, so that counters can be mapped back to source lines.
+
+use crate::diagnostics::{remap_elements, remap_exports, write_i32, write_name};
+use crate::lowering::{
+    count_imported_functions, skip_immediates, write_len, write_section, write_u32, LoweringError,
+    Reader, IMPORT_KIND_FUNCTION, OPCODE_CALL, OPCODE_I32_CONST, OPCODE_I32_EXTEND8_S,
+    OPCODE_I64_EXTEND32_S, OPCODE_MISC_MEMORY_COPY, OPCODE_MISC_MEMORY_FILL, OPCODE_PREFIX_MISC,
+    SECTION_CODE, SECTION_EXPORT, SECTION_IMPORT, SECTION_TYPE, WASM_HEADER,
+};
+use alloc::vec::Vec;
+
+/// Module of the import injected by [`instrument_coverage`].
+pub const COVERAGE_MODULE: &str = "env";
+/// `(func (param i32))`, called with the index of the basic block being entered.
+pub const COVERAGE_HIT: &str = "__coverage_hit";
+
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_START: u8 = 8;
+const SECTION_ELEMENT: u8 = 9;
+
+const NAME_SECTION: &[u8] = b"name";
+const NAME_SUBSECTION_FUNCTIONS: u8 = 1;
+const DEBUG_SECTION_PREFIX: &[u8] = b".debug_";
+
+const OPCODE_BLOCK: u8 = 0x02;
+pub(crate) const OPCODE_LOOP: u8 = 0x03;
+const OPCODE_IF: u8 = 0x04;
+const OPCODE_ELSE: u8 = 0x05;
+const OPCODE_END: u8 = 0x0B;
+const OPCODE_BR_IF: u8 = 0x0D;
+
+/// Basic blocks of a module instrumented with [`instrument_coverage`].
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct CoverageMap {
+    /// Offset of the first instruction of each block, relative to the payload of the code section.
+    /// Increasing, a block spans up to the offset of the next one.
+    pub offsets: Vec<u32>,
+    /// End of the last function body.
+    pub end: u32,
+}
+
+impl CoverageMap {
+    /// Index of the block containing the instruction at `offset`.
+    #[must_use]
+    pub fn block_at(&self, offset: u32) -> Option<usize> {
+        if offset >= self.end {
+            return None;
+        }
+        self.offsets
+            .partition_point(|start| *start <= offset)
+            .checked_sub(1)
+    }
+}
+
+/// Custom sections of a module, as `(name, payload)`.
+///
+/// # Errors
+///
+/// Fails if the module is malformed.
+pub fn custom_sections(code: &[u8]) -> Result<Vec<(&[u8], &[u8])>, LoweringError> {
+    let mut reader = Reader::new(code);
+    if reader.slice(WASM_HEADER.len())? != WASM_HEADER {
+        return Err(LoweringError::InvalidHeader);
+    }
+    let mut sections = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.len()?;
+        let payload = reader.slice(len)?;
+        if id == SECTION_CUSTOM {
+            let mut payload = Reader::new(payload);
+            let name_len = payload.len()?;
+            sections.push((payload.slice(name_len)?, payload.remaining()));
+        }
+    }
+    Ok(sections)
+}
+
+/// Inject the coverage counters of each basic block, see the [module documentation](self).
+///
+/// The `name` section is kept up to date while the DWARF sections are dropped, they no longer
+/// match the instrumented code.
+///
+/// # Errors
+///
+/// Fails if the module is malformed, uses unsupported constructs or has a start function, which
+/// would run before any host function can be called.
+pub fn instrument_coverage(code: &[u8]) -> Result<(Vec<u8>, CoverageMap), LoweringError> {
+    let mut map = CoverageMap::default();
+    let (output, end) = inject_import(
+        code,
+        COVERAGE_HIT,
+        &[0x60, 0x01, 0x7F, 0x00],
+        |body, offset, hit, remap| instrument_body(body, offset, hit, remap, &mut map),
+    )?;
+    map.end = end;
+    Ok((output, map))
+}
+
+/// Import the host function `env.<name>` of type `signature`, shifting the indices of the defined
+/// functions, and rewrite each function body with `instrument`. It is given the body, its offset
+/// relative to the payload of the code section, the index of the import and the function remapping.
+///
+/// Returns the instrumented module and the end of the last function body.
+#[allow(clippy::too_many_lines)]
+pub(crate) fn inject_import(
+    code: &[u8],
+    name: &str,
+    signature: &[u8],
+    mut instrument: impl FnMut(&[u8], u32, u32, &dyn Fn(u32) -> u32) -> Result<Vec<u8>, LoweringError>,
+) -> Result<(Vec<u8>, u32), LoweringError> {
+    let mut reader = Reader::new(code);
+    if reader.slice(WASM_HEADER.len())? != WASM_HEADER {
+        return Err(LoweringError::InvalidHeader);
+    }
+    let mut sections = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.len()?;
+        sections.push((id, reader.slice(len)?));
+    }
+    let type_count = sections
+        .iter()
+        .find(|(id, _)| *id == SECTION_TYPE)
+        .map(|(_, payload)| Reader::new(payload).u32())
+        .transpose()?
+        .unwrap_or(0);
+    let imported = sections
+        .iter()
+        .find(|(id, _)| *id == SECTION_IMPORT)
+        .map(|(_, payload)| count_imported_functions(payload))
+        .transpose()?
+        .unwrap_or(0);
+    let import = imported;
+    let remap = |function: u32| {
+        if function < imported {
+            function
+        } else {
+            function + 1
+        }
+    };
+
+    let mut end = 0;
+    let mut output = Vec::with_capacity(code.len() * 2);
+    output.extend_from_slice(&WASM_HEADER);
+    let mut types_written = false;
+    let mut imports_written = false;
+    for &(id, payload) in &sections {
+        if id != SECTION_CUSTOM && id > SECTION_TYPE && !types_written {
+            // The module doesn't declare any type.
+            let mut types = Vec::new();
+            write_u32(&mut types, 1);
+            types.extend_from_slice(signature);
+            write_section(&mut output, SECTION_TYPE, &types)?;
+            types_written = true;
+        }
+        if id != SECTION_CUSTOM && id > SECTION_IMPORT && !imports_written {
+            // The module doesn't import anything.
+            let mut imports = Vec::new();
+            write_u32(&mut imports, 1);
+            write_import(&mut imports, name, type_count)?;
+            write_section(&mut output, SECTION_IMPORT, &imports)?;
+            imports_written = true;
+        }
+        let mut reader = Reader::new(payload);
+        match id {
+            SECTION_CUSTOM => {
+                let name_len = reader.len()?;
+                let name = reader.slice(name_len)?;
+                if name == NAME_SECTION {
+                    let mut rewritten = Vec::with_capacity(payload.len());
+                    write_name(&mut rewritten, name)?;
+                    remap_function_names(&mut reader, &mut rewritten, remap)?;
+                    write_section(&mut output, id, &rewritten)?;
+                } else if !name.starts_with(DEBUG_SECTION_PREFIX) {
+                    write_section(&mut output, id, payload)?;
+                }
+            }
+            SECTION_TYPE => {
+                let mut rewritten = Vec::with_capacity(payload.len() + signature.len());
+                write_u32(&mut rewritten, reader.u32()? + 1);
+                rewritten.extend_from_slice(reader.remaining());
+                rewritten.extend_from_slice(signature);
+                write_section(&mut output, id, &rewritten)?;
+                types_written = true;
+            }
+            SECTION_IMPORT => {
+                let mut rewritten = Vec::with_capacity(payload.len() + 32);
+                write_u32(&mut rewritten, reader.u32()? + 1);
+                rewritten.extend_from_slice(reader.remaining());
+                write_import(&mut rewritten, name, type_count)?;
+                write_section(&mut output, id, &rewritten)?;
+                imports_written = true;
+            }
+            SECTION_EXPORT => {
+                let rewritten = remap_exports(payload, remap, |_, _| {})?;
+                write_section(&mut output, id, &rewritten)?;
+            }
+            SECTION_START => return Err(LoweringError::UnsupportedStartSection),
+            SECTION_ELEMENT => {
+                write_section(&mut output, id, &remap_elements(payload, remap)?)?;
+            }
+            SECTION_CODE => {
+                let mut rewritten = Vec::with_capacity(payload.len() * 2);
+                let count = reader.u32()?;
+                write_u32(&mut rewritten, count);
+                for _ in 0..count {
+                    let len = reader.len()?;
+                    let offset = u32::try_from(reader.position)
+                        .map_err(|_| LoweringError::InvalidSection)?;
+                    let body = instrument(reader.slice(len)?, offset, import, &remap)?;
+                    write_len(&mut rewritten, body.len())?;
+                    rewritten.extend_from_slice(&body);
+                }
+                end = u32::try_from(reader.position).map_err(|_| LoweringError::InvalidSection)?;
+                write_section(&mut output, id, &rewritten)?;
+            }
+            _ => write_section(&mut output, id, payload)?,
+        }
+    }
+    Ok((output, end))
+}
+
+fn write_import(output: &mut Vec<u8>, name: &str, type_index: u32) -> Result<(), LoweringError> {
+    write_name(output, COVERAGE_MODULE.as_bytes())?;
+    write_name(output, name.as_bytes())?;
+    output.push(IMPORT_KIND_FUNCTION);
+    write_u32(output, type_index);
+    Ok(())
+}
+
+/// Shift the function names, the other subsections are dropped.
+fn remap_function_names(
+    reader: &mut Reader,
+    output: &mut Vec<u8>,
+    remap: impl Fn(u32) -> u32,
+) -> Result<(), LoweringError> {
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.len()?;
+        let mut subsection = Reader::new(reader.slice(len)?);
+        if id == NAME_SUBSECTION_FUNCTIONS {
+            let mut rewritten = Vec::with_capacity(len);
+            let count = subsection.u32()?;
+            write_u32(&mut rewritten, count);
+            for _ in 0..count {
+                write_u32(&mut rewritten, remap(subsection.u32()?));
+                let name_len = subsection.len()?;
+                write_name(&mut rewritten, subsection.slice(name_len)?)?;
+            }
+            output.push(id);
+            write_len(output, rewritten.len())?;
+            output.extend_from_slice(&rewritten);
+        }
+    }
+    Ok(())
+}
+
+/// Inject a counter at the beginning of each basic block of a function body located at `offset`.
+fn instrument_body(
+    body: &[u8],
+    offset: u32,
+    hit: u32,
+    remap: &dyn Fn(u32) -> u32,
+    map: &mut CoverageMap,
+) -> Result<Vec<u8>, LoweringError> {
+    let mut reader = Reader::new(body);
+    for _ in 0..reader.u32()? {
+        reader.u32()?;
+        reader.byte()?;
+    }
+    let mut output = Vec::with_capacity(body.len() * 2);
+    output.extend_from_slice(&body[..reader.position]);
+    let mut counter = |output: &mut Vec<u8>, position: usize| {
+        let block = i32::try_from(map.offsets.len()).map_err(|_| LoweringError::InvalidSection)?;
+        let position = u32::try_from(position).map_err(|_| LoweringError::InvalidSection)?;
+        map.offsets.push(offset + position);
+        output.push(OPCODE_I32_CONST);
+        write_i32(output, block);
+        output.push(OPCODE_CALL);
+        write_u32(output, hit);
+        Ok::<_, LoweringError>(())
+    };
+    // The entry block includes the local declarations, addressed by DWARF as the function start.
+    counter(&mut output, 0)?;
+    let mut depth = 0_u32;
+    while !reader.is_empty() {
+        let start = reader.position;
+        let opcode = reader.byte()?;
+        match opcode {
+            OPCODE_CALL => {
+                output.push(OPCODE_CALL);
+                write_u32(&mut output, remap(reader.u32()?));
+                continue;
+            }
+            OPCODE_I32_EXTEND8_S..=OPCODE_I64_EXTEND32_S => {}
+            OPCODE_PREFIX_MISC => match reader.u32()? {
+                OPCODE_MISC_MEMORY_COPY => {
+                    reader.slice(2)?;
+                }
+                OPCODE_MISC_MEMORY_FILL => {
+                    reader.byte()?;
+                }
+                other => return Err(LoweringError::UnsupportedPrefixedOpcode(opcode, other)),
+            },
+            _ => skip_immediates(&mut reader, opcode)?,
+        }
+        output.extend_from_slice(&body[start..reader.position]);
+        match opcode {
+            OPCODE_BLOCK | OPCODE_LOOP | OPCODE_IF => {
+                depth += 1;
+                counter(&mut output, reader.position)?;
+            }
+            OPCODE_ELSE | OPCODE_BR_IF => counter(&mut output, reader.position)?,
+            // The end of the function.
+            OPCODE_END if depth == 0 => {}
+            OPCODE_END => {
+                depth -= 1;
+                counter(&mut output, reader.position)?;
+            }
+            // The code following an unconditional branch (`br`, `br_table`, `return` or
+            // `unreachable`) is dead until the next `end` or `else`, no block is started.
+            _ => {}
+        }
+    }
+    Ok(output)
+}
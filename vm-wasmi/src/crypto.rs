@@ -0,0 +1,50 @@
+// crypto.rs ---
+
+// Copyright (C) 2022 Hussein Ait-Lahcen
+
+// Author: Hussein Ait-Lahcen <hussein.aitlahcen@gmail.com>
+
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// Except as contained in this notice, the name(s) of the above copyright
+// holders shall not be used in advertising or otherwise to promote the sale,
+// use or other dealings in this Software without prior written authorization.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+// THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Errors of the host cryptographic functions.
+
+use cosmwasm_vm::vm::CryptoError;
+
+/// Map an error of `cosmwasm-crypto` to the error handed over to the contract.
+#[must_use]
+pub fn crypto_error(error: cosmwasm_crypto::CryptoError) -> CryptoError {
+    match error {
+        cosmwasm_crypto::CryptoError::BatchErr { .. } => CryptoError::Batch,
+        cosmwasm_crypto::CryptoError::GenericErr { .. } => CryptoError::Generic,
+        cosmwasm_crypto::CryptoError::InvalidHashFormat { .. } => CryptoError::InvalidHashFormat,
+        cosmwasm_crypto::CryptoError::InvalidPubkeyFormat { .. } => {
+            CryptoError::InvalidPubkeyFormat
+        }
+        cosmwasm_crypto::CryptoError::InvalidSignatureFormat { .. } => {
+            CryptoError::InvalidSignatureFormat
+        }
+        cosmwasm_crypto::CryptoError::InvalidRecoveryParam { .. } => {
+            CryptoError::InvalidRecoveryParam
+        }
+    }
+}
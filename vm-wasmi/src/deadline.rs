@@ -0,0 +1,104 @@
+// deadline.rs ---
+
+// Copyright (C) 2022 Hussein Ait-Lahcen
+
+// Author: Hussein Ait-Lahcen <hussein.aitlahcen@gmail.com>
+
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// Except as contained in this notice, the name(s) of the above copyright
+// holders shall not be used in advertising or otherwise to promote the sale,
+// use or other dealings in this Software without prior written authorization.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+// THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Execution deadline of contracts without gas metering.
+//!
+//! [`instrument_deadline`] injects a call to the [`DEADLINE_CHECK`] import at the entry of each
+//! function and at the header of each loop, the only places an execution can spend an unbounded
+//! amount of time. The host aborts the execution with [`crate::WasmiVMError::DeadlineExceeded`]
+//! once [`crate::WasmiModuleExecutor::deadline_exceeded`] holds.
+
+use crate::coverage::{inject_import, OPCODE_LOOP};
+use crate::lowering::{
+    skip_immediates, write_u32, LoweringError, Reader, OPCODE_CALL, OPCODE_I32_EXTEND8_S,
+    OPCODE_I64_EXTEND32_S, OPCODE_MISC_MEMORY_COPY, OPCODE_MISC_MEMORY_FILL, OPCODE_PREFIX_MISC,
+};
+use alloc::vec::Vec;
+
+/// `(func)`, imported from [`crate::coverage::COVERAGE_MODULE`] and called on function entries
+/// and loop headers.
+pub const DEADLINE_CHECK: &str = "__deadline_check";
+
+/// Inject the deadline checks, see the [module documentation](self).
+///
+/// # Errors
+///
+/// Fails if the module is malformed, uses unsupported constructs or has a start function.
+pub fn instrument_deadline(code: &[u8]) -> Result<Vec<u8>, LoweringError> {
+    let (output, _) = inject_import(
+        code,
+        DEADLINE_CHECK,
+        &[0x60, 0x00, 0x00],
+        |body, _, check, remap| instrument_body(body, check, remap),
+    )?;
+    Ok(output)
+}
+
+fn instrument_body(
+    body: &[u8],
+    check: u32,
+    remap: &dyn Fn(u32) -> u32,
+) -> Result<Vec<u8>, LoweringError> {
+    let mut reader = Reader::new(body);
+    for _ in 0..reader.u32()? {
+        reader.u32()?;
+        reader.byte()?;
+    }
+    let mut output = Vec::with_capacity(body.len() + 16);
+    output.extend_from_slice(&body[..reader.position]);
+    output.push(OPCODE_CALL);
+    write_u32(&mut output, check);
+    while !reader.is_empty() {
+        let start = reader.position;
+        let opcode = reader.byte()?;
+        match opcode {
+            OPCODE_CALL => {
+                output.push(OPCODE_CALL);
+                write_u32(&mut output, remap(reader.u32()?));
+                continue;
+            }
+            OPCODE_I32_EXTEND8_S..=OPCODE_I64_EXTEND32_S => {}
+            OPCODE_PREFIX_MISC => match reader.u32()? {
+                OPCODE_MISC_MEMORY_COPY => {
+                    reader.slice(2)?;
+                }
+                OPCODE_MISC_MEMORY_FILL => {
+                    reader.byte()?;
+                }
+                other => return Err(LoweringError::UnsupportedPrefixedOpcode(opcode, other)),
+            },
+            _ => skip_immediates(&mut reader, opcode)?,
+        }
+        output.extend_from_slice(&body[start..reader.position]);
+        if opcode == OPCODE_LOOP {
+            output.push(OPCODE_CALL);
+            write_u32(&mut output, check);
+        }
+    }
+    Ok(output)
+}
@@ -0,0 +1,513 @@
+// diagnostics.rs ---
+
+// Copyright (C) 2022 Hussein Ait-Lahcen
+
+// Author: Hussein Ait-Lahcen <hussein.aitlahcen@gmail.com>
+
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// Except as contained in this notice, the name(s) of the above copyright
+// holders shall not be used in advertising or otherwise to promote the sale,
+// use or other dealings in this Software without prior written authorization.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+// THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Symbolized backtraces of contract traps.
+//!
+//! The interpreter doesn't expose its call stack, a shadow one is maintained instead.
+//! Each function `f` of the module gets a wrapper calling `__trace_enter(f)`, then `f` and
+//! finally `__trace_leave()`. Every reference to `f` (calls, exports and table elements) is
+//! redirected to its wrapper. As a trap unwinds without reaching `__trace_leave`, the shadow stack
+//! holds the frames that were active when it happened.
+
+use crate::lowering::{
+    skip_immediates, write_len, write_section, write_u32, LoweringError, Reader,
+    IMPORT_KIND_FUNCTION, OPCODE_CALL, OPCODE_I32_CONST, SECTION_CODE, SECTION_FUNCTION,
+    SECTION_IMPORT, SECTION_TYPE, WASM_HEADER,
+};
+use crate::profiler::Profile;
+use alloc::{collections::BTreeMap, format, string::String, vec, vec::Vec};
+
+/// Module of the imports injected by [`instrument_call_stack`].
+pub const TRACE_MODULE: &str = "env";
+/// `(func (param i32))`, pushes the original index of the function being entered.
+pub const TRACE_ENTER: &str = "__trace_enter";
+/// `(func)`, pops the function being left.
+pub const TRACE_LEAVE: &str = "__trace_leave";
+
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_START: u8 = 8;
+const SECTION_ELEMENT: u8 = 9;
+
+const NAME_SECTION: &[u8] = b"name";
+const NAME_SUBSECTION_FUNCTIONS: u8 = 1;
+
+const OPCODE_LOCAL_GET: u8 = 0x20;
+const OPCODE_END: u8 = 0x0B;
+
+/// Diagnostic of a failed contract call.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrapDiagnostics {
+    /// Error reported by the interpreter.
+    pub message: String,
+    /// Functions active when the call failed, innermost first.
+    pub frames: Vec<String>,
+    /// Last message given by the contract to `abort`, if any.
+    pub abort: Option<String>,
+}
+
+/// Shadow call stack of a module instrumented with [`instrument_call_stack`].
+#[derive(Default, Clone, Debug)]
+pub struct Diagnostics {
+    names: BTreeMap<u32, String>,
+    host_names: BTreeMap<usize, String>,
+    stack: Vec<u32>,
+    host_failed: bool,
+    aborting: bool,
+    unwinding: bool,
+    last_abort: Option<String>,
+    last_trap: Option<TrapDiagnostics>,
+    profile: Option<Profile>,
+}
+
+impl Diagnostics {
+    #[must_use]
+    pub fn new(names: BTreeMap<u32, String>, host_names: BTreeMap<usize, String>) -> Self {
+        Diagnostics {
+            names,
+            host_names,
+            ..Default::default()
+        }
+    }
+
+    /// Start profiling the executions, see [`crate::profiler`].
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Profile::new(self.names.clone(), self.host_names.clone()));
+    }
+
+    /// Profile accumulated since [`Diagnostics::enable_profiling`].
+    #[must_use]
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Diagnostic of the last failed call, including calls failing on a host error such as `abort`.
+    #[must_use]
+    pub fn last_trap(&self) -> Option<&TrapDiagnostics> {
+        self.last_trap.as_ref()
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub(crate) fn enter(&mut self, function: u32) {
+        self.unwinding = false;
+        self.stack.push(function);
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.unwinding = false;
+        self.stack.pop();
+    }
+
+    pub(crate) fn metered(&mut self, metered: u32) {
+        if let Some(profile) = &mut self.profile {
+            profile.metered(&self.stack, metered);
+        }
+    }
+
+    pub(crate) fn host_called(&mut self, index: usize) {
+        if let Some(profile) = &mut self.profile {
+            profile.host_called(&self.stack, index);
+        }
+    }
+
+    pub(crate) fn aborted(&mut self, message: String) {
+        self.last_abort = Some(message);
+        self.aborting = true;
+    }
+
+    /// Record that a host function failed, the error isn't an interpreter trap unless the
+    /// contract called `abort`.
+    pub(crate) fn host_failed(&mut self) {
+        self.host_failed = !core::mem::take(&mut self.aborting);
+    }
+
+    /// Called when a call started at `depth` fails, returns the diagnostic to report instead of
+    /// the error if the failure is an interpreter trap or an `abort`.
+    ///
+    /// Only the innermost failing call records the frames, the outer ones only unwind the stack
+    /// and keep their error, which already carries the diagnostic.
+    pub(crate) fn failed(&mut self, depth: usize, message: String) -> Option<TrapDiagnostics> {
+        let host_failed = core::mem::take(&mut self.host_failed);
+        let innermost = !core::mem::replace(&mut self.unwinding, true);
+        if innermost {
+            let frames = self
+                .stack
+                .iter()
+                .rev()
+                .map(|function| {
+                    self.names
+                        .get(function)
+                        .cloned()
+                        .unwrap_or_else(|| format!("<function {function}>"))
+                })
+                .collect();
+            self.last_trap = Some(TrapDiagnostics {
+                message,
+                frames,
+                abort: self.last_abort.clone(),
+            });
+        }
+        self.stack.truncate(depth);
+        if innermost && !host_failed {
+            self.last_trap.clone()
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn started(&mut self, depth: usize) {
+        if depth == 0 {
+            self.last_abort = None;
+            self.last_trap = None;
+        }
+        self.host_failed = false;
+        self.aborting = false;
+        self.unwinding = false;
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn write_i32(output: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_name(output: &mut Vec<u8>, name: &[u8]) -> Result<(), LoweringError> {
+    write_len(output, name.len())?;
+    output.extend_from_slice(name);
+    Ok(())
+}
+
+/// Instrument a module to maintain a shadow call stack through the [`TRACE_ENTER`] and
+/// [`TRACE_LEAVE`] imports.
+///
+/// Returns the instrumented module along with the name of the functions, indexed as in the original
+/// module, taken from the `name` section or the exports.
+///
+/// # Errors
+///
+/// Fails if the module is malformed or uses post-MVP constructs, see [`crate::lowering::lower_to_mvp`].
+#[allow(clippy::too_many_lines)]
+pub fn instrument_call_stack(
+    code: &[u8],
+) -> Result<(Vec<u8>, BTreeMap<u32, String>), LoweringError> {
+    let mut reader = Reader::new(code);
+    if reader.slice(WASM_HEADER.len())? != WASM_HEADER {
+        return Err(LoweringError::InvalidHeader);
+    }
+    let mut sections = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.len()?;
+        sections.push((id, reader.slice(len)?));
+    }
+    let section = |id| sections.iter().find(|(x, _)| *x == id).map(|(_, s)| *s);
+
+    let types = section(SECTION_TYPE)
+        .map(parse_parameter_counts)
+        .transpose()?
+        .unwrap_or_default();
+    let function_types = section(SECTION_FUNCTION)
+        .map(|payload| {
+            let mut reader = Reader::new(payload);
+            (0..reader.u32()?)
+                .map(|_| reader.u32())
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+    if function_types.is_empty() {
+        return Ok((code.into(), BTreeMap::new()));
+    }
+    let imported = section(SECTION_IMPORT)
+        .map(crate::lowering::count_imported_functions)
+        .transpose()?
+        .unwrap_or(0);
+    let defined = u32::try_from(function_types.len()).map_err(|_| LoweringError::InvalidSection)?;
+    let type_count = u32::try_from(types.len()).map_err(|_| LoweringError::InvalidSection)?;
+    let (enter_type, leave_type) = (type_count, type_count + 1);
+    let (enter, leave) = (imported, imported + 1);
+    let original = |function: u32| imported + 2 + function;
+    let wrapper = |function: u32| imported + 2 + defined + function;
+    let remap = |function: u32| {
+        if function < imported {
+            function
+        } else {
+            wrapper(function - imported)
+        }
+    };
+
+    let mut names = BTreeMap::new();
+    let mut output = Vec::with_capacity(code.len() * 2);
+    output.extend_from_slice(&WASM_HEADER);
+    let mut imports_written = false;
+    for &(id, payload) in &sections {
+        if id != SECTION_CUSTOM && id > SECTION_IMPORT && !imports_written {
+            // The module doesn't import anything.
+            let mut imports = Vec::new();
+            write_u32(&mut imports, 2);
+            write_trace_imports(&mut imports, enter_type, leave_type)?;
+            write_section(&mut output, SECTION_IMPORT, &imports)?;
+            imports_written = true;
+        }
+        let mut reader = Reader::new(payload);
+        match id {
+            SECTION_CUSTOM => {
+                let name_len = reader.len()?;
+                if reader.slice(name_len)? == NAME_SECTION {
+                    parse_function_names(&mut reader, &mut names)?;
+                } else {
+                    write_section(&mut output, id, payload)?;
+                }
+            }
+            SECTION_TYPE => {
+                let mut rewritten = Vec::with_capacity(payload.len() + 8);
+                write_u32(&mut rewritten, reader.u32()? + 2);
+                rewritten.extend_from_slice(reader.remaining());
+                rewritten.extend_from_slice(&[0x60, 0x01, 0x7F, 0x00, 0x60, 0x00, 0x00]);
+                write_section(&mut output, id, &rewritten)?;
+            }
+            SECTION_IMPORT => {
+                let mut rewritten = Vec::with_capacity(payload.len() + 64);
+                write_u32(&mut rewritten, reader.u32()? + 2);
+                rewritten.extend_from_slice(reader.remaining());
+                write_trace_imports(&mut rewritten, enter_type, leave_type)?;
+                write_section(&mut output, id, &rewritten)?;
+                imports_written = true;
+            }
+            SECTION_FUNCTION => {
+                let mut rewritten = Vec::with_capacity(payload.len() * 2);
+                write_u32(&mut rewritten, defined * 2);
+                for type_index in function_types.iter().chain(function_types.iter()) {
+                    write_u32(&mut rewritten, *type_index);
+                }
+                write_section(&mut output, id, &rewritten)?;
+            }
+            SECTION_EXPORT => {
+                let rewritten = remap_exports(payload, remap, |index, name| {
+                    names
+                        .entry(index)
+                        .or_insert_with(|| String::from_utf8_lossy(name).into());
+                })?;
+                write_section(&mut output, id, &rewritten)?;
+            }
+            // The start function runs before any host function can be called.
+            SECTION_START => return Err(LoweringError::UnsupportedStartSection),
+            SECTION_ELEMENT => {
+                write_section(&mut output, id, &remap_elements(payload, remap)?)?;
+            }
+            SECTION_CODE => {
+                let mut rewritten = Vec::with_capacity(payload.len() * 2);
+                let count = reader.u32()?;
+                write_u32(&mut rewritten, count * 2);
+                for _ in 0..count {
+                    let len = reader.len()?;
+                    let body = remap_calls(reader.slice(len)?, remap)?;
+                    write_len(&mut rewritten, body.len())?;
+                    rewritten.extend_from_slice(&body);
+                }
+                for (function, type_index) in (0..).zip(&function_types) {
+                    let parameters = *types
+                        .get(
+                            usize::try_from(*type_index)
+                                .map_err(|_| LoweringError::InvalidSection)?,
+                        )
+                        .ok_or(LoweringError::InvalidSection)?;
+                    let mut body = vec![0x00];
+                    body.push(OPCODE_I32_CONST);
+                    write_i32(
+                        &mut body,
+                        i32::try_from(imported + function)
+                            .map_err(|_| LoweringError::InvalidSection)?,
+                    );
+                    body.push(OPCODE_CALL);
+                    write_u32(&mut body, enter);
+                    for parameter in 0..parameters {
+                        body.push(OPCODE_LOCAL_GET);
+                        write_u32(&mut body, parameter);
+                    }
+                    body.push(OPCODE_CALL);
+                    write_u32(&mut body, original(function));
+                    body.push(OPCODE_CALL);
+                    write_u32(&mut body, leave);
+                    body.push(OPCODE_END);
+                    write_len(&mut rewritten, body.len())?;
+                    rewritten.extend_from_slice(&body);
+                }
+                write_section(&mut output, id, &rewritten)?;
+            }
+            _ => write_section(&mut output, id, payload)?,
+        }
+    }
+    Ok((output, names))
+}
+
+/// Redirect the functions exported by an export section, `exported` is given their original index and name.
+pub(crate) fn remap_exports(
+    payload: &[u8],
+    remap: impl Fn(u32) -> u32,
+    mut exported: impl FnMut(u32, &[u8]),
+) -> Result<Vec<u8>, LoweringError> {
+    let mut reader = Reader::new(payload);
+    let mut rewritten = Vec::with_capacity(payload.len());
+    let count = reader.u32()?;
+    write_u32(&mut rewritten, count);
+    for _ in 0..count {
+        let name_len = reader.len()?;
+        let name = reader.slice(name_len)?;
+        let kind = reader.byte()?;
+        let mut index = reader.u32()?;
+        write_name(&mut rewritten, name)?;
+        rewritten.push(kind);
+        if kind == IMPORT_KIND_FUNCTION {
+            exported(index, name);
+            index = remap(index);
+        }
+        write_u32(&mut rewritten, index);
+    }
+    Ok(rewritten)
+}
+
+/// Redirect the functions referenced by an element section, only MVP segments are supported.
+pub(crate) fn remap_elements(
+    payload: &[u8],
+    remap: impl Fn(u32) -> u32,
+) -> Result<Vec<u8>, LoweringError> {
+    let mut reader = Reader::new(payload);
+    let mut rewritten = Vec::with_capacity(payload.len());
+    let count = reader.u32()?;
+    write_u32(&mut rewritten, count);
+    for _ in 0..count {
+        let flags = reader.u32()?;
+        if flags != 0 {
+            return Err(LoweringError::UnsupportedElementSegment(flags));
+        }
+        write_u32(&mut rewritten, flags);
+        // Offset expression, terminated by `end`.
+        loop {
+            let start = reader.position;
+            let opcode = reader.byte()?;
+            skip_immediates(&mut reader, opcode)?;
+            rewritten.extend_from_slice(&payload[start..reader.position]);
+            if opcode == OPCODE_END {
+                break;
+            }
+        }
+        let functions = reader.u32()?;
+        write_u32(&mut rewritten, functions);
+        for _ in 0..functions {
+            write_u32(&mut rewritten, remap(reader.u32()?));
+        }
+    }
+    Ok(rewritten)
+}
+
+fn write_trace_imports(
+    output: &mut Vec<u8>,
+    enter_type: u32,
+    leave_type: u32,
+) -> Result<(), LoweringError> {
+    for (name, type_index) in [(TRACE_ENTER, enter_type), (TRACE_LEAVE, leave_type)] {
+        write_name(output, TRACE_MODULE.as_bytes())?;
+        write_name(output, name.as_bytes())?;
+        output.push(IMPORT_KIND_FUNCTION);
+        write_u32(output, type_index);
+    }
+    Ok(())
+}
+
+/// Number of parameters of each function type.
+fn parse_parameter_counts(payload: &[u8]) -> Result<Vec<u32>, LoweringError> {
+    let mut reader = Reader::new(payload);
+    (0..reader.u32()?)
+        .map(|_| {
+            if reader.byte()? != 0x60 {
+                return Err(LoweringError::InvalidSection);
+            }
+            let parameters = reader.u32()?;
+            reader
+                .slice(usize::try_from(parameters).map_err(|_| LoweringError::InvalidSection)?)?;
+            let results = reader.len()?;
+            reader.slice(results)?;
+            Ok(parameters)
+        })
+        .collect()
+}
+
+fn parse_function_names(
+    reader: &mut Reader,
+    names: &mut BTreeMap<u32, String>,
+) -> Result<(), LoweringError> {
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.len()?;
+        let mut subsection = Reader::new(reader.slice(len)?);
+        if id == NAME_SUBSECTION_FUNCTIONS {
+            for _ in 0..subsection.u32()? {
+                let index = subsection.u32()?;
+                let name_len = subsection.len()?;
+                let name = subsection.slice(name_len)?;
+                names.insert(index, String::from_utf8_lossy(name).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Redirect the `call` instructions of a function body.
+fn remap_calls(body: &[u8], remap: impl Fn(u32) -> u32) -> Result<Vec<u8>, LoweringError> {
+    let mut reader = Reader::new(body);
+    for _ in 0..reader.u32()? {
+        reader.u32()?;
+        reader.byte()?;
+    }
+    let mut output = Vec::with_capacity(body.len());
+    output.extend_from_slice(&body[..reader.position]);
+    while !reader.is_empty() {
+        let start = reader.position;
+        let opcode = reader.byte()?;
+        if opcode == OPCODE_CALL {
+            output.push(OPCODE_CALL);
+            write_u32(&mut output, remap(reader.u32()?));
+        } else {
+            skip_immediates(&mut reader, opcode)?;
+            output.extend_from_slice(&body[start..reader.position]);
+        }
+    }
+    Ok(output)
+}
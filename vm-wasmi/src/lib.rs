@@ -32,12 +32,21 @@
 
 extern crate alloc;
 
+pub mod allocation;
 pub mod code_gen;
+pub mod coverage;
+#[cfg(any(test, feature = "crypto"))]
+pub mod crypto;
+pub mod deadline;
+pub mod diagnostics;
+pub mod lowering;
+pub mod profiler;
 
 #[cfg(test)]
 mod semantic;
 
 use alloc::{borrow::ToOwned, collections::BTreeMap, format, string::String, vec, vec::Vec};
+use allocation::AllocationTracker;
 use core::{
     fmt::{Debug, Display},
     marker::PhantomData,
@@ -51,7 +60,7 @@ use cosmwasm_std::{
 };
 use cosmwasm_vm::{
     executor::{
-        AllocateCall, AsFunctionName, CosmwasmCallInput, CosmwasmCallWithoutInfoInput,
+        constants, AllocateCall, AsFunctionName, CosmwasmCallInput, CosmwasmCallWithoutInfoInput,
         CosmwasmQueryResult, DeallocateCall, ExecutorError, QueryResult, Unit,
     },
     has::Has,
@@ -59,27 +68,40 @@ use cosmwasm_vm::{
         MemoryReadError, MemoryWriteError, Pointable, PointerOf, ReadWriteMemory, ReadableMemory,
         ReadableMemoryErrorOf, WritableMemory, WritableMemoryErrorOf,
     },
-    system::{CosmwasmContractMeta, SystemError},
+    system::{CosmwasmBaseVM, CosmwasmContractMeta, SystemError},
     tagged::Tagged,
     transaction::{Transactional, TransactionalErrorOf},
     vm::{
-        VMBase, VmAddressOf, VmCanonicalAddressOf, VmContracMetaOf, VmErrorOf, VmGas,
-        VmGasCheckpoint, VmMessageCustomOf, VmQueryCustomOf, VmStorageKeyOf, VmStorageValueOf, VM,
+        CryptoError, VMBase, VmAddressOf, VmCanonicalAddressOf, VmCodecOf, VmContracMetaOf,
+        VmErrorOf, VmGas, VmGasCheckpoint, VmMessageCustomOf, VmQueryCustomOf, VmStorageKeyOf,
+        VmStorageValueOf, VM,
     },
 };
+use coverage::COVERAGE_HIT;
+use deadline::DEADLINE_CHECK;
+use diagnostics::{instrument_call_stack, Diagnostics, TrapDiagnostics, TRACE_ENTER, TRACE_LEAVE};
 use either::Either;
-use wasmi::{CanResume, Externals, FuncInstance, ImportResolver, NopExternals, RuntimeValue};
+use lowering::{lower_to_mvp, LoweringError};
+use wasmi::{
+    memory_units::Bytes, CanResume, Externals, FuncInstance, ImportResolver, NopExternals,
+    RuntimeValue,
+};
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
-pub struct WasmiFunctionName(String);
+pub struct WasmiFunctionName(pub String);
 pub type WasmiFunctionArgs<'a> = (Vec<RuntimeValue>, PhantomData<&'a ()>);
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
-pub struct WasmiModuleName(String);
+pub struct WasmiModuleName(pub String);
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct WasmiHostFunctionIndex(usize);
 pub type WasmiHostFunction<T> =
     fn(&mut WasmiVM<T>, &[RuntimeValue]) -> Result<Option<RuntimeValue>, VmErrorOf<T>>;
-pub type WasmiHostModuleEntry<T> = (WasmiHostFunctionIndex, WasmiHostFunction<T>);
+pub type WasmiHostModuleEntry<T> = (
+    WasmiHostFunctionIndex,
+    WasmiHostFunction<T>,
+    wasmi::Signature,
+);
+pub type WasmiHostSignature = (WasmiHostFunctionIndex, wasmi::Signature);
 pub type WasmiHostModule<T> = BTreeMap<WasmiFunctionName, WasmiHostModuleEntry<T>>;
 
 #[derive(PartialEq, Eq, Debug)]
@@ -89,6 +111,7 @@ pub enum WasmiVMError {
     MemoryReadError(MemoryReadError),
     MemoryWriteError(MemoryWriteError),
     HostFunctionNotFound(WasmiHostFunctionIndex),
+    ExportNotFound(String),
     MemoryNotExported,
     MemoryExportedIsNotMemory,
     LowLevelMemoryReadError,
@@ -100,7 +123,12 @@ pub enum WasmiVMError {
     InvalidHostSignature,
     InvalidValue,
     MaxLimitExceeded,
+    SectionsDecodeError(SectionsDecodeError),
+    LoweringError(LoweringError),
+    DeadlineExceeded,
+    Trap(TrapDiagnostics),
 }
+
 impl From<ExecutorError> for WasmiVMError {
     fn from(e: ExecutorError) -> Self {
         WasmiVMError::ExecutorError(e)
@@ -116,6 +144,16 @@ impl From<MemoryWriteError> for WasmiVMError {
         WasmiVMError::MemoryWriteError(e)
     }
 }
+impl From<LoweringError> for WasmiVMError {
+    fn from(e: LoweringError) -> Self {
+        WasmiVMError::LoweringError(e)
+    }
+}
+impl From<SectionsDecodeError> for WasmiVMError {
+    fn from(e: SectionsDecodeError) -> Self {
+        WasmiVMError::SectionsDecodeError(e)
+    }
+}
 impl From<SystemError> for WasmiVMError {
     fn from(e: SystemError) -> Self {
         WasmiVMError::SystemError(e)
@@ -163,8 +201,62 @@ where
 pub trait WasmiModuleExecutor: Sized + VMBase {
     fn executing_module(&self) -> WasmiModule;
     fn host_function(&self, index: WasmiHostFunctionIndex) -> Option<&WasmiHostFunction<Self>>;
+    /// Whether the optional execution deadline has passed.
+    /// Checked on every gas instrumentation callback and on every check injected by
+    /// [`deadline::instrument_deadline`], the execution is aborted with [`WasmiVMError::DeadlineExceeded`] if it did.
+    fn deadline_exceeded(&self) -> bool {
+        false
+    }
+    /// Optional accounting of the regions exchanged with the contract, disabled by default.
+    fn allocation_tracker(&mut self) -> Option<&mut AllocationTracker> {
+        None
+    }
+    /// Shadow call stack of a module loaded with [`new_wasmi_vm_with_diagnostics`], disabled by default.
+    fn diagnostics(&mut self) -> Option<&mut Diagnostics> {
+        None
+    }
+    /// Hit counters of the basic blocks of a module instrumented with
+    /// [`coverage::instrument_coverage`], disabled by default.
+    fn coverage_counters(&mut self) -> Option<&mut [u64]> {
+        None
+    }
 }
 
+/// Optional hooks of the VM reached by the host functions, whatever the backend running the contract.
+pub trait WasmiHostHooks {
+    /// See [`WasmiModuleExecutor::deadline_exceeded`].
+    fn deadline_exceeded(&self) -> bool {
+        false
+    }
+    /// See [`WasmiModuleExecutor::diagnostics`].
+    fn diagnostics(&mut self) -> Option<&mut Diagnostics> {
+        None
+    }
+    /// See [`WasmiModuleExecutor::coverage_counters`].
+    fn coverage_counters(&mut self) -> Option<&mut [u64]> {
+        None
+    }
+}
+
+/// A VM the [`host_functions`] can operate on, allowing other backends to share them with [`WasmiVM`].
+pub trait WasmiHostVM = CosmwasmBaseVM + ReadWriteMemory<Pointer = u32> + WasmiHostHooks
+where
+    VmAddressOf<Self>: Clone + TryFrom<String, Error = VmErrorOf<Self>> + Into<Addr>,
+    VmCanonicalAddressOf<Self>:
+        Clone + TryFrom<Vec<u8>, Error = VmErrorOf<Self>> + Into<CanonicalAddr>,
+    VmErrorOf<Self>: From<WasmiVMError>
+        + From<MemoryReadError>
+        + From<MemoryWriteError>
+        + From<ReadableMemoryErrorOf<Self>>
+        + From<WritableMemoryErrorOf<Self>>
+        + From<ExecutorError>
+        + From<SystemError>
+        + From<TransactionalErrorOf<Self>>
+        + Debug
+        + Display,
+    ReadableMemoryErrorOf<Self>: From<MemoryReadError>,
+    WritableMemoryErrorOf<Self>: From<MemoryWriteError>;
+
 pub struct WasmiVM<T>(pub T);
 impl<T> Externals for WasmiVM<T>
 where
@@ -176,13 +268,23 @@ where
         index: usize,
         args: wasmi::RuntimeArgs,
     ) -> Result<Option<RuntimeValue>, Self::Error> {
-        self.0
+        if let Some(diagnostics) = self.0.diagnostics() {
+            diagnostics.host_called(index);
+        }
+        let result = self
+            .0
             .host_function(WasmiHostFunctionIndex(index))
             .ok_or_else(|| {
                 VmErrorOf::<T>::from(WasmiVMError::HostFunctionNotFound(WasmiHostFunctionIndex(
                     index,
                 )))
-            })?(self, args.as_ref())
+            })?(self, args.as_ref());
+        if result.is_err() {
+            if let Some(diagnostics) = self.0.diagnostics() {
+                diagnostics.host_failed();
+            }
+        }
+        result
     }
 }
 
@@ -205,14 +307,19 @@ where
                     "A module tried to load an unknown host module: {module_name}"
                 ))
             })?;
-        let (WasmiHostFunctionIndex(function_index), _) = *module
+        let (WasmiHostFunctionIndex(function_index), _, expected_signature) = module
             .get(&WasmiFunctionName(field_name.to_owned()))
             .ok_or_else(|| {
                 wasmi::Error::Instantiation(format!(
                     "A module tried to load an unknown host function: {module_name}.{field_name}"
                 ))
             })?;
-        Ok(FuncInstance::alloc_host(signature.clone(), function_index))
+        if signature != expected_signature {
+            return Err(wasmi::Error::Instantiation(format!(
+                "A module tried to load the host function {module_name}.{field_name} with signature {signature:?} while {expected_signature:?} is expected"
+            )));
+        }
+        Ok(FuncInstance::alloc_host(signature.clone(), *function_index))
     }
 
     fn resolve_global(
@@ -412,7 +519,26 @@ where
     {
         log::trace!("Function name: {}", function_name);
         let WasmiModule { module, memory } = self.0.executing_module();
-        let value = module.invoke_export(&function_name, &function_args, self)?;
+        let depth = self.0.diagnostics().map(|diagnostics| {
+            let depth = diagnostics.depth();
+            diagnostics.started(depth);
+            depth
+        });
+        let value = if self.0.allocation_tracker().is_some() {
+            self.tracked_invoke(&module, &memory, &function_name, &function_args)
+        } else {
+            module.invoke_export(&function_name, &function_args, self)
+        };
+        let value = match (value, depth) {
+            (Err(e), Some(depth)) => {
+                let trap = self
+                    .0
+                    .diagnostics()
+                    .and_then(|diagnostics| diagnostics.failed(depth, format!("{e}")));
+                return Err(trap.map_or(e, |trap| WasmiVMError::Trap(trap).into()));
+            }
+            (value, _) => value?,
+        };
         O::try_from(WasmiOutput(
             match value {
                 Some(non_unit) => Either::Right((&memory, non_unit)),
@@ -423,6 +549,58 @@ where
     }
 }
 
+impl<T> WasmiVM<T>
+where
+    T: WasmiBaseVM,
+{
+    fn tracker(&mut self) -> &mut AllocationTracker {
+        self.0
+            .allocation_tracker()
+            .expect("only called when tracking is enabled")
+    }
+
+    /// Invoke an export while feeding the allocation tracker of the underlying VM.
+    #[allow(clippy::cast_sign_loss)]
+    fn tracked_invoke(
+        &mut self,
+        module: &wasmi::ModuleRef,
+        memory: &wasmi::MemoryRef,
+        function_name: &str,
+        function_args: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>> {
+        let pointers = function_args
+            .iter()
+            .filter_map(|value| match value {
+                RuntimeValue::I32(pointer) => Some(*pointer as u32),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let memory_size = || Bytes::from(memory.current_size()).0;
+        if function_name == AllocateCall::<u32>::NAME {
+            let value = module.invoke_export(function_name, function_args, self)?;
+            if let (Some(RuntimeValue::I32(pointer)), [len]) = (value, pointers.as_slice()) {
+                self.tracker().allocated(pointer as u32, *len);
+            }
+            Ok(value)
+        } else if function_name == DeallocateCall::<u32>::NAME {
+            if let [pointer] = pointers.as_slice() {
+                self.tracker().deallocated(*pointer);
+            }
+            Ok(module.invoke_export(function_name, function_args, self)?)
+        } else {
+            self.tracker()
+                .entrypoint_started(function_name, &pointers, memory_size());
+            let value = module.invoke_export(function_name, function_args, self);
+            let result = match value {
+                Ok(Some(RuntimeValue::I32(pointer))) => Some(pointer as u32),
+                _ => None,
+            };
+            self.tracker().entrypoint_finished(result, memory_size());
+            Ok(value?)
+        }
+    }
+}
+
 impl<T> VMBase for WasmiVM<T>
 where
     T: WasmiBaseVM,
@@ -436,6 +614,7 @@ where
     type CanonicalAddress = VmCanonicalAddressOf<T>;
     type StorageKey = VmStorageKeyOf<T>;
     type StorageValue = VmStorageValueOf<T>;
+    type Codec = VmCodecOf<T>;
     type Error = VmErrorOf<T>;
 
     fn running_contract_meta(&mut self) -> Result<Self::ContractMeta, Self::Error> {
@@ -463,6 +642,18 @@ where
         self.0.db_next(iterator_id)
     }
 
+    #[cfg(feature = "iterator")]
+    fn db_next_key(&mut self, iterator_id: u32) -> Result<Self::StorageKey, Self::Error> {
+        self.charge(VmGas::DbNextKey)?;
+        self.0.db_next_key(iterator_id)
+    }
+
+    #[cfg(feature = "iterator")]
+    fn db_next_value(&mut self, iterator_id: u32) -> Result<Self::StorageValue, Self::Error> {
+        self.charge(VmGas::DbNextValue)?;
+        self.0.db_next_value(iterator_id)
+    }
+
     fn set_contract_meta(
         &mut self,
         address: Self::Address,
@@ -671,7 +862,7 @@ where
         message_hash: &[u8],
         signature: &[u8],
         public_key: &[u8],
-    ) -> Result<bool, Self::Error> {
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
         self.0.charge(VmGas::Secp256k1Verify)?;
         self.0.secp256k1_verify(message_hash, signature, public_key)
     }
@@ -681,7 +872,7 @@ where
         message_hash: &[u8],
         signature: &[u8],
         recovery_param: u8,
-    ) -> Result<Result<Vec<u8>, ()>, Self::Error> {
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
         self.0.charge(VmGas::Secp256k1RecoverPubkey)?;
         self.0
             .secp256k1_recover_pubkey(message_hash, signature, recovery_param)
@@ -692,7 +883,7 @@ where
         message: &[u8],
         signature: &[u8],
         public_key: &[u8],
-    ) -> Result<bool, Self::Error> {
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
         self.0.charge(VmGas::Ed25519Verify)?;
         self.0.ed25519_verify(message, signature, public_key)
     }
@@ -702,12 +893,97 @@ where
         messages: &[&[u8]],
         signatures: &[&[u8]],
         public_keys: &[&[u8]],
-    ) -> Result<bool, Self::Error> {
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
         self.0.charge(VmGas::Ed25519BatchVerify)?;
         self.0
             .ed25519_batch_verify(messages, signatures, public_keys)
     }
 
+    fn secp256r1_verify(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        self.0.charge(VmGas::Secp256r1Verify)?;
+        self.0.secp256r1_verify(message_hash, signature, public_key)
+    }
+
+    fn secp256r1_recover_pubkey(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        recovery_param: u8,
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.0.charge(VmGas::Secp256r1RecoverPubkey)?;
+        self.0
+            .secp256r1_recover_pubkey(message_hash, signature, recovery_param)
+    }
+
+    fn bls12_381_aggregate_g1(
+        &mut self,
+        g1s: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.0.charge(VmGas::Bls12381AggregateG1 {
+            nb_of_points: u32::try_from(g1s.len() / constants::BLS12_381_G1_POINT_LENGTH)
+                .map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.0.bls12_381_aggregate_g1(g1s)
+    }
+
+    fn bls12_381_aggregate_g2(
+        &mut self,
+        g2s: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.0.charge(VmGas::Bls12381AggregateG2 {
+            nb_of_points: u32::try_from(g2s.len() / constants::BLS12_381_G2_POINT_LENGTH)
+                .map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.0.bls12_381_aggregate_g2(g2s)
+    }
+
+    fn bls12_381_pairing_equality(
+        &mut self,
+        ps: &[u8],
+        qs: &[u8],
+        r: &[u8],
+        s: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        self.0.charge(VmGas::Bls12381PairingEquality {
+            nb_of_pairs: u32::try_from(ps.len() / constants::BLS12_381_G1_POINT_LENGTH)
+                .map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.0.bls12_381_pairing_equality(ps, qs, r, s)
+    }
+
+    fn bls12_381_hash_to_g1(
+        &mut self,
+        hash_function: u32,
+        message: &[u8],
+        dst: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.0.charge(VmGas::Bls12381HashToG1)?;
+        self.0.bls12_381_hash_to_g1(hash_function, message, dst)
+    }
+
+    fn bls12_381_hash_to_g2(
+        &mut self,
+        hash_function: u32,
+        message: &[u8],
+        dst: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        self.0.charge(VmGas::Bls12381HashToG2)?;
+        self.0.bls12_381_hash_to_g2(hash_function, message, dst)
+    }
+
+    fn keccak256(&mut self, message: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        self.0.charge(VmGas::Keccak256 {
+            nb_of_bytes: u32::try_from(message.len())
+                .map_err(|_| WasmiVMError::MaxLimitExceeded)?,
+        })?;
+        self.0.keccak256(message)
+    }
+
     #[cfg(feature = "stargate")]
     fn ibc_transfer(
         &mut self,
@@ -738,6 +1014,21 @@ where
     }
 }
 
+impl<T> WasmiHostHooks for WasmiVM<T>
+where
+    T: WasmiBaseVM,
+{
+    fn deadline_exceeded(&self) -> bool {
+        self.0.deadline_exceeded()
+    }
+    fn diagnostics(&mut self) -> Option<&mut Diagnostics> {
+        self.0.diagnostics()
+    }
+    fn coverage_counters(&mut self) -> Option<&mut [u64]> {
+        self.0.coverage_counters()
+    }
+}
+
 impl<T> Transactional for WasmiVM<T>
 where
     T: Transactional,
@@ -764,6 +1055,59 @@ pub fn new_wasmi_vm<T>(
     resolver: &WasmiImportResolver<T>,
     code: &[u8],
 ) -> Result<WasmiModule, VmErrorOf<T>>
+where
+    T: WasmiBaseVM,
+{
+    let code = lower_to_mvp(code).map_err(WasmiVMError::from)?;
+    instantiate(resolver, code)
+}
+
+/// Same as [`new_wasmi_vm`], but the module is instrumented to maintain a shadow call stack.
+/// The returned [`Diagnostics`] must be exposed through [`WasmiModuleExecutor::diagnostics`] for
+/// traps and aborts to be reported as [`WasmiVMError::Trap`], along with the functions active at
+/// the time.
+pub fn new_wasmi_vm_with_diagnostics<T>(
+    resolver: &WasmiImportResolver<T>,
+    code: &[u8],
+) -> Result<(WasmiModule, Diagnostics), VmErrorOf<T>>
+where
+    T: WasmiBaseVM,
+{
+    let code = lower_to_mvp(code).map_err(WasmiVMError::from)?;
+    let (code, names) = instrument_call_stack(&code).map_err(WasmiVMError::from)?;
+    let host_names = resolver
+        .0
+        .iter()
+        .flat_map(|(WasmiModuleName(module), functions)| {
+            functions
+                .iter()
+                .filter(|(WasmiFunctionName(name), _)| {
+                    ![
+                        TRACE_ENTER,
+                        TRACE_LEAVE,
+                        COVERAGE_HIT,
+                        DEADLINE_CHECK,
+                        "gas",
+                    ]
+                    .contains(&name.as_str())
+                })
+                .map(
+                    move |(WasmiFunctionName(name), (WasmiHostFunctionIndex(index), ..))| {
+                        (*index, format!("{module}::{name}"))
+                    },
+                )
+        })
+        .collect();
+    Ok((
+        instantiate(resolver, code)?,
+        Diagnostics::new(names, host_names),
+    ))
+}
+
+fn instantiate<T>(
+    resolver: &WasmiImportResolver<T>,
+    code: Vec<u8>,
+) -> Result<WasmiModule, VmErrorOf<T>>
 where
     T: WasmiBaseVM,
 {
@@ -799,6 +1143,9 @@ where
     fn read(&self, offset: Self::Pointer, buffer: &mut [u8]) -> Result<(), Self::Error> {
         self.0.read(offset, buffer)
     }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
 }
 
 impl<T> WritableMemory for WasmiVM<T>
@@ -845,27 +1192,43 @@ pub fn encode_sections(sections: &[Vec<u8>]) -> Option<Vec<u8>> {
         })
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SectionsDecodeError {
+    /// Fewer than 4 bytes are left to read a section length from.
+    TruncatedLengthSuffix,
+    /// A section length is larger than the data preceding its suffix.
+    SectionLengthOutOfBounds,
+}
+
 /// Decodes sections of data into multiple slices.
 ///
 /// Each encoded section is suffixed by a section length, encoded as big endian uint32.
 ///
 /// See also: `encode_section`.
-#[must_use]
-pub fn decode_sections(data: &[u8]) -> Vec<&[u8]> {
+///
+/// # Errors
+///
+/// Fails if a length suffix is truncated or points before the start of `data`.
+pub fn decode_sections(data: &[u8]) -> Result<Vec<&[u8]>, SectionsDecodeError> {
     let mut result: Vec<&[u8]> = vec![];
-    let mut remaining_len = data.len();
-    while remaining_len >= 4 {
-        let tail_len = u32::from_be_bytes([
-            data[remaining_len - 4],
-            data[remaining_len - 3],
-            data[remaining_len - 2],
-            data[remaining_len - 1],
-        ]) as usize;
-        result.push(&data[remaining_len - 4 - tail_len..remaining_len - 4]);
-        remaining_len -= 4 + tail_len;
+    let mut remaining = data;
+    while !remaining.is_empty() {
+        let suffix_start = remaining
+            .len()
+            .checked_sub(4)
+            .ok_or(SectionsDecodeError::TruncatedLengthSuffix)?;
+        let (rest, suffix) = remaining.split_at(suffix_start);
+        let tail_len = u32::from_be_bytes([suffix[0], suffix[1], suffix[2], suffix[3]]);
+        let section_start = usize::try_from(tail_len)
+            .ok()
+            .and_then(|tail_len| rest.len().checked_sub(tail_len))
+            .ok_or(SectionsDecodeError::SectionLengthOutOfBounds)?;
+        let (rest, section) = rest.split_at(section_start);
+        result.push(section);
+        remaining = rest;
     }
     result.reverse();
-    result
+    Ok(result)
 }
 
 // Our casts are generally OK.
@@ -878,10 +1241,10 @@ pub fn decode_sections(data: &[u8]) -> Vec<&[u8]> {
 #[allow(dead_code)]
 pub mod host_functions {
     use super::{
-        decode_sections, encode_sections, format, BTreeMap, RuntimeValue, String, Tagged, ToOwned,
-        VMBase, Vec, VmErrorOf, VmGas, VmQueryCustomOf, WasmiBaseVM, WasmiFunctionName,
-        WasmiHostFunction, WasmiHostFunctionIndex, WasmiHostModule, WasmiModuleName, WasmiVM,
-        WasmiVMError,
+        decode_sections, encode_sections, format, BTreeMap, CryptoError, RuntimeValue, String,
+        Tagged, ToOwned, Vec, VmErrorOf, VmGas, VmQueryCustomOf, WasmiBaseVM, WasmiFunctionName,
+        WasmiHostFunctionIndex, WasmiHostModule, WasmiHostSignature, WasmiHostVM, WasmiModuleName,
+        WasmiVM, WasmiVMError, COVERAGE_HIT, DEADLINE_CHECK, TRACE_ENTER, TRACE_LEAVE,
     };
     #[cfg(feature = "iterator")]
     use cosmwasm_std::Order;
@@ -893,12 +1256,64 @@ pub mod host_functions {
         },
         system::cosmwasm_system_query_raw,
     };
+    use wasmi::{Signature, ValueType};
+
+    /// Host function shared by the backends, operating on any [`WasmiHostVM`].
+    pub type HostFunction<V> =
+        fn(&mut V, &[RuntimeValue]) -> Result<Option<RuntimeValue>, VmErrorOf<V>>;
+
+    /// Encode the outcome of a signature verification or a pairing check for the contract:
+    /// `0` if valid, `1` if invalid and the error code if the inputs are malformed.
+    fn verification_result(result: Result<bool, CryptoError>) -> RuntimeValue {
+        RuntimeValue::I32(match result {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(e) => e.code() as i32,
+        })
+    }
+
+    /// Encode the outcome of a crypto primitive writing its result to the contract memory:
+    /// `0` on success and the error code otherwise.
+    fn write_result<V>(
+        vm: &mut V,
+        destination_pointer: i32,
+        result: Result<Vec<u8>, CryptoError>,
+    ) -> Result<RuntimeValue, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        match result {
+            Ok(value) => {
+                passthrough_in_to::<V>(vm, destination_pointer as u32, &value)?;
+                Ok(RuntimeValue::I32(0))
+            }
+            Err(e) => Ok(RuntimeValue::I32(e.code() as i32)),
+        }
+    }
+
+    /// Encode the outcome of a public key recovery for the contract: on success, the pointer
+    /// to the key is written to the lower 4 bytes. On failure, the error code is written to the
+    /// upper 4 bytes.
+    fn recover_pubkey_result<V>(
+        vm: &mut V,
+        result: Result<Vec<u8>, CryptoError>,
+    ) -> Result<RuntimeValue, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        match result {
+            Ok(pubkey) => {
+                let Tagged(value_pointer, _) = passthrough_in::<V, ()>(vm, &pubkey)?;
+                Ok(RuntimeValue::I64(i64::from(value_pointer)))
+            }
+            Err(e) => Ok(RuntimeValue::I64(i64::from(e.code()) << 32)),
+        }
+    }
 
+    /// Index and signature of the host functions, by module and name.
     #[must_use]
     #[allow(clippy::too_many_lines)]
-    pub fn definitions<T>() -> BTreeMap<WasmiModuleName, WasmiHostModule<T>>
-    where
-        T: WasmiBaseVM,
+    pub fn signatures() -> BTreeMap<WasmiModuleName, BTreeMap<WasmiFunctionName, WasmiHostSignature>>
     {
         BTreeMap::from([(
             WasmiModuleName("env".to_owned()),
@@ -907,21 +1322,21 @@ pub mod host_functions {
                     WasmiFunctionName("db_read".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0001),
-                        env_db_read as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
                     ),
                 ),
                 (
                     WasmiFunctionName("db_write".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0002),
-                        env_db_write as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32, ValueType::I32][..], None),
                     ),
                 ),
                 (
                     WasmiFunctionName("db_remove".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0003),
-                        env_db_remove as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32][..], None),
                     ),
                 ),
                 #[cfg(feature = "iterator")]
@@ -929,7 +1344,10 @@ pub mod host_functions {
                     WasmiFunctionName("db_scan".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0004),
-                        env_db_scan as WasmiHostFunction<T>,
+                        Signature::new(
+                            &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                            Some(ValueType::I32),
+                        ),
                     ),
                 ),
                 #[cfg(feature = "iterator")]
@@ -937,109 +1355,318 @@ pub mod host_functions {
                     WasmiFunctionName("db_next".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0005),
-                        env_db_next as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
                     ),
                 ),
                 (
                     WasmiFunctionName("addr_validate".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0006),
-                        env_addr_validate as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
                     ),
                 ),
                 (
                     WasmiFunctionName("addr_canonicalize".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0007),
-                        env_addr_canonicalize as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
                     ),
                 ),
                 (
                     WasmiFunctionName("addr_humanize".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0008),
-                        env_addr_humanize as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
                     ),
                 ),
                 (
                     WasmiFunctionName("secp256k1_verify".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0009),
-                        env_secp256k1_verify as WasmiHostFunction<T>,
+                        Signature::new(
+                            &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                            Some(ValueType::I32),
+                        ),
                     ),
                 ),
                 (
                     WasmiFunctionName("secp256k1_recover_pubkey".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x000B),
-                        env_secp256k1_recover_pubkey as WasmiHostFunction<T>,
+                        Signature::new(
+                            &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                            Some(ValueType::I64),
+                        ),
                     ),
                 ),
                 (
                     WasmiFunctionName("ed25519_verify".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x000C),
-                        env_ed25519_verify as WasmiHostFunction<T>,
+                        Signature::new(
+                            &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                            Some(ValueType::I32),
+                        ),
                     ),
                 ),
                 (
                     WasmiFunctionName("ed25519_batch_verify".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x000D),
-                        env_ed25519_batch_verify as WasmiHostFunction<T>,
+                        Signature::new(
+                            &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                            Some(ValueType::I32),
+                        ),
                     ),
                 ),
                 (
                     WasmiFunctionName("debug".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x000E),
-                        env_debug as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32][..], None),
                     ),
                 ),
                 (
                     WasmiFunctionName("query_chain".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x000F),
-                        env_query_chain as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
                     ),
                 ),
                 (
                     WasmiFunctionName("abort".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0010),
-                        env_abort as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32][..], None),
                     ),
                 ),
                 (
                     WasmiFunctionName("gas".to_owned()),
                     (
                         WasmiHostFunctionIndex(0x0011),
-                        env_gas as WasmiHostFunction<T>,
+                        Signature::new(&[ValueType::I32][..], None),
+                    ),
+                ),
+                (
+                    WasmiFunctionName("secp256r1_verify".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x0012),
+                        Signature::new(
+                            &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                            Some(ValueType::I32),
+                        ),
+                    ),
+                ),
+                (
+                    WasmiFunctionName("secp256r1_recover_pubkey".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x0013),
+                        Signature::new(
+                            &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                            Some(ValueType::I64),
+                        ),
+                    ),
+                ),
+                (
+                    WasmiFunctionName("bls12_381_aggregate_g1".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x0014),
+                        Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                    ),
+                ),
+                (
+                    WasmiFunctionName("bls12_381_aggregate_g2".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x0015),
+                        Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                    ),
+                ),
+                (
+                    WasmiFunctionName("bls12_381_pairing_equality".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x0016),
+                        Signature::new(
+                            &[
+                                ValueType::I32,
+                                ValueType::I32,
+                                ValueType::I32,
+                                ValueType::I32,
+                            ][..],
+                            Some(ValueType::I32),
+                        ),
+                    ),
+                ),
+                (
+                    WasmiFunctionName("bls12_381_hash_to_g1".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x0017),
+                        Signature::new(
+                            &[
+                                ValueType::I32,
+                                ValueType::I32,
+                                ValueType::I32,
+                                ValueType::I32,
+                            ][..],
+                            Some(ValueType::I32),
+                        ),
+                    ),
+                ),
+                (
+                    WasmiFunctionName("bls12_381_hash_to_g2".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x0018),
+                        Signature::new(
+                            &[
+                                ValueType::I32,
+                                ValueType::I32,
+                                ValueType::I32,
+                                ValueType::I32,
+                            ][..],
+                            Some(ValueType::I32),
+                        ),
+                    ),
+                ),
+                (
+                    WasmiFunctionName("keccak256".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x0019),
+                        Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                    ),
+                ),
+                #[cfg(feature = "iterator")]
+                (
+                    WasmiFunctionName("db_next_key".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x001A),
+                        Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+                    ),
+                ),
+                #[cfg(feature = "iterator")]
+                (
+                    WasmiFunctionName("db_next_value".to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x001B),
+                        Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+                    ),
+                ),
+                (
+                    WasmiFunctionName(TRACE_ENTER.to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x001C),
+                        Signature::new(&[ValueType::I32][..], None),
+                    ),
+                ),
+                (
+                    WasmiFunctionName(TRACE_LEAVE.to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x001D),
+                        Signature::new(&[][..], None),
+                    ),
+                ),
+                (
+                    WasmiFunctionName(COVERAGE_HIT.to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x001E),
+                        Signature::new(&[ValueType::I32][..], None),
+                    ),
+                ),
+                (
+                    WasmiFunctionName(DEADLINE_CHECK.to_owned()),
+                    (
+                        WasmiHostFunctionIndex(0x001F),
+                        Signature::new(&[][..], None),
                     ),
                 ),
             ]),
         )])
     }
 
-    fn env_db_read<T>(
-        vm: &mut WasmiVM<T>,
-        values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    #[must_use]
+    pub fn definitions<T>() -> BTreeMap<WasmiModuleName, WasmiHostModule<T>>
     where
         T: WasmiBaseVM,
+    {
+        signatures()
+            .into_iter()
+            .map(|(module, functions)| {
+                let functions = functions
+                    .into_iter()
+                    .filter_map(|(name, (index, signature))| {
+                        host_function::<WasmiVM<T>>(index)
+                            .map(|function| (name, (index, function, signature)))
+                    })
+                    .collect();
+                (module, functions)
+            })
+            .collect()
+    }
+
+    /// Host functions of [`signatures`], for any backend implementing [`WasmiHostVM`].
+    #[must_use]
+    pub fn host_function<V>(
+        WasmiHostFunctionIndex(index): WasmiHostFunctionIndex,
+    ) -> Option<HostFunction<V>>
+    where
+        V: WasmiHostVM,
+    {
+        let function: HostFunction<V> = match index {
+            0x0001 => env_db_read,
+            0x0002 => env_db_write,
+            0x0003 => env_db_remove,
+            #[cfg(feature = "iterator")]
+            0x0004 => env_db_scan,
+            #[cfg(feature = "iterator")]
+            0x0005 => env_db_next,
+            0x0006 => env_addr_validate,
+            0x0007 => env_addr_canonicalize,
+            0x0008 => env_addr_humanize,
+            0x0009 => env_secp256k1_verify,
+            0x000B => env_secp256k1_recover_pubkey,
+            0x000C => env_ed25519_verify,
+            0x000D => env_ed25519_batch_verify,
+            0x000E => env_debug,
+            0x000F => env_query_chain,
+            0x0010 => env_abort,
+            0x0011 => env_gas,
+            0x0012 => env_secp256r1_verify,
+            0x0013 => env_secp256r1_recover_pubkey,
+            0x0014 => env_bls12_381_aggregate_g1,
+            0x0015 => env_bls12_381_aggregate_g2,
+            0x0016 => env_bls12_381_pairing_equality,
+            0x0017 => env_bls12_381_hash_to_g1,
+            0x0018 => env_bls12_381_hash_to_g2,
+            0x0019 => env_keccak256,
+            #[cfg(feature = "iterator")]
+            0x001A => env_db_next_key,
+            #[cfg(feature = "iterator")]
+            0x001B => env_db_next_value,
+            0x001C => env_trace_enter,
+            0x001D => env_trace_leave,
+            0x001E => env_coverage_hit,
+            0x001F => env_deadline_check,
+            _ => return None,
+        };
+        Some(function)
+    }
+
+    fn env_db_read<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
     {
         log::debug!("db_read");
         match values {
             [RuntimeValue::I32(key_pointer)] => {
-                let key = passthrough_out::<
-                    WasmiVM<T>,
-                    ConstantReadLimit<{ constants::MAX_LENGTH_DB_KEY }>,
-                >(vm, *key_pointer as u32)?;
+                let key = passthrough_out::<V, ConstantReadLimit<{ constants::MAX_LENGTH_DB_KEY }>>(
+                    vm,
+                    *key_pointer as u32,
+                )?;
                 let value = vm.db_read(key);
                 match value {
                     Ok(Some(value)) => {
-                        let Tagged(value_pointer, _) =
-                            passthrough_in::<WasmiVM<T>, ()>(vm, &value)?;
+                        let Tagged(value_pointer, _) = passthrough_in::<V, ()>(vm, &value)?;
                         Ok(Some(RuntimeValue::I32(value_pointer as i32)))
                     }
                     Ok(None) => Ok(Some(RuntimeValue::I32(0))),
@@ -1050,22 +1677,22 @@ pub mod host_functions {
         }
     }
 
-    fn env_db_write<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_db_write<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("db_write");
         match values {
             [RuntimeValue::I32(key_pointer), RuntimeValue::I32(value_pointer)] => {
-                let key = passthrough_out::<
-                    WasmiVM<T>,
-                    ConstantReadLimit<{ constants::MAX_LENGTH_DB_KEY }>,
-                >(vm, *key_pointer as u32)?;
+                let key = passthrough_out::<V, ConstantReadLimit<{ constants::MAX_LENGTH_DB_KEY }>>(
+                    vm,
+                    *key_pointer as u32,
+                )?;
                 let value = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_DB_VALUE }>,
                 >(vm, *value_pointer as u32)?;
                 vm.db_write(key, value)?;
@@ -1075,20 +1702,20 @@ pub mod host_functions {
         }
     }
 
-    fn env_db_remove<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_db_remove<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("db_remove");
         match values {
             [RuntimeValue::I32(key_pointer)] => {
-                let key = passthrough_out::<
-                    WasmiVM<T>,
-                    ConstantReadLimit<{ constants::MAX_LENGTH_DB_KEY }>,
-                >(vm, *key_pointer as u32)?;
+                let key = passthrough_out::<V, ConstantReadLimit<{ constants::MAX_LENGTH_DB_KEY }>>(
+                    vm,
+                    *key_pointer as u32,
+                )?;
                 vm.db_remove(key)?;
                 Ok(None)
             }
@@ -1097,24 +1724,24 @@ pub mod host_functions {
     }
 
     #[cfg(feature = "iterator")]
-    fn env_db_scan<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_db_scan<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("db_scan");
         match values {
             [RuntimeValue::I32(start_ptr), RuntimeValue::I32(end_ptr), RuntimeValue::I32(order)] => {
                 let start = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_DB_KEY }>,
                 >(vm, *start_ptr as u32)?;
-                let end = passthrough_out::<
-                    WasmiVM<T>,
-                    ConstantReadLimit<{ constants::MAX_LENGTH_DB_KEY }>,
-                >(vm, *end_ptr as u32)?;
+                let end = passthrough_out::<V, ConstantReadLimit<{ constants::MAX_LENGTH_DB_KEY }>>(
+                    vm,
+                    *end_ptr as u32,
+                )?;
                 let order: Order =
                     TryInto::<Order>::try_into(*order).map_err(|_| WasmiVMError::InvalidValue)?;
                 let value = vm.db_scan(
@@ -1129,12 +1756,12 @@ pub mod host_functions {
     }
 
     #[cfg(feature = "iterator")]
-    fn env_db_next<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_db_next<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("db_next");
         match values {
@@ -1144,8 +1771,7 @@ pub mod host_functions {
                     Ok((key, value)) => {
                         let out_data =
                             encode_sections(&[key, value]).ok_or(WasmiVMError::InvalidValue)?;
-                        let Tagged(value_pointer, _) =
-                            passthrough_in::<WasmiVM<T>, ()>(vm, &out_data)?;
+                        let Tagged(value_pointer, _) = passthrough_in::<V, ()>(vm, &out_data)?;
                         Ok(Some(RuntimeValue::I32(value_pointer as i32)))
                     }
                     Err(e) => Err(e),
@@ -1155,26 +1781,73 @@ pub mod host_functions {
         }
     }
 
-    fn env_addr_validate<T>(
-        vm: &mut WasmiVM<T>,
+    #[cfg(feature = "iterator")]
+    fn env_db_next_key<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
+    {
+        log::debug!("db_next_key");
+        match values {
+            [RuntimeValue::I32(iterator_id)] => {
+                let key = vm.db_next_key(*iterator_id as u32)?;
+                // An empty key marks the end of the iteration, signaled by a null pointer.
+                if key.is_empty() {
+                    Ok(Some(RuntimeValue::I32(0)))
+                } else {
+                    let Tagged(value_pointer, _) = passthrough_in::<V, ()>(vm, &key)?;
+                    Ok(Some(RuntimeValue::I32(value_pointer as i32)))
+                }
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    #[cfg(feature = "iterator")]
+    fn env_db_next_value<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        log::debug!("db_next_value");
+        match values {
+            [RuntimeValue::I32(iterator_id)] => {
+                let value = vm.db_next_value(*iterator_id as u32)?;
+                // Stored values are never empty, an empty value marks the end of the iteration.
+                if value.is_empty() {
+                    Ok(Some(RuntimeValue::I32(0)))
+                } else {
+                    let Tagged(value_pointer, _) = passthrough_in::<V, ()>(vm, &value)?;
+                    Ok(Some(RuntimeValue::I32(value_pointer as i32)))
+                }
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_addr_validate<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
     {
         log::debug!("addr_validate");
         match values {
             [RuntimeValue::I32(address_pointer)] => {
                 let address = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_HUMAN_ADDRESS }>,
                 >(vm, *address_pointer as u32)?;
 
                 let address = match String::from_utf8(address) {
                     Ok(address) => address,
                     Err(e) => {
-                        let Tagged(value_pointer, _) =
-                            passthrough_in::<WasmiVM<T>, ()>(vm, e.as_bytes())?;
+                        let Tagged(value_pointer, _) = passthrough_in::<V, ()>(vm, e.as_bytes())?;
                         return Ok(Some(RuntimeValue::I32(value_pointer as i32)));
                     }
                 };
@@ -1183,7 +1856,7 @@ pub mod host_functions {
                     Ok(_) => Ok(Some(RuntimeValue::I32(0))),
                     Err(e) => {
                         let Tagged(value_pointer, _) =
-                            passthrough_in::<WasmiVM<T>, ()>(vm, format!("{e}").as_bytes())?;
+                            passthrough_in::<V, ()>(vm, format!("{e}").as_bytes())?;
                         Ok(Some(RuntimeValue::I32(value_pointer as i32)))
                     }
                 }
@@ -1192,18 +1865,18 @@ pub mod host_functions {
         }
     }
 
-    fn env_addr_canonicalize<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_addr_canonicalize<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("addr_canonicalize");
         match values {
             [RuntimeValue::I32(address_pointer), RuntimeValue::I32(destination_pointer)] => {
                 let address = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_HUMAN_ADDRESS }>,
                 >(vm, *address_pointer as u32)?;
 
@@ -1211,14 +1884,14 @@ pub mod host_functions {
                     Ok(address) => address,
                     Err(e) => {
                         let Tagged(value_pointer, _) =
-                            passthrough_in::<WasmiVM<T>, ()>(vm, format!("{e}").as_bytes())?;
+                            passthrough_in::<V, ()>(vm, format!("{e}").as_bytes())?;
                         return Ok(Some(RuntimeValue::I32(value_pointer as i32)));
                     }
                 };
 
                 match vm.addr_canonicalize(&address)? {
                     Ok(canonical_address) => {
-                        passthrough_in_to::<WasmiVM<T>>(
+                        passthrough_in_to::<V>(
                             vm,
                             *destination_pointer as u32,
                             &canonical_address.into(),
@@ -1227,7 +1900,7 @@ pub mod host_functions {
                     }
                     Err(e) => {
                         let Tagged(value_pointer, _) =
-                            passthrough_in::<WasmiVM<T>, ()>(vm, format!("{e}").as_bytes())?;
+                            passthrough_in::<V, ()>(vm, format!("{e}").as_bytes())?;
                         Ok(Some(RuntimeValue::I32(value_pointer as i32)))
                     }
                 }
@@ -1236,24 +1909,24 @@ pub mod host_functions {
         }
     }
 
-    fn env_addr_humanize<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_addr_humanize<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("addr_humanize");
         match values {
             [RuntimeValue::I32(address_pointer), RuntimeValue::I32(destination_pointer)] => {
                 let address = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_CANONICAL_ADDRESS }>,
                 >(vm, *address_pointer as u32)?;
 
                 match vm.addr_humanize(&address.try_into()?)? {
                     Ok(address) => {
-                        passthrough_in_to::<WasmiVM<T>>(
+                        passthrough_in_to::<V>(
                             vm,
                             *destination_pointer as u32,
                             address.into().as_bytes(),
@@ -1262,7 +1935,7 @@ pub mod host_functions {
                     }
                     Err(e) => {
                         let Tagged(value_pointer, _) =
-                            passthrough_in::<WasmiVM<T>, ()>(vm, format!("{e}").as_bytes())?;
+                            passthrough_in::<V, ()>(vm, format!("{e}").as_bytes())?;
                         Ok(Some(RuntimeValue::I32(value_pointer as i32)))
                     }
                 }
@@ -1271,111 +1944,102 @@ pub mod host_functions {
         }
     }
 
-    fn env_secp256k1_verify<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_secp256k1_verify<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         match values {
             [RuntimeValue::I32(message_hash_ptr), RuntimeValue::I32(signature_ptr), RuntimeValue::I32(public_key_ptr)] =>
             {
                 let message_hash = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_MESSAGE_HASH }>,
                 >(vm, *message_hash_ptr as u32)?;
                 let signature = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::EDCSA_SIGNATURE_LENGTH }>,
                 >(vm, *signature_ptr as u32)?;
                 let public_key = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_EDCSA_PUBKEY_LENGTH }>,
                 >(vm, *public_key_ptr as u32)?;
 
                 let result = vm.secp256k1_verify(&message_hash, &signature, &public_key)?;
 
-                Ok(Some(RuntimeValue::I32(i32::from(!result))))
+                Ok(Some(verification_result(result)))
             }
             _ => Err(WasmiVMError::InvalidHostSignature.into()),
         }
     }
 
-    fn env_secp256k1_recover_pubkey<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_secp256k1_recover_pubkey<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("secp256k1_recover_pubkey");
         match values {
             [RuntimeValue::I32(message_hash_ptr), RuntimeValue::I32(signature_ptr), RuntimeValue::I32(recovery_param)] =>
             {
                 let message_hash = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_MESSAGE_HASH }>,
                 >(vm, *message_hash_ptr as u32)?;
                 let signature = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::EDCSA_SIGNATURE_LENGTH }>,
                 >(vm, *signature_ptr as u32)?;
 
-                if let Ok(pubkey) =
-                    vm.secp256k1_recover_pubkey(&message_hash, &signature, *recovery_param as u8)?
-                {
-                    // Note that if the call is success, the pointer is written to the lower
-                    // 4-bytes. On failure, the error code is written to the upper 4-bytes, and
-                    // we don't return an error.
-                    let Tagged(value_pointer, _) = passthrough_in::<WasmiVM<T>, ()>(vm, &pubkey)?;
-                    Ok(Some(RuntimeValue::I64(i64::from(value_pointer))))
-                } else {
-                    const GENERIC_ERROR_CODE: i64 = 10;
-                    Ok(Some(RuntimeValue::I64(1_i64 << 32)))
-                }
+                let result =
+                    vm.secp256k1_recover_pubkey(&message_hash, &signature, *recovery_param as u8)?;
+                recover_pubkey_result(vm, result).map(Some)
             }
             _ => Err(WasmiVMError::InvalidHostSignature.into()),
         }
     }
 
-    fn env_ed25519_verify<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_ed25519_verify<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("ed25519_verify");
         match values {
             [RuntimeValue::I32(message_ptr), RuntimeValue::I32(signature_ptr), RuntimeValue::I32(public_key_ptr)] =>
             {
                 let message = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_ED25519_MESSAGE }>,
                 >(vm, *message_ptr as u32)?;
                 let signature = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_ED25519_SIGNATURE }>,
                 >(vm, *signature_ptr as u32)?;
                 let public_key = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::EDDSA_PUBKEY_LENGTH }>,
                 >(vm, *public_key_ptr as u32)?;
 
                 vm.ed25519_verify(&message, &signature, &public_key)
-                    .map(|result| Some(RuntimeValue::I32(i32::from(!result))))
+                    .map(|result| Some(verification_result(result)))
             }
             _ => Err(WasmiVMError::InvalidHostSignature.into()),
         }
     }
 
-    fn env_ed25519_batch_verify<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_ed25519_batch_verify<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         match values {
             [RuntimeValue::I32(messages_pointer), RuntimeValue::I32(signatures_pointer), RuntimeValue::I32(public_keys_pointer)] =>
@@ -1384,7 +2048,7 @@ pub mod host_functions {
                 // read a flat memory, not iterate through pointers and read arbitrary memory
                 // locations.
                 let messages = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<
                         {
                             (constants::MAX_LENGTH_ED25519_MESSAGE + 4)
@@ -1393,7 +2057,7 @@ pub mod host_functions {
                     >,
                 >(vm, *messages_pointer as u32)?;
                 let signatures = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<
                         {
                             (constants::MAX_LENGTH_ED25519_SIGNATURE + 4)
@@ -1402,7 +2066,7 @@ pub mod host_functions {
                     >,
                 >(vm, *signatures_pointer as u32)?;
                 let public_keys = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<
                         {
                             (constants::EDDSA_PUBKEY_LENGTH + 4)
@@ -1412,30 +2076,242 @@ pub mod host_functions {
                 >(vm, *public_keys_pointer as u32)?;
 
                 let (messages, signatures, public_keys) = (
-                    decode_sections(&messages),
-                    decode_sections(&signatures),
-                    decode_sections(&public_keys),
+                    decode_sections(&messages).map_err(WasmiVMError::from)?,
+                    decode_sections(&signatures).map_err(WasmiVMError::from)?,
+                    decode_sections(&public_keys).map_err(WasmiVMError::from)?,
                 );
 
                 vm.ed25519_batch_verify(&messages, &signatures, &public_keys)
-                    .map(|result| Some(RuntimeValue::I32(i32::from(!result))))
+                    .map(|result| Some(verification_result(result)))
             }
             _ => Err(WasmiVMError::InvalidHostSignature.into()),
         }
     }
 
-    fn env_debug<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_secp256r1_verify<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
+    {
+        log::debug!("secp256r1_verify");
+        match values {
+            [RuntimeValue::I32(message_hash_ptr), RuntimeValue::I32(signature_ptr), RuntimeValue::I32(public_key_ptr)] =>
+            {
+                let message_hash = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_MESSAGE_HASH }>,
+                >(vm, *message_hash_ptr as u32)?;
+                let signature = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::EDCSA_SIGNATURE_LENGTH }>,
+                >(vm, *signature_ptr as u32)?;
+                let public_key = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_EDCSA_PUBKEY_LENGTH }>,
+                >(vm, *public_key_ptr as u32)?;
+
+                let result = vm.secp256r1_verify(&message_hash, &signature, &public_key)?;
+
+                Ok(Some(verification_result(result)))
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_secp256r1_recover_pubkey<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        log::debug!("secp256r1_recover_pubkey");
+        match values {
+            [RuntimeValue::I32(message_hash_ptr), RuntimeValue::I32(signature_ptr), RuntimeValue::I32(recovery_param)] =>
+            {
+                let message_hash = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_MESSAGE_HASH }>,
+                >(vm, *message_hash_ptr as u32)?;
+                let signature = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::EDCSA_SIGNATURE_LENGTH }>,
+                >(vm, *signature_ptr as u32)?;
+
+                let result =
+                    vm.secp256r1_recover_pubkey(&message_hash, &signature, *recovery_param as u8)?;
+                recover_pubkey_result(vm, result).map(Some)
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_bls12_381_aggregate_g1<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        log::debug!("bls12_381_aggregate_g1");
+        match values {
+            [RuntimeValue::I32(g1s_pointer), RuntimeValue::I32(destination_pointer)] => {
+                let g1s = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_BLS12_381_AGGREGATE }>,
+                >(vm, *g1s_pointer as u32)?;
+                let result = vm.bls12_381_aggregate_g1(&g1s)?;
+                write_result(vm, *destination_pointer, result).map(Some)
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_bls12_381_aggregate_g2<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        log::debug!("bls12_381_aggregate_g2");
+        match values {
+            [RuntimeValue::I32(g2s_pointer), RuntimeValue::I32(destination_pointer)] => {
+                let g2s = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_BLS12_381_AGGREGATE }>,
+                >(vm, *g2s_pointer as u32)?;
+                let result = vm.bls12_381_aggregate_g2(&g2s)?;
+                write_result(vm, *destination_pointer, result).map(Some)
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_bls12_381_pairing_equality<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        log::debug!("bls12_381_pairing_equality");
+        match values {
+            [RuntimeValue::I32(ps_pointer), RuntimeValue::I32(qs_pointer), RuntimeValue::I32(r_pointer), RuntimeValue::I32(s_pointer)] =>
+            {
+                let ps = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_BLS12_381_AGGREGATE }>,
+                >(vm, *ps_pointer as u32)?;
+                let qs = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_BLS12_381_AGGREGATE }>,
+                >(vm, *qs_pointer as u32)?;
+                let r = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::BLS12_381_G1_POINT_LENGTH }>,
+                >(vm, *r_pointer as u32)?;
+                let s = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::BLS12_381_G2_POINT_LENGTH }>,
+                >(vm, *s_pointer as u32)?;
+
+                let result = vm.bls12_381_pairing_equality(&ps, &qs, &r, &s)?;
+
+                Ok(Some(verification_result(result)))
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_bls12_381_hash_to_g1<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        log::debug!("bls12_381_hash_to_g1");
+        match values {
+            [RuntimeValue::I32(hash_function), RuntimeValue::I32(message_pointer), RuntimeValue::I32(dst_pointer), RuntimeValue::I32(destination_pointer)] =>
+            {
+                let message = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_BLS12_381_MESSAGE }>,
+                >(vm, *message_pointer as u32)?;
+                let dst = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_BLS12_381_DST }>,
+                >(vm, *dst_pointer as u32)?;
+                let result = vm.bls12_381_hash_to_g1(*hash_function as u32, &message, &dst)?;
+                write_result(vm, *destination_pointer, result).map(Some)
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_bls12_381_hash_to_g2<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        log::debug!("bls12_381_hash_to_g2");
+        match values {
+            [RuntimeValue::I32(hash_function), RuntimeValue::I32(message_pointer), RuntimeValue::I32(dst_pointer), RuntimeValue::I32(destination_pointer)] =>
+            {
+                let message = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_BLS12_381_MESSAGE }>,
+                >(vm, *message_pointer as u32)?;
+                let dst = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_BLS12_381_DST }>,
+                >(vm, *dst_pointer as u32)?;
+                let result = vm.bls12_381_hash_to_g2(*hash_function as u32, &message, &dst)?;
+                write_result(vm, *destination_pointer, result).map(Some)
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_keccak256<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        log::debug!("keccak256");
+        match values {
+            [RuntimeValue::I32(message_pointer), RuntimeValue::I32(destination_pointer)] => {
+                let message = passthrough_out::<
+                    V,
+                    ConstantReadLimit<{ constants::MAX_LENGTH_KECCAK256_MESSAGE }>,
+                >(vm, *message_pointer as u32)?;
+                let digest = vm.keccak256(&message)?;
+                passthrough_in_to::<V>(vm, *destination_pointer as u32, &digest)?;
+                Ok(Some(RuntimeValue::I32(0)))
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_debug<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
     {
         log::debug!("debug");
         match values {
             [RuntimeValue::I32(message_pointer)] => {
                 let message: Vec<u8> = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_ABORT }>,
                 >(vm, *message_pointer as u32)?;
                 vm.debug(message)?;
@@ -1445,58 +2321,134 @@ pub mod host_functions {
         }
     }
 
-    fn env_query_chain<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_query_chain<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("query_chain");
         match values {
             [RuntimeValue::I32(query_pointer)] => {
-                let request = marshall_out::<WasmiVM<T>, QueryRequest<VmQueryCustomOf<T>>>(
-                    vm,
-                    *query_pointer as u32,
-                )?;
-                let value = cosmwasm_system_query_raw::<WasmiVM<T>>(vm, request)?;
-                let Tagged(value_pointer, _) = passthrough_in::<WasmiVM<T>, ()>(vm, &value)?;
+                let request =
+                    marshall_out::<V, QueryRequest<VmQueryCustomOf<V>>>(vm, *query_pointer as u32)?;
+                let value = cosmwasm_system_query_raw::<V>(vm, request)?;
+                let Tagged(value_pointer, _) = passthrough_in::<V, ()>(vm, &value)?;
                 Ok(Some(RuntimeValue::I32(value_pointer as i32)))
             }
             _ => Err(WasmiVMError::InvalidHostSignature.into()),
         }
     }
 
-    fn env_abort<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_abort<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
     {
         log::debug!("abort");
         match values {
             [RuntimeValue::I32(message_pointer)] => {
                 let message: Vec<u8> = passthrough_out::<
-                    WasmiVM<T>,
+                    V,
                     ConstantReadLimit<{ constants::MAX_LENGTH_ABORT }>,
                 >(vm, *message_pointer as u32)?;
-                vm.abort(String::from_utf8_lossy(&message).into())?;
+                let message: String = String::from_utf8_lossy(&message).into();
+                if let Some(diagnostics) = vm.diagnostics() {
+                    diagnostics.aborted(message.clone());
+                }
+                vm.abort(message)?;
                 Ok(None)
             }
             _ => Err(WasmiVMError::InvalidHostSignature.into()),
         }
     }
 
-    fn env_gas<T>(
-        vm: &mut WasmiVM<T>,
+    fn env_trace_enter<V>(
+        vm: &mut V,
         values: &[RuntimeValue],
-    ) -> Result<Option<RuntimeValue>, VmErrorOf<T>>
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
     where
-        T: WasmiBaseVM,
+        V: WasmiHostVM,
+    {
+        match values {
+            [RuntimeValue::I32(function)] => {
+                if let Some(diagnostics) = vm.diagnostics() {
+                    diagnostics.enter(*function as u32);
+                }
+                Ok(None)
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_trace_leave<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        match values {
+            [] => {
+                if let Some(diagnostics) = vm.diagnostics() {
+                    diagnostics.leave();
+                }
+                Ok(None)
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_coverage_hit<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        match values {
+            [RuntimeValue::I32(block)] => {
+                if let Some(counters) = vm.coverage_counters() {
+                    let counter = counters
+                        .get_mut(*block as usize)
+                        .ok_or(WasmiVMError::InvalidValue)?;
+                    *counter = counter.saturating_add(1);
+                }
+                Ok(None)
+            }
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_deadline_check<V>(
+        vm: &mut V,
+        values: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
+    {
+        match values {
+            [] if vm.deadline_exceeded() => Err(WasmiVMError::DeadlineExceeded.into()),
+            [] => Ok(None),
+            _ => Err(WasmiVMError::InvalidHostSignature.into()),
+        }
+    }
+
+    fn env_gas<V>(vm: &mut V, values: &[RuntimeValue]) -> Result<Option<RuntimeValue>, VmErrorOf<V>>
+    where
+        V: WasmiHostVM,
     {
         match values {
             [RuntimeValue::I32(value)] => {
+                if vm.deadline_exceeded() {
+                    return Err(WasmiVMError::DeadlineExceeded.into());
+                }
+                if let Some(diagnostics) = vm.diagnostics() {
+                    diagnostics.metered(*value as u32);
+                }
                 vm.charge(VmGas::Instrumentation {
                     metered: *value as u32,
                 })?;
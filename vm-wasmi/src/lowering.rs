@@ -0,0 +1,551 @@
+// lowering.rs ---
+
+// Copyright (C) 2022 Hussein Ait-Lahcen
+
+// Author: Hussein Ait-Lahcen <hussein.aitlahcen@gmail.com>
+
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// Except as contained in this notice, the name(s) of the above copyright
+// holders shall not be used in advertising or otherwise to promote the sale,
+// use or other dealings in this Software without prior written authorization.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+// THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Rewrite the sign-extension and bulk-memory instructions emitted by recent
+//! compilers into equivalent MVP instructions, as the interpreter only supports the latter.
+//!
+//! - `i32.extend8_s` & co. are replaced by a pair of shifts.
+//! - `memory.copy` and `memory.fill` are replaced by calls to functions appended to the module.
+//!
+//! Other post-MVP instructions are rejected.
+
+use alloc::vec::Vec;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LoweringError {
+    InvalidHeader,
+    UnexpectedEof,
+    InvalidLeb128,
+    InvalidSection,
+    UnsupportedOpcode(u8),
+    UnsupportedPrefixedOpcode(u8, u32),
+    UnsupportedElementSegment(u32),
+    UnsupportedStartSection,
+}
+
+pub(crate) const WASM_HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+
+pub(crate) const SECTION_TYPE: u8 = 1;
+pub(crate) const SECTION_IMPORT: u8 = 2;
+pub(crate) const SECTION_FUNCTION: u8 = 3;
+pub(crate) const SECTION_EXPORT: u8 = 7;
+pub(crate) const SECTION_CODE: u8 = 10;
+const SECTION_DATA_COUNT: u8 = 12;
+
+pub(crate) const IMPORT_KIND_FUNCTION: u8 = 0;
+pub(crate) const IMPORT_KIND_TABLE: u8 = 1;
+pub(crate) const IMPORT_KIND_MEMORY: u8 = 2;
+pub(crate) const IMPORT_KIND_GLOBAL: u8 = 3;
+
+pub(crate) const OPCODE_CALL: u8 = 0x10;
+pub(crate) const OPCODE_I32_CONST: u8 = 0x41;
+const OPCODE_I64_CONST: u8 = 0x42;
+const OPCODE_I32_SHL: u8 = 0x74;
+const OPCODE_I32_SHR_S: u8 = 0x75;
+const OPCODE_I64_SHL: u8 = 0x86;
+const OPCODE_I64_SHR_S: u8 = 0x87;
+pub(crate) const OPCODE_I32_EXTEND8_S: u8 = 0xC0;
+const OPCODE_I32_EXTEND16_S: u8 = 0xC1;
+const OPCODE_I64_EXTEND8_S: u8 = 0xC2;
+const OPCODE_I64_EXTEND16_S: u8 = 0xC3;
+pub(crate) const OPCODE_I64_EXTEND32_S: u8 = 0xC4;
+pub(crate) const OPCODE_PREFIX_MISC: u8 = 0xFC;
+pub(crate) const OPCODE_MISC_MEMORY_COPY: u32 = 10;
+pub(crate) const OPCODE_MISC_MEMORY_FILL: u32 = 11;
+
+/// `(func (param i32 i32 i32))`, shared by the `memory.copy` and `memory.fill` replacements.
+const HELPER_TYPE: [u8; 6] = [0x60, 0x03, 0x7F, 0x7F, 0x7F, 0x00];
+
+/// Traps if `local[base] + local[2]` is past the end of the memory.
+macro_rules! bounds_check {
+    ($base:literal) => {
+        [
+            0x20, $base, 0xAD, // i64.extend_i32_u (local.get base)
+            0x20, 0x02, 0xAD, // i64.extend_i32_u (local.get n)
+            0x7C, // i64.add
+            0x3F, 0x00, 0xAD, 0x42, 0x10, 0x86, // i64.shl (i64.extend_i32_u (memory.size)) 16
+            0x56, // i64.gt_u
+        ]
+    };
+}
+
+/// `memory.copy(dst, src, n)`, copying forward or backward depending on the overlap.
+#[rustfmt::skip]
+const HELPER_MEMORY_COPY: &[&[u8]] = &[
+    &[0x00], // no locals
+    &bounds_check!(0x01),
+    &bounds_check!(0x00),
+    &[0x72, 0x04, 0x40, 0x00, 0x0B], // if (i32.or ..) unreachable
+    &[0x20, 0x00, 0x20, 0x01, 0x49, 0x04, 0x40], // if (i32.lt_u dst src)
+    &[0x02, 0x40, 0x03, 0x40], // block loop
+    &[0x20, 0x02, 0x45, 0x0D, 0x01], // br_if 1 (i32.eqz n)
+    &[0x20, 0x00, 0x20, 0x01, 0x2D, 0x00, 0x00, 0x3A, 0x00, 0x00], // store8 dst (load8_u src)
+    &[0x20, 0x00, 0x41, 0x01, 0x6A, 0x21, 0x00], // dst += 1
+    &[0x20, 0x01, 0x41, 0x01, 0x6A, 0x21, 0x01], // src += 1
+    &[0x20, 0x02, 0x41, 0x01, 0x6B, 0x21, 0x02], // n -= 1
+    &[0x0C, 0x00, 0x0B, 0x0B], // br 0, end loop, end block
+    &[0x05], // else
+    &[0x02, 0x40, 0x03, 0x40], // block loop
+    &[0x20, 0x02, 0x45, 0x0D, 0x01], // br_if 1 (i32.eqz n)
+    &[0x20, 0x02, 0x41, 0x01, 0x6B, 0x21, 0x02], // n -= 1
+    &[0x20, 0x00, 0x20, 0x02, 0x6A], // dst + n
+    &[0x20, 0x01, 0x20, 0x02, 0x6A, 0x2D, 0x00, 0x00], // load8_u (src + n)
+    &[0x3A, 0x00, 0x00], // store8
+    &[0x0C, 0x00, 0x0B, 0x0B], // br 0, end loop, end block
+    &[0x0B, 0x0B], // end if, end function
+];
+
+/// `memory.fill(dst, value, n)`.
+#[rustfmt::skip]
+const HELPER_MEMORY_FILL: &[&[u8]] = &[
+    &[0x00], // no locals
+    &bounds_check!(0x00),
+    &[0x04, 0x40, 0x00, 0x0B], // if (..) unreachable
+    &[0x02, 0x40, 0x03, 0x40], // block loop
+    &[0x20, 0x02, 0x45, 0x0D, 0x01], // br_if 1 (i32.eqz n)
+    &[0x20, 0x00, 0x20, 0x01, 0x3A, 0x00, 0x00], // store8 dst value
+    &[0x20, 0x00, 0x41, 0x01, 0x6A, 0x21, 0x00], // dst += 1
+    &[0x20, 0x02, 0x41, 0x01, 0x6B, 0x21, 0x02], // n -= 1
+    &[0x0C, 0x00, 0x0B, 0x0B], // br 0, end loop, end block
+    &[0x0B], // end function
+];
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pub(crate) position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    pub(crate) fn remaining(&self) -> &'a [u8] {
+        &self.bytes[self.position..]
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, LoweringError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or(LoweringError::UnexpectedEof)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn peek(&self) -> Result<u8, LoweringError> {
+        self.bytes
+            .get(self.position)
+            .copied()
+            .ok_or(LoweringError::UnexpectedEof)
+    }
+
+    pub(crate) fn slice(&mut self, len: usize) -> Result<&'a [u8], LoweringError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(LoweringError::UnexpectedEof)?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, LoweringError> {
+        let mut result = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            result |= u32::from(byte & 0x7F)
+                .checked_shl(shift)
+                .filter(|_| shift < 28 || byte & 0x70 == 0)
+                .ok_or(LoweringError::InvalidLeb128)?;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(LoweringError::InvalidLeb128)
+    }
+
+    pub(crate) fn len(&mut self) -> Result<usize, LoweringError> {
+        usize::try_from(self.u32()?).map_err(|_| LoweringError::InvalidLeb128)
+    }
+
+    /// Skip a signed LEB128 of at most `max_bytes`.
+    fn skip_signed(&mut self, max_bytes: usize) -> Result<(), LoweringError> {
+        for _ in 0..max_bytes {
+            if self.byte()? & 0x80 == 0 {
+                return Ok(());
+            }
+        }
+        Err(LoweringError::InvalidLeb128)
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn write_u32(output: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_len(output: &mut Vec<u8>, len: usize) -> Result<(), LoweringError> {
+    write_u32(
+        output,
+        u32::try_from(len).map_err(|_| LoweringError::InvalidSection)?,
+    );
+    Ok(())
+}
+
+pub(crate) fn write_section(
+    output: &mut Vec<u8>,
+    id: u8,
+    payload: &[u8],
+) -> Result<(), LoweringError> {
+    output.push(id);
+    write_len(output, payload.len())?;
+    output.extend_from_slice(payload);
+    Ok(())
+}
+
+/// Names of the exports of a module, only its export section being decoded.
+///
+/// # Errors
+///
+/// Fails if the module is malformed.
+pub fn export_names(code: &[u8]) -> Result<Vec<&[u8]>, LoweringError> {
+    let mut reader = Reader::new(code);
+    if reader.slice(WASM_HEADER.len())? != WASM_HEADER {
+        return Err(LoweringError::InvalidHeader);
+    }
+    let mut names = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.len()?;
+        let payload = reader.slice(len)?;
+        if id == SECTION_EXPORT {
+            let mut payload = Reader::new(payload);
+            for _ in 0..payload.u32()? {
+                let name_len = payload.len()?;
+                names.push(payload.slice(name_len)?);
+                // Kind and index of the export.
+                payload.byte()?;
+                payload.u32()?;
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Indices of the functions replacing the bulk-memory instructions.
+#[derive(Copy, Clone)]
+struct Helpers {
+    memory_copy: u32,
+    memory_fill: u32,
+}
+
+/// Rewrite a module such that it only contains MVP instructions.
+///
+/// # Errors
+///
+/// Fails if the module is malformed or uses a post-MVP instruction that can't be lowered.
+pub fn lower_to_mvp(code: &[u8]) -> Result<Vec<u8>, LoweringError> {
+    let mut reader = Reader::new(code);
+    if reader.slice(WASM_HEADER.len())? != WASM_HEADER {
+        return Err(LoweringError::InvalidHeader);
+    }
+    let mut sections = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.len()?;
+        sections.push((id, reader.slice(len)?));
+    }
+    let section = |id| sections.iter().find(|(x, _)| *x == id).map(|(_, s)| *s);
+    let type_count = section(SECTION_TYPE)
+        .map(|payload| Reader::new(payload).u32())
+        .transpose()?
+        .unwrap_or(0);
+    let imported_functions = section(SECTION_IMPORT)
+        .map(count_imported_functions)
+        .transpose()?
+        .unwrap_or(0);
+    let defined_functions = section(SECTION_FUNCTION)
+        .map(|payload| Reader::new(payload).u32())
+        .transpose()?
+        .unwrap_or(0);
+    let first_helper = imported_functions
+        .checked_add(defined_functions)
+        .ok_or(LoweringError::InvalidSection)?;
+    let helpers = Helpers {
+        memory_copy: first_helper,
+        memory_fill: first_helper + 1,
+    };
+
+    let mut uses_helpers = false;
+    let mut code_section = section(SECTION_CODE)
+        .map(|payload| lower_code_section(payload, helpers, &mut uses_helpers))
+        .transpose()?;
+
+    let mut output = Vec::with_capacity(code.len());
+    output.extend_from_slice(&WASM_HEADER);
+    for &(id, payload) in &sections {
+        match id {
+            SECTION_TYPE if uses_helpers => {
+                let mut reader = Reader::new(payload);
+                reader.u32()?;
+                let mut types = Vec::with_capacity(payload.len() + HELPER_TYPE.len());
+                write_u32(&mut types, type_count + 1);
+                types.extend_from_slice(reader.remaining());
+                types.extend_from_slice(&HELPER_TYPE);
+                write_section(&mut output, id, &types)?;
+            }
+            SECTION_FUNCTION if uses_helpers => {
+                let mut reader = Reader::new(payload);
+                reader.u32()?;
+                let mut functions = Vec::with_capacity(payload.len() + 4);
+                write_u32(&mut functions, defined_functions + 2);
+                functions.extend_from_slice(reader.remaining());
+                write_u32(&mut functions, type_count);
+                write_u32(&mut functions, type_count);
+                write_section(&mut output, id, &functions)?;
+            }
+            SECTION_CODE => {
+                let (count, mut bodies) =
+                    code_section.take().ok_or(LoweringError::InvalidSection)?;
+                let mut code = Vec::with_capacity(bodies.len() + 256);
+                if uses_helpers {
+                    write_u32(&mut code, count + 2);
+                    for helper in [HELPER_MEMORY_COPY, HELPER_MEMORY_FILL] {
+                        let body = helper.concat();
+                        write_len(&mut bodies, body.len())?;
+                        bodies.extend_from_slice(&body);
+                    }
+                } else {
+                    write_u32(&mut code, count);
+                }
+                code.extend_from_slice(&bodies);
+                write_section(&mut output, id, &code)?;
+            }
+            // Only required by `memory.init` and `data.drop`, which are rejected.
+            SECTION_DATA_COUNT => {}
+            _ => write_section(&mut output, id, payload)?,
+        }
+    }
+    Ok(output)
+}
+
+pub(crate) fn count_imported_functions(payload: &[u8]) -> Result<u32, LoweringError> {
+    let mut reader = Reader::new(payload);
+    let mut functions = 0;
+    for _ in 0..reader.u32()? {
+        let module_len = reader.len()?;
+        reader.slice(module_len)?;
+        let field_len = reader.len()?;
+        reader.slice(field_len)?;
+        match reader.byte()? {
+            IMPORT_KIND_FUNCTION => {
+                reader.u32()?;
+                functions += 1;
+            }
+            IMPORT_KIND_TABLE => {
+                reader.byte()?;
+                skip_limits(&mut reader)?;
+            }
+            IMPORT_KIND_MEMORY => skip_limits(&mut reader)?,
+            IMPORT_KIND_GLOBAL => {
+                reader.slice(2)?;
+            }
+            _ => return Err(LoweringError::InvalidSection),
+        }
+    }
+    Ok(functions)
+}
+
+pub(crate) fn skip_limits(reader: &mut Reader) -> Result<(), LoweringError> {
+    let flags = reader.byte()?;
+    reader.u32()?;
+    if flags & 0x01 != 0 {
+        reader.u32()?;
+    }
+    Ok(())
+}
+
+/// Returns the number of bodies along with the lowered bodies, without the count prefix.
+fn lower_code_section(
+    payload: &[u8],
+    helpers: Helpers,
+    uses_helpers: &mut bool,
+) -> Result<(u32, Vec<u8>), LoweringError> {
+    let mut reader = Reader::new(payload);
+    let count = reader.u32()?;
+    let mut output = Vec::with_capacity(payload.len());
+    for _ in 0..count {
+        let len = reader.len()?;
+        let body = lower_function_body(reader.slice(len)?, helpers, uses_helpers)?;
+        write_len(&mut output, body.len())?;
+        output.extend_from_slice(&body);
+    }
+    Ok((count, output))
+}
+
+fn lower_function_body(
+    body: &[u8],
+    helpers: Helpers,
+    uses_helpers: &mut bool,
+) -> Result<Vec<u8>, LoweringError> {
+    let mut reader = Reader::new(body);
+    for _ in 0..reader.u32()? {
+        reader.u32()?;
+        reader.byte()?;
+    }
+    let mut output = Vec::with_capacity(body.len());
+    output.extend_from_slice(&body[..reader.position]);
+    while !reader.is_empty() {
+        let start = reader.position;
+        let opcode = reader.byte()?;
+        match opcode {
+            OPCODE_I32_EXTEND8_S => sign_extend_i32(&mut output, 24),
+            OPCODE_I32_EXTEND16_S => sign_extend_i32(&mut output, 16),
+            OPCODE_I64_EXTEND8_S => sign_extend_i64(&mut output, 56),
+            OPCODE_I64_EXTEND16_S => sign_extend_i64(&mut output, 48),
+            OPCODE_I64_EXTEND32_S => sign_extend_i64(&mut output, 32),
+            OPCODE_PREFIX_MISC => {
+                let helper = match reader.u32()? {
+                    OPCODE_MISC_MEMORY_COPY => {
+                        reader.byte()?;
+                        reader.byte()?;
+                        helpers.memory_copy
+                    }
+                    OPCODE_MISC_MEMORY_FILL => {
+                        reader.byte()?;
+                        helpers.memory_fill
+                    }
+                    other => {
+                        return Err(LoweringError::UnsupportedPrefixedOpcode(opcode, other));
+                    }
+                };
+                *uses_helpers = true;
+                output.push(OPCODE_CALL);
+                write_u32(&mut output, helper);
+            }
+            _ => {
+                skip_immediates(&mut reader, opcode)?;
+                output.extend_from_slice(&body[start..reader.position]);
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// `(x << shift) >> shift`, keeping the sign of the lowest `32 - shift` bits.
+fn sign_extend_i32(output: &mut Vec<u8>, shift: u8) {
+    output.extend_from_slice(&[
+        OPCODE_I32_CONST,
+        shift,
+        OPCODE_I32_SHL,
+        OPCODE_I32_CONST,
+        shift,
+        OPCODE_I32_SHR_S,
+    ]);
+}
+
+/// `(x << shift) >> shift`, keeping the sign of the lowest `64 - shift` bits.
+fn sign_extend_i64(output: &mut Vec<u8>, shift: u8) {
+    output.extend_from_slice(&[
+        OPCODE_I64_CONST,
+        shift,
+        OPCODE_I64_SHL,
+        OPCODE_I64_CONST,
+        shift,
+        OPCODE_I64_SHR_S,
+    ]);
+}
+
+/// Skip the immediates of an MVP instruction.
+pub(crate) fn skip_immediates(reader: &mut Reader, opcode: u8) -> Result<(), LoweringError> {
+    match opcode {
+        // unreachable, nop, else, end, return, drop, select and the numeric instructions
+        0x00 | 0x01 | 0x05 | 0x0B | 0x0F | 0x1A | 0x1B | 0x45..=0xBF => {}
+        // block, loop, if
+        0x02..=0x04 => {
+            // Either the empty block type, a value type or a type index.
+            if matches!(reader.peek()?, 0x40 | 0x7B..=0x7F | 0x70 | 0x6F) {
+                reader.byte()?;
+            } else {
+                reader.skip_signed(5)?;
+            }
+        }
+        // br, br_if, call, local.*, global.*
+        0x0C | 0x0D | 0x10 | 0x20..=0x24 => {
+            reader.u32()?;
+        }
+        // br_table
+        0x0E => {
+            for _ in 0..=reader.u32()? {
+                reader.u32()?;
+            }
+        }
+        // call_indirect
+        0x11 => {
+            reader.u32()?;
+            reader.u32()?;
+        }
+        // load and store instructions
+        0x28..=0x3E => {
+            reader.u32()?;
+            reader.u32()?;
+        }
+        // memory.size, memory.grow
+        0x3F | 0x40 => {
+            reader.byte()?;
+        }
+        // i32.const
+        0x41 => reader.skip_signed(5)?,
+        // i64.const
+        0x42 => reader.skip_signed(10)?,
+        // f32.const
+        0x43 => {
+            reader.slice(4)?;
+        }
+        // f64.const
+        0x44 => {
+            reader.slice(8)?;
+        }
+        _ => return Err(LoweringError::UnsupportedOpcode(opcode)),
+    }
+    Ok(())
+}
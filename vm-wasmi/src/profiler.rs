@@ -0,0 +1,130 @@
+// profiler.rs ---
+
+// Copyright (C) 2022 Hussein Ait-Lahcen
+
+// Author: Hussein Ait-Lahcen <hussein.aitlahcen@gmail.com>
+
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// Except as contained in this notice, the name(s) of the above copyright
+// holders shall not be used in advertising or otherwise to promote the sale,
+// use or other dealings in this Software without prior written authorization.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+// THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Per-function profile of an execution, built on the shadow call stack of [`crate::diagnostics`].
+//!
+//! The instrumented gas reported through the `gas` import is attributed to the innermost function,
+//! and calls to host imports to the function calling them. Both are exported in the folded stack
+//! format consumed by flamegraph tools, one `root;...;leaf weight` line per stack.
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::fmt::Write;
+
+#[derive(Default, Clone, Debug)]
+pub struct Profile {
+    names: BTreeMap<u32, String>,
+    host_names: BTreeMap<usize, String>,
+    gas: BTreeMap<Vec<u32>, u64>,
+    host_calls: BTreeMap<(Vec<u32>, usize), u64>,
+}
+
+impl Profile {
+    pub(crate) fn new(names: BTreeMap<u32, String>, host_names: BTreeMap<usize, String>) -> Self {
+        Profile {
+            names,
+            host_names,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn metered(&mut self, stack: &[u32], metered: u32) {
+        match self.gas.get_mut(stack) {
+            Some(gas) => *gas += u64::from(metered),
+            None => {
+                self.gas.insert(stack.into(), u64::from(metered));
+            }
+        }
+    }
+
+    pub(crate) fn host_called(&mut self, stack: &[u32], index: usize) {
+        if self.host_names.contains_key(&index) {
+            *self.host_calls.entry((stack.into(), index)).or_default() += 1;
+        }
+    }
+
+    fn name(&self, function: u32) -> String {
+        self.names
+            .get(&function)
+            .cloned()
+            .unwrap_or_else(|| format!("<function {function}>"))
+    }
+
+    fn folded_stack(&self, stack: &[u32]) -> String {
+        stack
+            .iter()
+            .map(|function| self.name(*function))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    /// Instrumented gas consumed by each function itself, excluding its callees.
+    #[must_use]
+    pub fn function_gas(&self) -> BTreeMap<String, u64> {
+        let mut functions = BTreeMap::new();
+        for (stack, gas) in &self.gas {
+            if let Some(function) = stack.last() {
+                *functions.entry(self.name(*function)).or_default() += gas;
+            }
+        }
+        functions
+    }
+
+    /// Number of calls to each host import.
+    #[must_use]
+    pub fn host_calls(&self) -> BTreeMap<String, u64> {
+        let mut imports = BTreeMap::new();
+        for ((_, index), calls) in &self.host_calls {
+            *imports.entry(self.host_names[index].clone()).or_default() += calls;
+        }
+        imports
+    }
+
+    /// Instrumented gas in the folded stack format.
+    #[must_use]
+    pub fn folded_gas(&self) -> String {
+        let mut output = String::new();
+        for (stack, gas) in &self.gas {
+            let _ = writeln!(output, "{} {gas}", self.folded_stack(stack));
+        }
+        output
+    }
+
+    /// Calls to host imports in the folded stack format, the import being the leaf frame.
+    #[must_use]
+    pub fn folded_host_calls(&self) -> String {
+        let mut output = String::new();
+        for ((stack, index), calls) in &self.host_calls {
+            let mut frames = self.folded_stack(stack);
+            if !frames.is_empty() {
+                frames.push(';');
+            }
+            let _ = writeln!(output, "{frames}{} {calls}", self.host_names[index]);
+        }
+        output
+    }
+}
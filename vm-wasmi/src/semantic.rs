@@ -1,15 +1,25 @@
 extern crate std;
 
 use super::{
-    code_gen, format, host_functions, new_wasmi_vm, vec, BTreeMap, CanResume, CanonicalAddr,
-    ContractInfoResponse, CosmwasmQueryResult, Debug, Display, ExecutorError, Has, MemoryReadError,
-    MemoryWriteError, Pointable, QueryResult, ReadWriteMemory, ReadableMemory, Reply, String,
-    SystemError, SystemResult, Transactional, VMBase, Vec, VmErrorOf, VmGas, VmGasCheckpoint,
-    WasmiHostFunction, WasmiHostFunctionIndex, WasmiImportResolver, WasmiInput, WasmiModule,
-    WasmiModuleExecutor, WasmiOutput, WasmiVM, WasmiVMError, WritableMemory,
+    allocation::{AllocationReport, AllocationTracker},
+    code_gen,
+    coverage::instrument_coverage,
+    crypto::crypto_error,
+    deadline::instrument_deadline,
+    decode_sections,
+    diagnostics::{Diagnostics, TrapDiagnostics},
+    format, host_functions,
+    lowering::{lower_to_mvp, LoweringError},
+    new_wasmi_vm, new_wasmi_vm_with_diagnostics, vec, BTreeMap, CanResume, CanonicalAddr,
+    ContractInfoResponse, CosmwasmQueryResult, CryptoError, Debug, Display, ExecutorError, Has,
+    MemoryReadError, MemoryWriteError, Pointable, QueryResult, ReadWriteMemory, ReadableMemory,
+    Reply, SectionsDecodeError, String, SystemError, SystemResult, Transactional, VMBase, Vec,
+    VmErrorOf, VmGas, VmGasCheckpoint, WasmiFunctionName, WasmiHostFunction,
+    WasmiHostFunctionIndex, WasmiImportResolver, WasmiInput, WasmiModule, WasmiModuleExecutor,
+    WasmiOutput, WasmiVM, WasmiVMError, WritableMemory, VM,
 };
-use alloc::{boxed::Box, string::ToString};
-use core::{assert_matches::assert_matches, num::NonZeroU32, str::FromStr};
+use alloc::string::ToString;
+use core::{assert_matches::assert_matches, marker::PhantomData, num::NonZeroU32, str::FromStr};
 #[cfg(feature = "stargate")]
 use cosmwasm_std::IbcTimeout;
 #[cfg(feature = "iterator")]
@@ -19,16 +29,19 @@ use cosmwasm_std::{
     Timestamp,
 };
 use cosmwasm_vm::{
+    codec::{Codec, CosmwasmCodec},
     executor::{
-        cosmwasm_call, CosmwasmExecutionResult, ExecuteCall, ExecuteResult, InstantiateCall,
-        InstantiateResult, MigrateCall, QueryCall, ReplyCall,
+        allocate, cosmwasm_call, deallocate, deserialization_limits, CosmwasmExecutionResult,
+        ExecuteCall, ExecuteResult, InstantiateCall, InstantiateResult, MigrateCall, QueryCall,
+        ReplyCall, Unit,
     },
+    memory::{LimitedRead, RawFromRegion, RawIntoRegion, Write},
     system::{
         cosmwasm_system_entrypoint, cosmwasm_system_run, CosmwasmCodeId, CosmwasmContractMeta,
     },
 };
-use std::error::Error;
 use wasm_instrument::gas_metering::Rules;
+use wasmi::{memory_units::Bytes, NopExternals, RuntimeValue};
 
 const CANONICAL_LENGTH: usize = 54;
 const SHUFFLES_ENCODE: usize = 18;
@@ -57,7 +70,6 @@ enum SimpleVMError {
     #[cfg(feature = "iterator")]
     IteratorDoesNotExist,
     CannotDeserialize,
-    Custom(Box<dyn Error>),
 }
 impl From<wasmi::Error> for SimpleVMError {
     fn from(e: wasmi::Error) -> Self {
@@ -188,6 +200,8 @@ struct SimpleWasmiVMExtension {
     next_account_id: BankAccount,
     transaction_depth: u32,
     gas: Gas,
+    charged: Vec<VmGas>,
+    deadline: Option<std::time::Instant>,
 }
 
 struct SimpleWasmiVM<'a> {
@@ -196,6 +210,9 @@ struct SimpleWasmiVM<'a> {
     env: Env,
     info: MessageInfo,
     extension: &'a mut SimpleWasmiVMExtension,
+    allocation_tracker: Option<AllocationTracker>,
+    diagnostics: Option<Diagnostics>,
+    coverage: Option<Vec<u64>>,
 }
 
 impl<'a> WasmiModuleExecutor for SimpleWasmiVM<'a> {
@@ -205,6 +222,20 @@ impl<'a> WasmiModuleExecutor for SimpleWasmiVM<'a> {
     fn host_function(&self, index: WasmiHostFunctionIndex) -> Option<&WasmiHostFunction<Self>> {
         self.host_functions.get(&index)
     }
+    fn deadline_exceeded(&self) -> bool {
+        self.extension
+            .deadline
+            .map_or(false, |deadline| std::time::Instant::now() >= deadline)
+    }
+    fn allocation_tracker(&mut self) -> Option<&mut AllocationTracker> {
+        self.allocation_tracker.as_mut()
+    }
+    fn diagnostics(&mut self) -> Option<&mut Diagnostics> {
+        self.diagnostics.as_mut()
+    }
+    fn coverage_counters(&mut self) -> Option<&mut [u64]> {
+        self.coverage.as_deref_mut()
+    }
 }
 
 impl<'a> Pointable for SimpleWasmiVM<'a> {
@@ -219,6 +250,9 @@ impl<'a> ReadableMemory for SimpleWasmiVM<'a> {
             .get_into(offset, buffer)
             .map_err(|_| WasmiVMError::LowLevelMemoryReadError.into())
     }
+    fn size(&self) -> usize {
+        Bytes::from(self.executing_module.memory.current_size()).0
+    }
 }
 
 impl<'a> WritableMemory for SimpleWasmiVM<'a> {
@@ -262,6 +296,7 @@ impl<'a> SimpleWasmiVM<'a> {
                 .0
                 .into_iter()
                 .flat_map(|(_, modules)| modules.into_values())
+                .map(|(index, function, _)| (index, function))
                 .collect(),
             executing_module: module,
             env: Env {
@@ -276,6 +311,9 @@ impl<'a> SimpleWasmiVM<'a> {
                 funds,
             },
             extension: self.extension,
+            allocation_tracker: None,
+            diagnostics: None,
+            coverage: None,
         });
         Ok(f(&mut sub_vm))
     }
@@ -313,6 +351,7 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
     type CanonicalAddress = CanonicalAddress;
     type StorageKey = Vec<u8>;
     type StorageValue = Vec<u8>;
+    type Codec = CosmwasmCodec;
     type Error = SimpleVMError;
 
     fn running_contract_meta(&mut self) -> Result<Self::ContractMeta, Self::Error> {
@@ -569,9 +608,11 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         message_hash: &[u8],
         signature: &[u8],
         public_key: &[u8],
-    ) -> Result<bool, Self::Error> {
-        cosmwasm_crypto::secp256k1_verify(message_hash, signature, public_key)
-            .map_err(|e| SimpleVMError::Custom(Box::new(e)))
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Ok(
+            cosmwasm_crypto::secp256k1_verify(message_hash, signature, public_key)
+                .map_err(crypto_error),
+        )
     }
 
     fn secp256k1_recover_pubkey(
@@ -579,10 +620,10 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         message_hash: &[u8],
         signature: &[u8],
         recovery_param: u8,
-    ) -> Result<Result<Vec<u8>, ()>, Self::Error> {
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
         Ok(
             cosmwasm_crypto::secp256k1_recover_pubkey(message_hash, signature, recovery_param)
-                .map_err(|_| ()),
+                .map_err(crypto_error),
         )
     }
 
@@ -591,9 +632,8 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         message: &[u8],
         signature: &[u8],
         public_key: &[u8],
-    ) -> Result<bool, Self::Error> {
-        cosmwasm_crypto::ed25519_verify(message, signature, public_key)
-            .map_err(|e| SimpleVMError::Custom(Box::new(e)))
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Ok(cosmwasm_crypto::ed25519_verify(message, signature, public_key).map_err(crypto_error))
     }
 
     fn ed25519_batch_verify(
@@ -601,9 +641,75 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
         messages: &[&[u8]],
         signatures: &[&[u8]],
         public_keys: &[&[u8]],
-    ) -> Result<bool, Self::Error> {
-        cosmwasm_crypto::ed25519_batch_verify(messages, signatures, public_keys)
-            .map_err(|e| SimpleVMError::Custom(Box::new(e)))
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Ok(
+            cosmwasm_crypto::ed25519_batch_verify(messages, signatures, public_keys)
+                .map_err(crypto_error),
+        )
+    }
+
+    fn secp256r1_verify(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Err(SimpleVMError::Unsupported)
+    }
+
+    fn secp256r1_recover_pubkey(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: u8,
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(SimpleVMError::Unsupported)
+    }
+
+    fn bls12_381_aggregate_g1(
+        &mut self,
+        _: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(SimpleVMError::Unsupported)
+    }
+
+    fn bls12_381_aggregate_g2(
+        &mut self,
+        _: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(SimpleVMError::Unsupported)
+    }
+
+    fn bls12_381_pairing_equality(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error> {
+        Err(SimpleVMError::Unsupported)
+    }
+
+    fn bls12_381_hash_to_g1(
+        &mut self,
+        _: u32,
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(SimpleVMError::Unsupported)
+    }
+
+    fn bls12_381_hash_to_g2(
+        &mut self,
+        _: u32,
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error> {
+        Err(SimpleVMError::Unsupported)
+    }
+
+    fn keccak256(&mut self, _: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        Err(SimpleVMError::Unsupported)
     }
 
     fn addr_validate(&mut self, input: &str) -> Result<Result<(), Self::Error>, Self::Error> {
@@ -725,6 +831,7 @@ impl<'a> VMBase for SimpleWasmiVM<'a> {
     }
 
     fn charge(&mut self, value: VmGas) -> Result<(), Self::Error> {
+        self.extension.charged.push(value);
         let gas_to_charge = match value {
             VmGas::Instrumentation { metered } => u64::from(metered),
             x => {
@@ -902,11 +1009,15 @@ fn create_vm(
             .clone()
             .into_iter()
             .flat_map(|(_, modules)| modules.into_values())
+            .map(|(index, function, _)| (index, function))
             .collect(),
         executing_module: module,
         env,
         info,
         extension,
+        allocation_tracker: None,
+        diagnostics: None,
+        coverage: None,
     })
 }
 
@@ -989,6 +1100,740 @@ fn test_bare() {
     );
 }
 
+#[test]
+fn test_host_import_signature_mismatch() {
+    let resolver = WasmiImportResolver(host_functions::definitions::<SimpleWasmiVM>());
+    let valid_code = wat::parse_str(
+        r#"(module
+             (import "env" "db_read" (func (param i32) (result i32)))
+             (memory (export "memory") 1))"#,
+    )
+    .unwrap();
+    assert!(new_wasmi_vm(&resolver, &valid_code).is_ok());
+    let invalid_code = wat::parse_str(
+        r#"(module
+             (import "env" "db_read" (func (param i32 i32) (result i32)))
+             (memory (export "memory") 1))"#,
+    )
+    .unwrap();
+    assert_matches!(
+        new_wasmi_vm(&resolver, &invalid_code).err(),
+        Some(SimpleVMError::Interpreter(wasmi::Error::Instantiation(message)))
+            if message.contains("env.db_read")
+    );
+}
+
+#[test]
+fn test_host_function_dispatch() {
+    let definitions = host_functions::definitions::<SimpleWasmiVM>();
+    for (module, functions) in host_functions::signatures() {
+        for (name, (index, signature)) in functions {
+            assert!(host_functions::host_function::<WasmiVM<SimpleWasmiVM>>(index).is_some());
+            assert_matches!(
+                definitions.get(&module).and_then(|functions| functions.get(&name)),
+                Some((defined_index, _, defined_signature))
+                    if *defined_index == index && *defined_signature == signature
+            );
+        }
+    }
+    assert!(
+        host_functions::host_function::<WasmiVM<SimpleWasmiVM>>(WasmiHostFunctionIndex(0xFFFF))
+            .is_none()
+    );
+}
+
+#[test]
+fn test_region_validation() {
+    fn region(offset: u32, capacity: u32, length: u32) -> Vec<u8> {
+        [offset, capacity, length]
+            .into_iter()
+            .flat_map(u32::to_le_bytes)
+            .collect()
+    }
+    let code = instrument_contract(include_bytes!("../../fixtures/cw20_base.wasm"));
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        codes: BTreeMap::from([(0x1337, code)]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        gas: Gas::new(100_000_000),
+        ..Default::default()
+    };
+    let vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    let pointer = 0x1000;
+    let read = |vm: &WasmiVM<SimpleWasmiVM>| {
+        RawFromRegion::try_from(LimitedRead(vm, pointer, 0x100)).map(|RawFromRegion(data)| data)
+    };
+
+    vm.write(pointer + 12, b"hello").unwrap();
+    vm.write(pointer, &region(pointer + 12, 5, 5)).unwrap();
+    assert_eq!(read(&vm).unwrap(), b"hello");
+
+    vm.write(pointer, &region(0, 5, 5)).unwrap();
+    assert_matches!(
+        read(&vm),
+        Err(SimpleVMError::VMError(WasmiVMError::MemoryReadError(
+            MemoryReadError::RegionZeroOffset
+        )))
+    );
+    assert_matches!(
+        RawIntoRegion::try_from(Write(&vm, pointer, b"hello")),
+        Err(SimpleVMError::VMError(WasmiVMError::MemoryWriteError(
+            MemoryWriteError::RegionZeroOffset
+        )))
+    );
+
+    vm.write(pointer, &region(pointer + 12, 4, 5)).unwrap();
+    assert_matches!(
+        read(&vm),
+        Err(SimpleVMError::VMError(WasmiVMError::MemoryReadError(
+            MemoryReadError::RegionLengthExceedsCapacity
+        )))
+    );
+
+    vm.write(pointer, &region(u32::MAX / 2, 5, 5)).unwrap();
+    assert_matches!(
+        read(&vm),
+        Err(SimpleVMError::VMError(WasmiVMError::MemoryReadError(
+            MemoryReadError::RegionExceedsMemory
+        )))
+    );
+    assert_matches!(
+        RawIntoRegion::try_from(Write(&vm, pointer, b"hello")),
+        Err(SimpleVMError::VMError(WasmiVMError::MemoryWriteError(
+            MemoryWriteError::RegionExceedsMemory
+        )))
+    );
+
+    vm.write(pointer, &region(u32::MAX - 2, 5, 5)).unwrap();
+    assert_matches!(
+        read(&vm),
+        Err(SimpleVMError::VMError(WasmiVMError::MemoryReadError(
+            MemoryReadError::RegionOutOfRange
+        )))
+    );
+    assert_matches!(
+        RawIntoRegion::try_from(Write(&vm, pointer, b"hello")),
+        Err(SimpleVMError::VMError(WasmiVMError::MemoryWriteError(
+            MemoryWriteError::RegionOutOfRange
+        )))
+    );
+}
+
+#[test]
+fn test_decode_sections() {
+    assert_eq!(decode_sections(&[]), Ok(vec![]));
+    assert_eq!(
+        decode_sections(b"ab\0\0\0\x02\0\0\0\0c\0\0\0\x01"),
+        Ok(vec![&b"ab"[..], &b""[..], &b"c"[..]])
+    );
+    assert_eq!(
+        decode_sections(b"\0\0\x01"),
+        Err(SectionsDecodeError::TruncatedLengthSuffix)
+    );
+    assert_eq!(
+        decode_sections(b"ab\0\0\0\x03"),
+        Err(SectionsDecodeError::SectionLengthOutOfBounds)
+    );
+    assert_eq!(
+        decode_sections(b"\xff\xff\xff\xff"),
+        Err(SectionsDecodeError::SectionLengthOutOfBounds)
+    );
+}
+
+#[test]
+fn test_allocation_tracking() {
+    let code = instrument_contract(include_bytes!("../../fixtures/cw20_base.wasm"));
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        codes: BTreeMap::from([(0x1337, code)]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        next_account_id: BankAccount(10_001),
+        gas: Gas::new(100_000_000),
+        ..Default::default()
+    };
+    let mut vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    vm.0.allocation_tracker = Some(AllocationTracker::new());
+    cosmwasm_call::<InstantiateCall<Empty>, WasmiVM<SimpleWasmiVM>>(
+        &mut vm,
+        r#"{"name":"Picasso","symbol":"PICA","decimals":12,"initial_balances":[]}"#.as_bytes(),
+    )
+    .unwrap();
+    cosmwasm_call::<QueryCall, WasmiVM<SimpleWasmiVM>>(
+        &mut vm,
+        r#"{ "token_info": {} }"#.as_bytes(),
+    )
+    .unwrap();
+    let reports = vm.0.allocation_tracker.as_mut().unwrap().finish();
+    assert_eq!(
+        reports
+            .iter()
+            .map(|report| (
+                report.entrypoint.as_str(),
+                report.leaked.as_slice(),
+                report.double_freed.as_slice()
+            ))
+            .collect::<Vec<_>>(),
+        [
+            ("instantiate", &[][..], &[][..]),
+            ("query", &[][..], &[][..])
+        ]
+    );
+}
+
+#[test]
+fn test_allocation_reports_per_entrypoint() {
+    let code = wat::parse_str(
+        r#"(module
+             (memory (export "memory") 1)
+             (global $heap (mut i32) (i32.const 1024))
+             (func (export "allocate") (param i32) (result i32)
+               global.get $heap
+               (global.set $heap (i32.add (global.get $heap) (local.get 0))))
+             (func (export "deallocate") (param i32))
+             (func (export "grow") (param i32)
+               (drop (memory.grow (i32.const 1)))))"#,
+    )
+    .unwrap();
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        codes: BTreeMap::from([(0x1337, code)]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        gas: Gas::new(u64::MAX),
+        ..Default::default()
+    };
+    let mut vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    vm.0.allocation_tracker = Some(AllocationTracker::new());
+    let grow = |vm: &mut WasmiVM<SimpleWasmiVM>, pointer: u32| {
+        vm.raw_call::<Unit>(WasmiInput(
+            WasmiFunctionName("grow".into()),
+            (
+                vec![RuntimeValue::I32(i32::try_from(pointer).unwrap())],
+                PhantomData,
+            ),
+            PhantomData,
+        ))
+        .unwrap();
+    };
+
+    // The argument of the entrypoint is consumed by it.
+    let argument = allocate::<_, u32, usize>(&mut vm, 16).unwrap();
+    grow(&mut vm, argument);
+    let freed = allocate::<_, u32, usize>(&mut vm, 8).unwrap();
+    deallocate(&mut vm, freed).unwrap();
+    deallocate(&mut vm, freed).unwrap();
+    let leaked = allocate::<_, u32, usize>(&mut vm, 4).unwrap();
+    let first = AllocationReport {
+        entrypoint: "grow".into(),
+        leaked: vec![(leaked, 4)],
+        double_freed: vec![freed],
+        peak_memory_growth: 65536,
+    };
+    assert_eq!(
+        vm.0.allocation_tracker.as_ref().unwrap().report(),
+        Some(first.clone())
+    );
+
+    let argument = allocate::<_, u32, usize>(&mut vm, 16).unwrap();
+    grow(&mut vm, argument);
+    assert_eq!(
+        vm.0.allocation_tracker.as_mut().unwrap().finish(),
+        [
+            first,
+            AllocationReport {
+                entrypoint: "grow".into(),
+                peak_memory_growth: 65536,
+                ..AllocationReport::default()
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_lowering_post_mvp_instructions() {
+    let resolver = WasmiImportResolver(host_functions::definitions::<SimpleWasmiVM>());
+    let code = wat::parse_str(
+        r#"(module
+             (memory (export "memory") 1)
+             (func (export "extend") (param i32) (result i32)
+               (i32.extend8_s (local.get 0)))
+             (func (export "fill_copy") (result i32)
+               (memory.fill (i32.const 8) (i32.const 0xAB) (i32.const 4))
+               (memory.copy (i32.const 10) (i32.const 8) (i32.const 4))
+               (i32.load (i32.const 10))))"#,
+    )
+    .unwrap();
+    let WasmiModule { module, .. } = new_wasmi_vm(&resolver, &code).unwrap();
+    let mut externals = NopExternals(PhantomData::<SimpleVMError>);
+    assert_eq!(
+        module
+            .invoke_export("extend", &[RuntimeValue::I32(0x80)], &mut externals)
+            .unwrap(),
+        Some(RuntimeValue::I32(-0x80))
+    );
+    assert_eq!(
+        module
+            .invoke_export("fill_copy", &[], &mut externals)
+            .unwrap(),
+        Some(RuntimeValue::I32(i32::from_ne_bytes([0xAB; 4])))
+    );
+
+    let unsupported_code = wat::parse_str(
+        r#"(module
+             (memory (export "memory") 1)
+             (func (result i32) (i32.trunc_sat_f32_s (f32.const 1))))"#,
+    )
+    .unwrap();
+    assert_matches!(
+        new_wasmi_vm(&resolver, &unsupported_code).err(),
+        Some(SimpleVMError::VMError(WasmiVMError::LoweringError(
+            LoweringError::UnsupportedPrefixedOpcode(0xFC, 0)
+        )))
+    );
+
+    let mvp_code = include_bytes!("../../fixtures/cw20_base.wasm");
+    assert_eq!(lower_to_mvp(mvp_code).unwrap(), mvp_code);
+}
+
+#[test]
+fn test_execution_deadline() {
+    let code = wat::parse_str(
+        r#"(module
+             (import "env" "gas" (func $gas (param i32)))
+             (memory (export "memory") 1)
+             (func (export "spin")
+               (loop (call $gas (i32.const 1)) (br 0))))"#,
+    )
+    .unwrap();
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        codes: BTreeMap::from([(0x1337, code)]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        gas: Gas::new(u64::MAX),
+        deadline: Some(std::time::Instant::now() + std::time::Duration::from_millis(50)),
+        ..Default::default()
+    };
+    let mut vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    assert_matches!(
+        vm.raw_call::<Unit>(WasmiInput(
+            WasmiFunctionName("spin".into()),
+            (vec![], PhantomData),
+            PhantomData
+        )),
+        Err(SimpleVMError::VMError(WasmiVMError::DeadlineExceeded))
+    );
+}
+
+#[test]
+fn test_execution_deadline_without_metering() {
+    let code = wat::parse_str(
+        r#"(module
+             (memory (export "memory") 1)
+             (func (export "spin") (loop (br 0))))"#,
+    )
+    .unwrap();
+    let code = instrument_deadline(&code).unwrap();
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        codes: BTreeMap::from([(0x1337, code)]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        gas: Gas::new(u64::MAX),
+        deadline: Some(std::time::Instant::now() + std::time::Duration::from_millis(50)),
+        ..Default::default()
+    };
+    let mut vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    assert_matches!(
+        vm.raw_call::<Unit>(WasmiInput(
+            WasmiFunctionName("spin".into()),
+            (vec![], PhantomData),
+            PhantomData
+        )),
+        Err(SimpleVMError::VMError(WasmiVMError::DeadlineExceeded))
+    );
+}
+
+#[test]
+fn test_trap_diagnostics() {
+    let code = wat::parse_str(
+        r#"(module
+             (import "env" "abort" (func $abort (param i32)))
+             (memory (export "memory") 1)
+             (data (i32.const 8) "\20\00\00\00\05\00\00\00\05\00\00\00")
+             (data (i32.const 32) "boom!")
+             (table 1 funcref)
+             (elem (i32.const 0) $inner)
+             (func $inner unreachable)
+             (func $outer (call_indirect (i32.const 0)))
+             (func (export "run") call $outer)
+             (func (export "fail") (call $abort (i32.const 8))))"#,
+    )
+    .unwrap();
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        codes: BTreeMap::from([(0x1337, code.clone())]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        gas: Gas::new(u64::MAX),
+        ..Default::default()
+    };
+    let mut vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    let resolver = WasmiImportResolver(host_functions::definitions::<SimpleWasmiVM>());
+    let (module, diagnostics) = new_wasmi_vm_with_diagnostics(&resolver, &code).unwrap();
+    vm.0.executing_module = module;
+    vm.0.diagnostics = Some(diagnostics);
+    let call = |vm: &mut WasmiVM<SimpleWasmiVM>, name: &str| {
+        vm.raw_call::<Unit>(WasmiInput(
+            WasmiFunctionName(name.into()),
+            (vec![], PhantomData),
+            PhantomData,
+        ))
+    };
+    assert_matches!(
+        call(&mut vm, "run"),
+        Err(SimpleVMError::VMError(WasmiVMError::Trap(TrapDiagnostics { frames, abort: None, .. })))
+            if frames == ["inner", "outer", "run"]
+    );
+    assert_matches!(
+        call(&mut vm, "fail"),
+        Err(SimpleVMError::VMError(WasmiVMError::Trap(TrapDiagnostics { frames, abort: Some(abort), .. })))
+            if frames == ["fail"] && abort == "boom!"
+    );
+    assert_matches!(
+        vm.0.diagnostics.as_ref().unwrap().last_trap(),
+        Some(TrapDiagnostics { frames, abort: Some(abort), .. })
+            if *frames == ["fail"] && abort == "boom!"
+    );
+    assert_matches!(
+        call(&mut vm, "run"),
+        Err(SimpleVMError::VMError(WasmiVMError::Trap(
+            TrapDiagnostics { abort: None, .. }
+        )))
+    );
+}
+
+#[test]
+fn test_nested_trap_diagnostics() {
+    let mut diagnostics = Diagnostics::new(
+        BTreeMap::from([(0, "outer".into()), (1, "inner".into())]),
+        BTreeMap::new(),
+    );
+    diagnostics.started(0);
+    diagnostics.enter(0);
+    // The nested call traps before entering any function, on call stack exhaustion for instance.
+    diagnostics.started(1);
+    assert_matches!(
+        diagnostics.failed(1, "call stack exhausted".into()),
+        Some(TrapDiagnostics { frames, .. }) if frames == ["outer"]
+    );
+    // The host function of the outer call fails with the error of the nested one, kept as-is.
+    diagnostics.host_failed();
+    assert_eq!(diagnostics.failed(0, "nested call failed".into()), None);
+    assert_matches!(
+        diagnostics.last_trap(),
+        Some(TrapDiagnostics { message, .. }) if message == "call stack exhausted"
+    );
+}
+
+#[test]
+fn test_profiler() {
+    let code = wat::parse_str(
+        r#"(module
+             (import "env" "gas" (func $gas (param i32)))
+             (import "env" "debug" (func $debug (param i32)))
+             (memory (export "memory") 1)
+             (data (i32.const 8) "\20\00\00\00\02\00\00\00\02\00\00\00")
+             (data (i32.const 32) "hi")
+             (func $inner (call $gas (i32.const 5)) (call $debug (i32.const 8)))
+             (func $outer (call $gas (i32.const 2)) call $inner call $inner)
+             (func (export "run") (call $gas (i32.const 1)) call $outer call $inner))"#,
+    )
+    .unwrap();
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        codes: BTreeMap::from([(0x1337, code.clone())]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        gas: Gas::new(u64::MAX),
+        ..Default::default()
+    };
+    let mut vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    let resolver = WasmiImportResolver(host_functions::definitions::<SimpleWasmiVM>());
+    let (module, mut diagnostics) = new_wasmi_vm_with_diagnostics(&resolver, &code).unwrap();
+    diagnostics.enable_profiling();
+    vm.0.executing_module = module;
+    vm.0.diagnostics = Some(diagnostics);
+    vm.raw_call::<Unit>(WasmiInput(
+        WasmiFunctionName("run".into()),
+        (vec![], PhantomData),
+        PhantomData,
+    ))
+    .unwrap();
+    let profile = vm.0.diagnostics.as_ref().unwrap().profile().unwrap();
+    assert_eq!(
+        profile.folded_gas(),
+        "run 1\nrun;inner 5\nrun;outer 2\nrun;outer;inner 10\n"
+    );
+    assert_eq!(
+        profile.folded_host_calls(),
+        "run;inner;env::debug 1\nrun;outer;inner;env::debug 2\n"
+    );
+    assert_eq!(
+        profile.function_gas(),
+        BTreeMap::from([("inner".into(), 15), ("outer".into(), 2), ("run".into(), 1)])
+    );
+    assert_eq!(
+        profile.host_calls(),
+        BTreeMap::from([("env::debug".into(), 3)])
+    );
+}
+
+#[test]
+fn test_coverage_counters() {
+    let code = wat::parse_str(
+        r#"(module
+             (memory (export "memory") 1)
+             (func $double (param i32) (result i32) (i32.add (local.get 0) (local.get 0)))
+             (func (export "run") (param i32) (result i32)
+               (if (result i32) (local.get 0)
+                 (then (call $double (local.get 0)))
+                 (else (i32.const 0)))))"#,
+    )
+    .unwrap();
+    let (instrumented, map) = instrument_coverage(&code).unwrap();
+    // Entry of `double`, then entry, `then`, `else` and continuation of `run`.
+    assert_eq!(map.offsets.len(), 5);
+    assert_eq!(map.block_at(map.offsets[2]), Some(2));
+    assert_eq!(map.block_at(map.end), None);
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        codes: BTreeMap::from([(0x1337, instrumented)]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        gas: Gas::new(u64::MAX),
+        ..Default::default()
+    };
+    let mut vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    vm.0.coverage = Some(vec![0; map.offsets.len()]);
+    for (argument, expected) in [(3, 6), (5, 10), (0, 0)] {
+        let WasmiModule { module, .. } = vm.0.executing_module();
+        assert_matches!(
+            module.invoke_export("run", &[RuntimeValue::I32(argument)], &mut vm),
+            Ok(Some(RuntimeValue::I32(value))) if value == expected
+        );
+    }
+    assert_eq!(vm.0.coverage, Some(vec![2, 3, 2, 1, 3]));
+}
+
+#[test]
+fn test_codec_rejects_floats() {
+    let payload = br#"{"amount":1.5}"#;
+    assert!(serde_json::from_slice::<serde_json::Value>(payload).is_ok());
+    assert!(CosmwasmCodec::decode::<serde_json::Value>(payload).is_err());
+    let coin = Coin::new(u128::MAX, "denom");
+    assert_eq!(
+        CosmwasmCodec::decode::<Coin>(&CosmwasmCodec::encode(&coin).unwrap()).unwrap(),
+        coin
+    );
+}
+
+#[test]
+fn test_response_deserialize_limit() {
+    // The region returned by `instantiate` is one byte longer than the deserialization limit.
+    let code = wat::parse_str(format!(
+        r#"(module
+             (memory (export "memory") 6)
+             (global $heap (mut i32) (i32.const 1024))
+             (func (export "allocate") (param $size i32) (result i32)
+               (local $region i32)
+               (local.set $region (global.get $heap))
+               (i32.store (local.get $region) (i32.add (local.get $region) (i32.const 12)))
+               (i32.store offset=4 (local.get $region) (local.get $size))
+               (i32.store offset=8 (local.get $region) (i32.const 0))
+               (global.set $heap
+                 (i32.add (local.get $region) (i32.add (local.get $size) (i32.const 12))))
+               (local.get $region))
+             (func (export "deallocate") (param i32))
+             (func (export "instantiate") (param i32 i32 i32) (result i32)
+               (i32.store (i32.const 8) (i32.const 65536))
+               (i32.store (i32.const 12) (i32.const {length}))
+               (i32.store (i32.const 16) (i32.const {length}))
+               (i32.const 8)))"#,
+        length = deserialization_limits::RESULT_INSTANTIATE + 1
+    ))
+    .unwrap();
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        codes: BTreeMap::from([(0x1337, code)]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        gas: Gas::new(u64::MAX),
+        ..Default::default()
+    };
+    let mut vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    assert_matches!(
+        cosmwasm_call::<InstantiateCall<Empty>, WasmiVM<SimpleWasmiVM>>(&mut vm, b"{}"),
+        Err(SimpleVMError::VMError(WasmiVMError::ExecutorError(
+            ExecutorError::DeserializeLimitExceeded
+        )))
+    );
+}
+
+#[cfg(feature = "iterator")]
+#[test]
+fn test_db_next_key_value_imports() {
+    let code = wat::parse_str(
+        r#"(module
+             (import "env" "db_next_key" (func $db_next_key (param i32) (result i32)))
+             (import "env" "db_next_value" (func $db_next_value (param i32) (result i32)))
+             (memory (export "memory") 1)
+             (global $heap (mut i32) (i32.const 1024))
+             (func (export "allocate") (param $size i32) (result i32)
+               (local $region i32)
+               (local.set $region (global.get $heap))
+               (i32.store (local.get $region) (i32.add (local.get $region) (i32.const 12)))
+               (i32.store offset=4 (local.get $region) (local.get $size))
+               (i32.store offset=8 (local.get $region) (i32.const 0))
+               (global.set $heap
+                 (i32.add (local.get $region) (i32.add (local.get $size) (i32.const 12))))
+               (local.get $region))
+             (func (export "deallocate") (param i32))
+             (func (export "next_key") (param i32) (result i32)
+               (call $db_next_key (local.get 0)))
+             (func (export "next_value") (param i32) (result i32)
+               (call $db_next_value (local.get 0))))"#,
+    )
+    .unwrap();
+    let address = BankAccount(10_000);
+    let mut extension = SimpleWasmiVMExtension {
+        storage: BTreeMap::from([(
+            address,
+            SimpleWasmiVMStorage {
+                data: BTreeMap::from([
+                    (b"a".to_vec(), b"1".to_vec()),
+                    (b"b".to_vec(), b"2".to_vec()),
+                ]),
+                ..Default::default()
+            },
+        )]),
+        codes: BTreeMap::from([(0x1337, code)]),
+        contracts: BTreeMap::from([(
+            address,
+            CosmwasmContractMeta {
+                code_id: 0x1337,
+                admin: None,
+                label: String::new(),
+            },
+        )]),
+        gas: Gas::new(u64::MAX),
+        ..Default::default()
+    };
+    let mut vm = create_simple_vm(BankAccount(100), address, vec![], &mut extension);
+    // The region of the next key or value, a null pointer ending the iteration.
+    let next = |vm: &mut WasmiVM<SimpleWasmiVM>, export: &str, iterator: u32| {
+        let WasmiModule { module, .. } = vm.0.executing_module();
+        match module.invoke_export(
+            export,
+            &[RuntimeValue::I32(i32::try_from(iterator).unwrap())],
+            vm,
+        ) {
+            Ok(Some(RuntimeValue::I32(0))) => None,
+            Ok(Some(RuntimeValue::I32(pointer))) => Some(
+                RawFromRegion::try_from(LimitedRead(&*vm, u32::try_from(pointer).unwrap(), 0x100))
+                    .map(|RawFromRegion(data)| data)
+                    .unwrap(),
+            ),
+            result => panic!("unexpected result: {result:?}"),
+        }
+    };
+    let charged = |vm: &WasmiVM<SimpleWasmiVM>, gas: VmGas| {
+        vm.0.extension
+            .charged
+            .iter()
+            .filter(|charged| **charged == gas)
+            .count()
+    };
+
+    // Keys and values are iterated independently.
+    let keys = vm.db_scan(None, None, Order::Ascending).unwrap();
+    let values = vm.db_scan(None, None, Order::Ascending).unwrap();
+    assert_eq!(
+        [
+            next(&mut vm, "next_key", keys),
+            next(&mut vm, "next_key", keys),
+            next(&mut vm, "next_key", keys)
+        ],
+        [Some(b"a".to_vec()), Some(b"b".to_vec()), None]
+    );
+    assert_eq!(
+        [
+            next(&mut vm, "next_value", values),
+            next(&mut vm, "next_value", values),
+            next(&mut vm, "next_value", values)
+        ],
+        [Some(b"1".to_vec()), Some(b"2".to_vec()), None]
+    );
+    assert_eq!(charged(&vm, VmGas::DbNextKey), 3);
+    assert_eq!(charged(&vm, VmGas::DbNextValue), 3);
+}
+
 mod test_code_gen {
     use cosmwasm_std::{ContractResult, Response};
 
@@ -1064,6 +1909,47 @@ mod test_code_gen {
         assert_eq!(result, InstantiateResult(response));
         assert_ne!(result, InstantiateResult(response_2));
     }
+
+    #[test]
+    fn instantiate_response_nesting_depth_limit() {
+        let response = (0..=deserialization_limits::MAX_NESTING_DEPTH)
+            .fold(serde_json::Value::Null, |value, _| {
+                serde_json::Value::Array(vec![value])
+            });
+        let module: code_gen::WasmModule =
+            code_gen::ModuleDefinition::with_instantiate_response(response)
+                .unwrap()
+                .into();
+
+        let sender = BankAccount(100);
+        let address = BankAccount(10_000);
+        let funds = vec![];
+        let mut extension = SimpleWasmiVMExtension {
+            storage: BTreeMap::default(),
+            codes: BTreeMap::from([(0x1337, module.code)]),
+            contracts: BTreeMap::from([(
+                address,
+                CosmwasmContractMeta {
+                    code_id: 0x1337,
+                    admin: None,
+                    label: String::new(),
+                },
+            )]),
+            next_account_id: BankAccount(10_001),
+            transaction_depth: 0,
+            gas: Gas::new(100_000_000),
+            ..Default::default()
+        };
+        let mut vm = create_simple_vm(sender, address, funds, &mut extension);
+        let result =
+            cosmwasm_call::<InstantiateCall, WasmiVM<SimpleWasmiVM>>(&mut vm, r#"{}"#.as_bytes());
+        assert_matches!(
+            result,
+            Err(SimpleVMError::VMError(WasmiVMError::ExecutorError(
+                ExecutorError::NestingDepthExceeded
+            )))
+        );
+    }
 }
 
 pub fn digit_sum(input: &[u8]) -> usize {
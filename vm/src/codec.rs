@@ -0,0 +1,66 @@
+// codec.rs ---
+
+// Copyright (C) 2022 Hussein Ait-Lahcen
+
+// Author: Hussein Ait-Lahcen <hussein.aitlahcen@gmail.com>
+
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+
+// Except as contained in this notice, the name(s) of the above copyright
+// holders shall not be used in advertising or otherwise to promote the sale,
+// use or other dealings in this Software without prior written authorization.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+// THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A JSON codec, used to encode the values given to a contract (`Env`, `MessageInfo`, query
+/// results...) and to decode the values returned by a contract (responses, query requests...).
+pub trait Codec {
+    /// Error raised while encoding a value.
+    type EncodeError: Debug;
+    /// Error raised while decoding a value.
+    type DecodeError: Debug;
+
+    /// Encode a value to JSON.
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Self::EncodeError>;
+
+    /// Decode a value from JSON.
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Self::DecodeError>;
+}
+
+/// The codec used by the contracts through `cosmwasm-std`, backed by `serde-json-wasm`.
+///
+/// The host and the contracts agree byte-for-byte on the encoding, and payloads carrying
+/// floats are rejected.
+#[allow(clippy::module_name_repetitions)]
+pub struct CosmwasmCodec;
+
+impl Codec for CosmwasmCodec {
+    type EncodeError = serde_json_wasm::ser::Error;
+    type DecodeError = serde_json_wasm::de::Error;
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Self::EncodeError> {
+        serde_json_wasm::to_vec(value)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Self::DecodeError> {
+        serde_json_wasm::from_slice(bytes)
+    }
+}
@@ -27,6 +27,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::{
+    codec::Codec,
     has::Has,
     input::Input,
     memory::{
@@ -34,7 +35,7 @@ use crate::{
         ReadableMemoryErrorOf, WritableMemoryErrorOf, Write,
     },
     tagged::Tagged,
-    vm::{VmErrorOf, VmInputOf, VmOutputOf, VM},
+    vm::{VmCodecOf, VmErrorOf, VmInputOf, VmOutputOf, VM},
 };
 use alloc::vec::Vec;
 use core::{fmt::Debug, marker::PhantomData};
@@ -117,6 +118,8 @@ pub mod deserialization_limits {
     /// Max length (in bytes) of the result data from a `ibc_packet_timeout` call.
     #[cfg(feature = "stargate")]
     pub const RESULT_IBC_PACKET_TIMEOUT: usize = 256 * KI;
+    /// Max nesting depth of the arrays and objects of any JSON payload.
+    pub const MAX_NESTING_DEPTH: usize = 64;
 }
 
 pub type CosmwasmExecutionResult<T> = ContractResult<Response<T>>;
@@ -619,6 +622,10 @@ pub enum ExecutorError {
     FailedToSerialize,
     /// Unable to deserialize the JSON payload to the given type.
     FailedToDeserialize,
+    /// The JSON payload is longer than the deserialization limit of the given type.
+    DeserializeLimitExceeded,
+    /// The JSON payload is nested deeper than [`deserialization_limits::MAX_NESTING_DEPTH`].
+    NestingDepthExceeded,
     /// The requested allocation size is too big and would overflow the memory.
     AllocationWouldOverflow,
     /// The requrested deallocation size is too big and would overflow the memory (must be impossible).
@@ -668,6 +675,18 @@ pub mod constants {
     pub const MAX_LENGTH_EDCSA_PUBKEY_LENGTH: usize = 65;
     /// Length of an eddsa public key
     pub const EDDSA_PUBKEY_LENGTH: usize = 32;
+    /// Length of a compressed BLS12-381 G1 point
+    pub const BLS12_381_G1_POINT_LENGTH: usize = 48;
+    /// Length of a compressed BLS12-381 G2 point
+    pub const BLS12_381_G2_POINT_LENGTH: usize = 96;
+    /// Max length of the concatenated points given to the BLS12-381 aggregation and pairing functions
+    pub const MAX_LENGTH_BLS12_381_AGGREGATE: usize = 2 * MI;
+    /// Max length of a message hashed to a BLS12-381 curve point
+    pub const MAX_LENGTH_BLS12_381_MESSAGE: usize = 5 * MI;
+    /// Max length of a BLS12-381 hash-to-curve domain separation tag
+    pub const MAX_LENGTH_BLS12_381_DST: usize = 5 * KI;
+    /// Max length of a message hashed with keccak256
+    pub const MAX_LENGTH_KECCAK256_MESSAGE: usize = 5 * MI;
 }
 
 /// Allow for untyped marshalling to specify a limit while extracting the bytes from a contract memory.
@@ -802,7 +821,8 @@ where
     T: serde::ser::Serialize + Sized,
 {
     log::trace!("MarshallIn");
-    let serialized = serde_json::to_vec(x).map_err(|_| ExecutorError::FailedToSerialize)?;
+    let serialized =
+        <VmCodecOf<V> as Codec>::encode(x).map_err(|_| ExecutorError::FailedToSerialize)?;
     passthrough_in(vm, &serialized)
 }
 
@@ -825,7 +845,45 @@ where
 {
     log::trace!("MarshallOut");
     let output = passthrough_out::<V, T>(vm, pointer)?;
-    Ok(serde_json::from_slice(&output).map_err(|_| ExecutorError::FailedToDeserialize)?)
+    if output.len() > T::deserialize_limit() {
+        return Err(ExecutorError::DeserializeLimitExceeded.into());
+    }
+    if exceeds_nesting_depth(&output, deserialization_limits::MAX_NESTING_DEPTH) {
+        return Err(ExecutorError::NestingDepthExceeded.into());
+    }
+    Ok(<VmCodecOf<V> as Codec>::decode(&output).map_err(|_| ExecutorError::FailedToDeserialize)?)
+}
+
+/// Whether the arrays and objects of a JSON payload are nested deeper than `max_depth`.
+///
+/// The payload is scanned without being parsed, malformed JSON is left to the deserializer.
+fn exceeds_nesting_depth(json: &[u8], max_depth: usize) -> bool {
+    let mut depth = 0_usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in json {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else {
+            match byte {
+                b'"' => in_string = true,
+                b'[' | b'{' => {
+                    depth += 1;
+                    if depth > max_depth {
+                        return true;
+                    }
+                }
+                b']' | b'}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    false
 }
 
 /// Execute a generic contract export (`instantiate`, `execute`, `migrate` etc...), providing the custom raw `message` input.
@@ -899,6 +957,6 @@ where
 {
     cosmwasm_call(
         vm,
-        &serde_json::to_vec(message).map_err(|_| ExecutorError::FailedToSerialize)?,
+        &<VmCodecOf<V> as Codec>::encode(message).map_err(|_| ExecutorError::FailedToSerialize)?,
     )
 }
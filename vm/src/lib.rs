@@ -31,6 +31,7 @@
 
 extern crate alloc;
 
+pub mod codec;
 pub mod executor;
 pub mod has;
 pub mod input;
@@ -47,6 +47,8 @@ pub type ReadableMemoryErrorOf<T> = <T as ReadableMemory>::Error;
 pub trait ReadableMemory: Pointable {
     type Error: From<MemoryReadError>;
     fn read(&self, offset: Self::Pointer, buffer: &mut [u8]) -> Result<(), Self::Error>;
+    /// Current size of the linear memory, in bytes.
+    fn size(&self) -> usize;
 }
 
 pub type WritableMemoryErrorOf<T> = <T as WritableMemory>::Error;
@@ -68,6 +70,10 @@ pub enum MemoryReadError {
     InvalidTypeSize,
     OverflowLimit,
     InvalidPointer,
+    RegionZeroOffset,
+    RegionLengthExceedsCapacity,
+    RegionOutOfRange,
+    RegionExceedsMemory,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -75,6 +81,10 @@ pub enum MemoryReadError {
 pub enum MemoryWriteError {
     RegionTooSmall,
     BufferSizeOverflowPointer,
+    RegionZeroOffset,
+    RegionLengthExceedsCapacity,
+    RegionOutOfRange,
+    RegionExceedsMemory,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -113,6 +123,71 @@ struct Region<Pointer> {
     length: Pointer,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum RegionError {
+    ZeroOffset,
+    LengthExceedsCapacity,
+    OutOfRange,
+    ExceedsMemory,
+}
+
+impl From<RegionError> for MemoryReadError {
+    fn from(e: RegionError) -> Self {
+        match e {
+            RegionError::ZeroOffset => MemoryReadError::RegionZeroOffset,
+            RegionError::LengthExceedsCapacity => MemoryReadError::RegionLengthExceedsCapacity,
+            RegionError::OutOfRange => MemoryReadError::RegionOutOfRange,
+            RegionError::ExceedsMemory => MemoryReadError::RegionExceedsMemory,
+        }
+    }
+}
+
+impl From<RegionError> for MemoryWriteError {
+    fn from(e: RegionError) -> Self {
+        match e {
+            RegionError::ZeroOffset => MemoryWriteError::RegionZeroOffset,
+            RegionError::LengthExceedsCapacity => MemoryWriteError::RegionLengthExceedsCapacity,
+            RegionError::OutOfRange => MemoryWriteError::RegionOutOfRange,
+            RegionError::ExceedsMemory => MemoryWriteError::RegionExceedsMemory,
+        }
+    }
+}
+
+impl<Pointer> Region<Pointer>
+where
+    Pointer: Copy + Ord + TryFrom<usize> + TryInto<usize>,
+{
+    /// Performs plausibility checks on a region read from the contract memory, which is
+    /// `memory_size` bytes long.
+    /// <https://github.com/CosmWasm/cosmwasm/blob/2a6b82875563b94ccb48513bd3512bf747843cc3/packages/vm/src/memory.rs#L86>
+    fn validate(&self, memory_size: usize) -> Result<(), RegionError> {
+        let offset: usize = self
+            .offset
+            .try_into()
+            .map_err(|_| RegionError::OutOfRange)?;
+        let capacity: usize = self
+            .capacity
+            .try_into()
+            .map_err(|_| RegionError::OutOfRange)?;
+        if offset == 0 {
+            Err(RegionError::ZeroOffset)
+        } else if self.length > self.capacity {
+            Err(RegionError::LengthExceedsCapacity)
+        } else {
+            // The end of the region must be addressable by a pointer.
+            let end = offset
+                .checked_add(capacity)
+                .filter(|end| Pointer::try_from(*end).is_ok())
+                .ok_or(RegionError::OutOfRange)?;
+            if end > memory_size {
+                Err(RegionError::ExceedsMemory)
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 #[repr(transparent)]
 pub struct FromRegion<T>(pub T);
@@ -127,6 +202,9 @@ where
     ) -> Result<Self, Self::Error> {
         log::trace!("FromRegion");
         let FromMemory(region) = FromMemory::<Region<M::Pointer>>::try_from(Read(memory, pointer))?;
+        region
+            .validate(memory.size())
+            .map_err(MemoryReadError::from)?;
         if region.length > limit {
             Err(MemoryReadError::OverflowLimit.into())
         } else {
@@ -149,6 +227,9 @@ where
     ) -> Result<Self, Self::Error> {
         log::trace!("RawFromRegion: {:?}", pointer);
         let FromMemory(region) = FromMemory::<Region<M::Pointer>>::try_from(Read(memory, pointer))?;
+        region
+            .validate(memory.size())
+            .map_err(MemoryReadError::from)?;
         if region.length > limit {
             Err(MemoryReadError::OverflowLimit.into())
         } else {
@@ -214,6 +295,9 @@ where
             FromMemory::<Region<M::Pointer>>::try_from(Read(memory, pointer))
                 .map_err(|_| MemoryWriteError::BufferSizeOverflowPointer)?;
         log::trace!("Region: {:?}", region);
+        region
+            .validate(memory.size())
+            .map_err(MemoryWriteError::from)?;
         let len = M::Pointer::try_from(core::mem::size_of::<T>())
             .map_err(|_| MemoryWriteError::BufferSizeOverflowPointer)?;
         if region.capacity < len {
@@ -241,6 +325,9 @@ where
             FromMemory::<Region<M::Pointer>>::try_from(Read(memory, pointer))
                 .map_err(|_| MemoryWriteError::BufferSizeOverflowPointer)?;
         log::trace!("Region: {:?}", region);
+        region
+            .validate(memory.size())
+            .map_err(MemoryWriteError::from)?;
         let len = M::Pointer::try_from(value.len())
             .map_err(|_| MemoryWriteError::BufferSizeOverflowPointer)?;
         if region.capacity < len {
@@ -34,6 +34,7 @@ use crate::executor::ibc::{
 #[cfg(feature = "stargate")]
 use crate::executor::AsFunctionName;
 use crate::{
+    codec::Codec,
     executor::{
         cosmwasm_call, AllocateCall, CosmwasmCallInput, CosmwasmCallWithoutInfoInput,
         CosmwasmQueryResult, DeallocateCall, DeserializeLimit, ExecuteCall, ExecutorError, HasInfo,
@@ -44,8 +45,8 @@ use crate::{
     memory::{PointerOf, ReadWriteMemory, ReadableMemoryErrorOf, WritableMemoryErrorOf},
     transaction::{Transactional, TransactionalErrorOf},
     vm::{
-        VmAddressOf, VmErrorOf, VmGasCheckpoint, VmInputOf, VmMessageCustomOf, VmOutputOf,
-        VmQueryCustomOf, VM,
+        VmAddressOf, VmCodecOf, VmErrorOf, VmGasCheckpoint, VmInputOf, VmMessageCustomOf,
+        VmOutputOf, VmQueryCustomOf, VM,
     },
 };
 use alloc::{fmt::Display, format, string::String, vec, vec::Vec};
@@ -405,7 +406,7 @@ where
 {
     cosmwasm_system_entrypoint(
         vm,
-        &serde_json::to_vec(message).map_err(|_| SystemError::FailedToSerialize)?,
+        &<VmCodecOf<V> as Codec>::encode(message).map_err(|_| SystemError::FailedToSerialize)?,
     )
 }
 
@@ -853,7 +854,7 @@ where
             BankQuery::Balance { address, denom } => {
                 let vm_account_addr = address.try_into()?;
                 let amount = vm.balance(&vm_account_addr, denom)?;
-                let serialized_info = serde_json::to_vec(&BalanceResponse { amount })
+                let serialized_info = <VmCodecOf<V> as Codec>::encode(&BalanceResponse { amount })
                     .map_err(|_| SystemError::FailedToSerialize)?;
                 Ok(SystemResult::Ok(ContractResult::Ok(Binary(
                     serialized_info,
@@ -862,8 +863,9 @@ where
             BankQuery::AllBalances { address } => {
                 let vm_account_addr = address.try_into()?;
                 let amount = vm.all_balance(&vm_account_addr)?;
-                let serialized_info = serde_json::to_vec(&AllBalanceResponse { amount })
-                    .map_err(|_| SystemError::FailedToSerialize)?;
+                let serialized_info =
+                    <VmCodecOf<V> as Codec>::encode(&AllBalanceResponse { amount })
+                        .map_err(|_| SystemError::FailedToSerialize)?;
                 Ok(SystemResult::Ok(ContractResult::Ok(Binary(
                     serialized_info,
                 ))))
@@ -892,8 +894,8 @@ where
             WasmQuery::ContractInfo { contract_addr } => {
                 let vm_contract_addr = contract_addr.try_into()?;
                 let info = vm.query_info(vm_contract_addr)?;
-                let serialized_info =
-                    serde_json::to_vec(&info).map_err(|_| SystemError::FailedToSerialize)?;
+                let serialized_info = <VmCodecOf<V> as Codec>::encode(&info)
+                    .map_err(|_| SystemError::FailedToSerialize)?;
                 Ok(SystemResult::Ok(ContractResult::Ok(Binary(
                     serialized_info,
                 ))))
@@ -917,6 +919,6 @@ where
     log::debug!("SystemQueryRaw");
     let output = cosmwasm_system_query(vm, request)?;
     Ok(Binary(
-        serde_json::to_vec(&output).map_err(|_| SystemError::FailedToSerialize)?,
+        <VmCodecOf<V> as Codec>::encode(&output).map_err(|_| SystemError::FailedToSerialize)?,
     ))
 }
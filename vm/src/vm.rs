@@ -27,6 +27,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::{
+    codec::Codec,
     executor::{CosmwasmQueryResult, QueryResult},
     input::Input,
 };
@@ -49,6 +50,60 @@ pub enum VmGasCheckpoint {
     Limited(u64),
 }
 
+/// Errors raised by the crypto primitives. They are reported to the contract as an error
+/// code rather than aborting the execution.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CryptoError {
+    /// The message hash has an invalid length.
+    InvalidHashFormat,
+    /// The signature could not be decoded.
+    InvalidSignatureFormat,
+    /// The public key could not be decoded.
+    InvalidPubkeyFormat,
+    /// The recovery parameter is out of range.
+    InvalidRecoveryParam,
+    /// The batch inputs are inconsistent.
+    Batch,
+    /// A BLS12-381 point could not be decoded.
+    InvalidPoint,
+    /// The hash-to-curve hash function is not supported.
+    UnknownHashFunction,
+    /// Any other failure.
+    Generic,
+    /// The G1 points length is not a multiple of the point length.
+    PairingEqualityNotMultipleG1,
+    /// The G2 points length is not a multiple of the point length.
+    PairingEqualityNotMultipleG2,
+    /// The number of G1 and G2 points differ.
+    PairingEqualityUnequalPointAmount,
+    /// No points were given to aggregate.
+    AggregationEmpty,
+    /// The points length is not a multiple of the point length.
+    AggregationNotMultiple,
+}
+
+impl CryptoError {
+    /// The error code returned to the contract, as defined by the CosmWasm ABI.
+    #[must_use]
+    pub fn code(self) -> u32 {
+        match self {
+            CryptoError::InvalidHashFormat => 3,
+            CryptoError::InvalidSignatureFormat => 4,
+            CryptoError::InvalidPubkeyFormat => 5,
+            CryptoError::InvalidRecoveryParam => 6,
+            CryptoError::Batch => 7,
+            CryptoError::InvalidPoint => 8,
+            CryptoError::UnknownHashFunction => 9,
+            CryptoError::Generic => 10,
+            CryptoError::PairingEqualityNotMultipleG1 => 11,
+            CryptoError::PairingEqualityNotMultipleG2 => 12,
+            CryptoError::PairingEqualityUnequalPointAmount => 13,
+            CryptoError::AggregationEmpty => 14,
+            CryptoError::AggregationNotMultiple => 15,
+        }
+    }
+}
+
 /// Gasable VM calls.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 #[allow(clippy::module_name_repetitions)]
@@ -97,6 +152,12 @@ pub enum VmGas {
     #[cfg(feature = "iterator")]
     /// Cost of `db_next`.
     DbNext,
+    #[cfg(feature = "iterator")]
+    /// Cost of `db_next_key`.
+    DbNextKey,
+    #[cfg(feature = "iterator")]
+    /// Cost of `db_next_value`.
+    DbNextValue,
     /// Cost of `debug`
     Debug,
     /// Cost of `secp256k1_verify`
@@ -107,6 +168,22 @@ pub enum VmGas {
     Ed25519Verify,
     /// Cost of `ed25519_batch_verify`
     Ed25519BatchVerify,
+    /// Cost of `secp256r1_verify`
+    Secp256r1Verify,
+    /// Cost of `secp256r1_recover_pubkey`
+    Secp256r1RecoverPubkey,
+    /// Cost of `bls12_381_aggregate_g1`
+    Bls12381AggregateG1 { nb_of_points: u32 },
+    /// Cost of `bls12_381_aggregate_g2`
+    Bls12381AggregateG2 { nb_of_points: u32 },
+    /// Cost of `bls12_381_pairing_equality`
+    Bls12381PairingEquality { nb_of_pairs: u32 },
+    /// Cost of `bls12_381_hash_to_g1`
+    Bls12381HashToG1,
+    /// Cost of `bls12_381_hash_to_g2`
+    Bls12381HashToG2,
+    /// Cost of `keccak256`
+    Keccak256 { nb_of_bytes: u32 },
     /// Cost of `addr_validate`
     AddrValidate,
     /// Cost of `addr_canonicalize`
@@ -144,6 +221,8 @@ pub type VmStorageKeyOf<T> = <T as VMBase>::StorageKey;
 pub type VmStorageValueOf<T> = <T as VMBase>::StorageValue;
 #[allow(clippy::module_name_repetitions)]
 pub type VmContracMetaOf<T> = <T as VMBase>::ContractMeta;
+#[allow(clippy::module_name_repetitions)]
+pub type VmCodecOf<T> = <T as VMBase>::Codec;
 
 /// A way of calling a VM. From the abstract `call` to `raw_call`.
 pub trait VM: VMBase {
@@ -183,6 +262,8 @@ pub trait VMBase {
     type StorageKey;
     /// Type of value used by the underlying DB.
     type StorageValue;
+    /// JSON codec used to marshall values between the host and the contracts.
+    type Codec: Codec;
     /// Possible errors raised by this VM.
     type Error;
 
@@ -206,6 +287,20 @@ pub trait VMBase {
         iterator_id: u32,
     ) -> Result<(Self::StorageKey, Self::StorageValue), Self::Error>;
 
+    #[cfg(feature = "iterator")]
+    /// Returns the key of the next element of the iterator with the given ID.
+    /// see: [`Self::db_next`]
+    fn db_next_key(&mut self, iterator_id: u32) -> Result<Self::StorageKey, Self::Error> {
+        self.db_next(iterator_id).map(|(key, _)| key)
+    }
+
+    #[cfg(feature = "iterator")]
+    /// Returns the value of the next element of the iterator with the given ID.
+    /// see: [`Self::db_next`]
+    fn db_next_value(&mut self, iterator_id: u32) -> Result<Self::StorageValue, Self::Error> {
+        self.db_next(iterator_id).map(|(_, value)| value)
+    }
+
     /// Change the contract meta of a contract, actually migrating it.
     fn set_contract_meta(
         &mut self,
@@ -351,25 +446,26 @@ pub trait VMBase {
     fn gas_ensure_available(&mut self) -> Result<(), Self::Error>;
 
     /// Verifies `message_hash` against a `signature` with a `public_key`, using the
-    /// secp256k1 ECDSA parametrization.
+    /// secp256k1 ECDSA parametrization. Any errors related to decoding the inputs
+    /// should result in `Ok(Err(_))`
     fn secp256k1_verify(
         &mut self,
         message_hash: &[u8],
         signature: &[u8],
         public_key: &[u8],
-    ) -> Result<bool, Self::Error>;
+    ) -> Result<Result<bool, CryptoError>, Self::Error>;
 
     /// Recovers a public key from a message hash and a signature.
     ///
     /// Returns the recovered pubkey in compressed form, which can be used
     /// in `secp256k1_verify` directly. Any errors related to recovering the
-    /// public key should result in `Ok(Err(()))`
+    /// public key should result in `Ok(Err(_))`
     fn secp256k1_recover_pubkey(
         &mut self,
         message_hash: &[u8],
         signature: &[u8],
         recovery_param: u8,
-    ) -> Result<Result<Vec<u8>, ()>, Self::Error>;
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error>;
 
     /// Verify `message` against a `signature`, with the `public_key` of the signer, using
     /// the ed25519 elliptic curve digital signature parametrization / algorithm.
@@ -378,7 +474,7 @@ pub trait VMBase {
         message: &[u8],
         signature: &[u8],
         public_key: &[u8],
-    ) -> Result<bool, Self::Error>;
+    ) -> Result<Result<bool, CryptoError>, Self::Error>;
 
     /// Performs batch Ed25519 signature verification.
     fn ed25519_batch_verify(
@@ -386,7 +482,73 @@ pub trait VMBase {
         messages: &[&[u8]],
         signatures: &[&[u8]],
         public_keys: &[&[u8]],
-    ) -> Result<bool, Self::Error>;
+    ) -> Result<Result<bool, CryptoError>, Self::Error>;
+
+    /// Verifies `message_hash` against a `signature` with a `public_key`, using the
+    /// secp256r1 (P-256) ECDSA parametrization.
+    fn secp256r1_verify(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error>;
+
+    /// Recovers a secp256r1 public key from a message hash and a signature.
+    /// see: [`Self::secp256k1_recover_pubkey`]
+    fn secp256r1_recover_pubkey(
+        &mut self,
+        message_hash: &[u8],
+        signature: &[u8],
+        recovery_param: u8,
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error>;
+
+    /// Aggregates a list of concatenated compressed BLS12-381 G1 points.
+    ///
+    /// Returns the compressed aggregated point. Any errors related to decoding
+    /// the points should result in `Ok(Err(_))`
+    fn bls12_381_aggregate_g1(
+        &mut self,
+        g1s: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error>;
+
+    /// Aggregates a list of concatenated compressed BLS12-381 G2 points.
+    /// see: [`Self::bls12_381_aggregate_g1`]
+    fn bls12_381_aggregate_g2(
+        &mut self,
+        g2s: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error>;
+
+    /// Checks the pairing equality `e(p_1, q_1) × … × e(p_n, q_n) = e(r, s)`, where `ps`
+    /// and `qs` are concatenated compressed G1 and G2 points respectively.
+    fn bls12_381_pairing_equality(
+        &mut self,
+        ps: &[u8],
+        qs: &[u8],
+        r: &[u8],
+        s: &[u8],
+    ) -> Result<Result<bool, CryptoError>, Self::Error>;
+
+    /// Hashes `message` to a compressed BLS12-381 G1 point, using the domain separation
+    /// tag `dst` and the hash function identified by `hash_function`.
+    /// see: [`Self::bls12_381_aggregate_g1`]
+    fn bls12_381_hash_to_g1(
+        &mut self,
+        hash_function: u32,
+        message: &[u8],
+        dst: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error>;
+
+    /// Hashes `message` to a compressed BLS12-381 G2 point.
+    /// see: [`Self::bls12_381_hash_to_g1`]
+    fn bls12_381_hash_to_g2(
+        &mut self,
+        hash_function: u32,
+        message: &[u8],
+        dst: &[u8],
+    ) -> Result<Result<Vec<u8>, CryptoError>, Self::Error>;
+
+    /// Computes the keccak256 digest of `message`.
+    fn keccak256(&mut self, message: &[u8]) -> Result<Vec<u8>, Self::Error>;
 
     #[cfg(feature = "stargate")]
     /// Transfer tokens over IBC.